@@ -0,0 +1,448 @@
+use crate::json;
+use crate::rates::DatedTouRates;
+use crate::usage_data::UsageEntry;
+use bigdecimal::BigDecimal;
+use jiff::civil::{Date, DateTime};
+use jiff::tz::TimeZone;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+
+/// Buckets net kWh by the hour of day each entry starts in.
+///
+/// An entry that spans more than one hour is attributed entirely to the hour it starts in. SCL's
+/// intervals are an hour or shorter, so this only smears usage by less than an hour, which is
+/// fine for seeing the shape of the day.
+pub fn hourly_usage<'a>(usage_data: impl Iterator<Item = &'a UsageEntry>) -> [BigDecimal; 24] {
+    let mut buckets: [BigDecimal; 24] = Default::default();
+    for entry in usage_data {
+        buckets[entry.start_time.hour() as usize] += entry.kwh_total();
+    }
+    buckets
+}
+
+/// The average net kWh in each hour of the day: [hourly_usage] divided by the number of days with
+/// usage starting in that hour, rounded to a millionth, or `None` for hours with no usage at all.
+pub fn hourly_profile(usage_data: &[UsageEntry]) -> [Option<BigDecimal>; 24] {
+    let totals = hourly_usage(usage_data.iter());
+    let mut days: [BTreeSet<Date>; 24] = Default::default();
+    for entry in usage_data {
+        days[entry.start_time.hour() as usize].insert(entry.date);
+    }
+    std::array::from_fn(|hour| {
+        let days = days[hour].len();
+        (days > 0).then(|| (&totals[hour] / BigDecimal::from(days as u64)).round(6))
+    })
+}
+
+/// Summary statistics of a set of values.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    pub count: usize,
+    #[serde(with = "json::decimal")]
+    pub min: BigDecimal,
+    #[serde(with = "json::decimal")]
+    pub max: BigDecimal,
+    /// The mean, rounded to a millionth.
+    #[serde(with = "json::decimal")]
+    pub mean: BigDecimal,
+    /// The middle value, or the mean of the two middle values if there are an even number.
+    #[serde(with = "json::decimal")]
+    pub median: BigDecimal,
+    /// The 95th percentile, by the nearest-rank method: the smallest value that at least 95% of
+    /// the values are no greater than.
+    #[serde(with = "json::decimal")]
+    pub p95: BigDecimal,
+}
+
+/// Computes the [Stats] of `values`, or `None` if there aren't any.
+pub fn stats(values: impl Iterator<Item = BigDecimal>) -> Option<Stats> {
+    let mut values: Vec<BigDecimal> = values.collect();
+    if values.is_empty() {
+        return None;
+    }
+    values.sort();
+    let count = values.len();
+    let sum: BigDecimal = values.iter().sum();
+    let median = if count.is_multiple_of(2) {
+        (&values[count / 2 - 1] + &values[count / 2]) / BigDecimal::from(2)
+    } else {
+        values[count / 2].clone()
+    };
+    let p95_rank = (count * 95).div_ceil(100);
+    Some(Stats {
+        count,
+        min: values[0].clone(),
+        max: values[count - 1].clone(),
+        mean: (sum / BigDecimal::from(count as u64)).round(6),
+        median,
+        p95: values[p95_rank - 1].clone(),
+    })
+}
+
+/// Finds the multiplier on the TOU rates at which `cost_at(multiplier)` reaches `target`, by
+/// bisection. `cost_at(1)` must be below `target`.
+///
+/// Returns `None` if raising the rates never reaches the target, e.g. because exports outweigh
+/// imports so higher rates only make TOU cheaper.
+pub fn find_breakeven_multiplier(
+    target: &BigDecimal,
+    cost_at: impl Fn(&BigDecimal) -> BigDecimal,
+) -> Option<BigDecimal> {
+    let mut low = BigDecimal::from(1);
+    let mut high = BigDecimal::from(2);
+    // Find an upper bound first. 2^20 times the current rates is far past anything meaningful.
+    let mut doublings = 0;
+    while cost_at(&high) < *target {
+        low = high.clone();
+        high = &high * BigDecimal::from(2);
+        doublings += 1;
+        if doublings > 20 {
+            return None;
+        }
+    }
+    let precision = BigDecimal::new(1.into(), 9);
+    while &high - &low > precision {
+        let middle = ((&low + &high) / BigDecimal::from(2)).round(12);
+        if cost_at(&middle) < *target {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    Some(high)
+}
+
+/// How much peak usage would have to move to off-peak for TOU to cost no more than the current
+/// plan.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShiftBreakeven {
+    /// TOU already costs no more without shifting any usage.
+    AlreadyBeneficial,
+    /// Shifting `kwh`, which is `fraction` of the peak usage, breaks even.
+    Shift {
+        kwh: BigDecimal,
+        fraction: BigDecimal,
+    },
+    /// Even shifting all of the peak usage leaves TOU costing more.
+    Unreachable,
+}
+
+/// Finds the smallest shift of peak usage to off-peak that makes TOU cost no more than the current
+/// plan, given how much more TOU costs without shifting and how much shifting all of the peak
+/// usage would save. The savings grow in proportion to the usage shifted, so there's no need to
+/// search.
+pub fn find_shift_breakeven(
+    excess_cost: &BigDecimal,
+    full_shift_savings: &BigDecimal,
+    peak_kwh: &BigDecimal,
+) -> ShiftBreakeven {
+    let zero = BigDecimal::from(0);
+    if *excess_cost <= zero {
+        ShiftBreakeven::AlreadyBeneficial
+    } else if *full_shift_savings <= zero || excess_cost > full_shift_savings {
+        ShiftBreakeven::Unreachable
+    } else {
+        let fraction = (excess_cost / full_shift_savings).round(6);
+        ShiftBreakeven::Shift {
+            kwh: (peak_kwh * &fraction).round(6),
+            fraction,
+        }
+    }
+}
+
+/// Groups the usage entries by the date they start on, in chronological order.
+pub fn group_by_date<'a>(
+    usage_data: impl Iterator<Item = &'a UsageEntry>,
+) -> BTreeMap<Date, Vec<&'a UsageEntry>> {
+    let mut days: BTreeMap<Date, Vec<&UsageEntry>> = BTreeMap::new();
+    for entry in usage_data {
+        days.entry(entry.date).or_default().push(entry);
+    }
+    days
+}
+
+/// Groups usage entries by the year and month of their date, in chronological order.
+pub fn group_by_month<'a>(
+    usage_data: impl Iterator<Item = &'a UsageEntry>,
+) -> BTreeMap<(i16, i8), Vec<&'a UsageEntry>> {
+    let mut months: BTreeMap<(i16, i8), Vec<&UsageEntry>> = BTreeMap::new();
+    for entry in usage_data {
+        months
+            .entry((entry.date.year(), entry.date.month()))
+            .or_default()
+            .push(entry);
+    }
+    months
+}
+
+/// The trailing moving average of a daily series in chronological order: each day's average is of
+/// the values on that day and the `days - 1` days before it. Days missing from the series aren't
+/// counted, so each average is over the days in its window that have data.
+pub fn trailing_average(series: &[(Date, &BigDecimal)], days: i64) -> Vec<BigDecimal> {
+    let mut averages = Vec::with_capacity(series.len());
+    let mut sum = BigDecimal::from(0);
+    let mut start = 0;
+    for (end, (date, value)) in series.iter().enumerate() {
+        sum += *value;
+        while days_between(series[start].0, *date) > days {
+            sum -= series[start].1;
+            start += 1;
+        }
+        let count = BigDecimal::from((end - start + 1) as u64);
+        averages.push((&sum / count).round(6));
+    }
+    averages
+}
+
+/// The effective rate paid per KWH, or `None` if no energy was used on net, which happens when
+/// exports match or exceed imports. The rate is rounded to a millionth of a dollar, since the
+/// division rarely comes out even.
+pub fn blended_rate(cost: &BigDecimal, kwh: &BigDecimal) -> Option<BigDecimal> {
+    (*kwh > BigDecimal::from(0)).then(|| (cost / kwh).round(6))
+}
+
+/// The first and last dates in the usage data.
+pub fn date_range<'a>(usage_data: impl Iterator<Item = &'a UsageEntry>) -> Option<(Date, Date)> {
+    usage_data.fold(None, |range, entry| match range {
+        None => Some((entry.date, entry.date)),
+        Some((first, last)) => Some((first.min(entry.date), last.max(entry.date))),
+    })
+}
+
+/// The number of days from the first to the last date in the usage data, inclusive.
+pub fn covered_days<'a>(usage_data: impl Iterator<Item = &'a UsageEntry>) -> Option<i64> {
+    let (first, last) = date_range(usage_data)?;
+    Some(days_between(first, last))
+}
+
+/// The number of days from `first` to `last`, inclusive.
+pub fn days_between(first: Date, last: Date) -> i64 {
+    let span = last.since(first).expect("dates are in range");
+    i64::from(span.get_days()) + 1
+}
+
+/// Which way the clocks change on a daylight saving time transition day.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DstChange {
+    /// An hour is skipped, so the day only has 23 hours.
+    SpringForward,
+    /// An hour is repeated, so the day has 25 hours.
+    FallBack,
+}
+
+/// The days from `first` to `last`, inclusive, on which the clocks change in `zone`.
+///
+/// Usage exports are in local time, so the intervals on these days can be missing or doubled.
+pub fn dst_transitions(zone: &TimeZone, first: Date, last: Date) -> Vec<(Date, DstChange)> {
+    let Ok(start) = first.to_zoned(zone.clone()) else {
+        return Vec::new();
+    };
+    let mut offset = start.offset();
+    let mut transitions = Vec::new();
+    for transition in zone.following(start.timestamp()) {
+        let date = zone.to_datetime(transition.timestamp()).date();
+        if date > last {
+            break;
+        }
+        let change = if transition.offset() > offset {
+            DstChange::SpringForward
+        } else {
+            DstChange::FallBack
+        };
+        offset = transition.offset();
+        transitions.push((date, change));
+    }
+    transitions
+}
+
+/// A problem with how the usage entries cover time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoverageIssue {
+    /// No entry covers the time from `from` to `to`.
+    Gap { from: DateTime, to: DateTime },
+    /// An entry starting at `start` begins before the entries before it end, at `previous_end`.
+    Overlap {
+        start: DateTime,
+        previous_end: DateTime,
+    },
+}
+
+impl Display for CoverageIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CoverageIssue::Gap { from, to } => write!(f, "gap from {} to {}", from, to),
+            CoverageIssue::Overlap {
+                start,
+                previous_end,
+            } => write!(
+                f,
+                "entry at {} overlaps the previous entry ending at {}",
+                start, previous_end
+            ),
+        }
+    }
+}
+
+/// Checks that the usage entries cover a continuous stretch of time, with no gaps between
+/// consecutive intervals and no intervals that overlap. The entries don't need to be sorted.
+pub fn check_coverage(entries: &[UsageEntry]) -> Vec<CoverageIssue> {
+    let mut checker = CoverageChecker::default();
+    for entry in entries {
+        checker.add(entry);
+    }
+    checker.finish()
+}
+
+/// Like [check_coverage], but takes the entries one at a time as they're read. Only each entry's
+/// interval is kept, not the whole entry.
+#[derive(Debug, Default)]
+pub struct CoverageChecker {
+    intervals: Vec<(DateTime, DateTime)>,
+}
+
+impl CoverageChecker {
+    pub fn add(&mut self, entry: &UsageEntry) {
+        self.intervals.push((entry.start(), entry.end()));
+    }
+
+    pub fn finish(mut self) -> Vec<CoverageIssue> {
+        self.intervals.sort();
+        let mut issues = Vec::new();
+        let mut intervals = self.intervals.into_iter();
+        let Some((_, mut covered_until)) = intervals.next() else {
+            return issues;
+        };
+        for (start, end) in intervals {
+            if start > covered_until {
+                issues.push(CoverageIssue::Gap {
+                    from: covered_until,
+                    to: start,
+                });
+            } else if start < covered_until {
+                issues.push(CoverageIssue::Overlap {
+                    start,
+                    previous_end: covered_until,
+                });
+            }
+            covered_until = covered_until.max(end);
+        }
+        issues
+    }
+}
+
+/// The interval lengths, in minutes, that usage exports usually have. SCL's are 15 or 60 minutes.
+pub const USUAL_INTERVAL_MINUTES: [i64; 3] = [15, 30, 60];
+
+/// A usage entry whose interval isn't one of the expected lengths. An interval several hours long
+/// usually means rows were merged or corrupted, and splitting its usage across the TOU periods it
+/// spans in proportion to time can put a lot of it in the wrong period.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnexpectedInterval {
+    pub start: DateTime,
+    pub minutes: i64,
+}
+
+impl Display for UnexpectedInterval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "entry at {} is {} minutes long",
+            self.start, self.minutes
+        )
+    }
+}
+
+/// Finds the usage entries whose intervals aren't any of the `expected` lengths, in minutes.
+pub fn check_interval_lengths(entries: &[UsageEntry], expected: &[i64]) -> Vec<UnexpectedInterval> {
+    let mut checker = IntervalLengthChecker::new(expected.to_vec());
+    for entry in entries {
+        checker.add(entry);
+    }
+    checker.finish()
+}
+
+/// Like [check_interval_lengths], but takes the entries one at a time as they're read.
+#[derive(Debug)]
+pub struct IntervalLengthChecker {
+    expected: Vec<i64>,
+    unexpected: Vec<UnexpectedInterval>,
+}
+
+impl IntervalLengthChecker {
+    pub fn new(expected: Vec<i64>) -> Self {
+        Self {
+            expected,
+            unexpected: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, entry: &UsageEntry) {
+        let minutes = entry.minutes();
+        if !self.expected.contains(&minutes) {
+            self.unexpected.push(UnexpectedInterval {
+                start: entry.start(),
+                minutes,
+            });
+        }
+    }
+
+    /// The unexpected intervals, in the order they start.
+    pub fn finish(mut self) -> Vec<UnexpectedInterval> {
+        self.unexpected.sort_by_key(|interval| interval.start);
+        self.unexpected
+    }
+}
+
+/// A usage entry that exported more than a solar system of the given size could produce in its
+/// interval, which usually means the export reading is corrupt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExcessExport {
+    pub start: DateTime,
+    pub exported: BigDecimal,
+    pub cap: BigDecimal,
+}
+
+impl Display for ExcessExport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "entry at {} exported {} KWH, more than the cap of {} KWH",
+            self.start,
+            self.exported.normalized(),
+            self.cap.normalized()
+        )
+    }
+}
+
+/// Checks that `entry` didn't export more than a system of `max_system_kw` could produce running
+/// flat out for the whole interval.
+pub fn check_export(entry: &UsageEntry, max_system_kw: &BigDecimal) -> Option<ExcessExport> {
+    let cap = max_system_kw * BigDecimal::from(entry.minutes()) / BigDecimal::from(60);
+    (entry.exported > cap).then(|| ExcessExport {
+        start: entry.start(),
+        exported: entry.exported.clone(),
+        cap: cap.round(6),
+    })
+}
+
+/// The rate change dates within the usage data's date range, from [date_range], that `rates`
+/// doesn't change on, so the usage on one side of the change is priced with the wrong rates.
+pub fn unpriced_rate_changes(
+    rates: &DatedTouRates,
+    change_dates: &BTreeSet<Date>,
+    range: Option<(Date, Date)>,
+) -> Vec<Date> {
+    let Some((first, last)) = range else {
+        return Vec::new();
+    };
+    change_dates
+        .iter()
+        .copied()
+        .filter(|&date| first < date && date <= last)
+        .filter(|&date| {
+            let day_before = date.yesterday().expect("date is after the first date");
+            rates.in_effect(date) == rates.in_effect(day_before)
+        })
+        .collect()
+}
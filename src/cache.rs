@@ -0,0 +1,371 @@
+//! A cache of each usage file's daily usage by TOU period, so usage files that haven't changed
+//! don't need to be read again.
+
+use crate::json::{self, Map, Value};
+use crate::rate_calculator::{DaySummary, TimeOfUse};
+use crate::schedule::TouSchedule;
+use crate::usage_data::{ReadOptions, UsageEntry};
+use bigdecimal::BigDecimal;
+use jiff::civil::{Date, Time};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+
+/// The version of the cache file's format. A cache with another version is ignored.
+const CACHE_VERSION: &str = "4";
+
+#[derive(Debug)]
+pub enum CacheError {
+    Io(std::io::Error),
+    Json(json::Error),
+    Invalid(String),
+}
+
+impl Display for CacheError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheError::Io(e) => write!(f, "Failed to read cache: {}", e),
+            CacheError::Json(e) => write!(f, "Cache is not valid JSON: {}", e),
+            CacheError::Invalid(message) => write!(f, "Invalid cache: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CacheError::Io(e) => Some(e),
+            CacheError::Json(e) => Some(e),
+            CacheError::Invalid(_) => None,
+        }
+    }
+}
+
+/// What the cached usage depends on besides the usage files. A cache made with a different key
+/// is ignored. The rates aren't part of it, since the cache only has KWH.
+pub fn cache_key(schedule: &TouSchedule, options: &ReadOptions) -> String {
+    format!(
+        "{}; usage_type={}; skip_bad_rows={}; date_format={}",
+        schedule.fingerprint(),
+        options.usage_type.trim().to_lowercase(),
+        options.skip_bad_rows,
+        options.date_format.as_deref().unwrap_or("auto")
+    )
+}
+
+/// The size and modification time of a usage file, which show whether it changed since it was
+/// cached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    size: u64,
+    /// Nanoseconds since the Unix epoch.
+    modified: u128,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos());
+        Ok(Self {
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// A day of usage from one usage file.
+#[derive(Debug, Clone)]
+pub struct CachedDay {
+    pub summary: DaySummary,
+    /// How many usage entries the day has in the file.
+    pub entries: usize,
+    /// How many of the day's entries have notes, see [UsageEntry::notes].
+    pub noted: usize,
+    /// The start times of the day's first and last entries in the file.
+    pub first_start: Time,
+    pub last_start: Time,
+}
+
+impl CachedDay {
+    pub fn new(entry: &UsageEntry, schedule: &TouSchedule) -> Self {
+        let mut day = Self {
+            summary: DaySummary::new(entry.date),
+            entries: 0,
+            noted: 0,
+            first_start: entry.start_time,
+            last_start: entry.start_time,
+        };
+        day.add(entry, schedule);
+        day
+    }
+
+    /// Adds an entry on the day's date.
+    pub fn add(&mut self, entry: &UsageEntry, schedule: &TouSchedule) {
+        self.summary.add(entry, schedule);
+        self.entries += 1;
+        if entry.notes.is_some() {
+            self.noted += 1;
+        }
+        self.first_start = self.first_start.min(entry.start_time);
+        self.last_start = self.last_start.max(entry.start_time);
+    }
+
+    pub fn date(&self) -> Date {
+        self.summary.date
+    }
+
+    /// Whether the two days' entries span overlapping times on the same date, so they're likely
+    /// the same usage from overlapping usage files rather than a day split across two files.
+    pub fn overlaps(&self, other: &CachedDay) -> bool {
+        self.date() == other.date()
+            && self.first_start <= other.last_start
+            && other.first_start <= self.last_start
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CachedFile {
+    stamp: FileStamp,
+    days: Vec<CachedDay>,
+}
+
+/// The cached days of each usage file, by the file's path.
+#[derive(Debug, Clone)]
+pub struct UsageCache {
+    key: String,
+    files: BTreeMap<PathBuf, CachedFile>,
+}
+
+impl UsageCache {
+    /// An empty cache for usage read with `key`, from [cache_key].
+    pub fn new(key: String) -> Self {
+        Self {
+            key,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the cache at `path`. If there's no cache there yet, or it was made with a different
+    /// `key` or format version, the cache is empty.
+    pub fn load(path: &Path, key: String) -> Result<Self, CacheError> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new(key)),
+            Err(e) => return Err(CacheError::Io(e)),
+        };
+        let value = json::parse(&contents).map_err(CacheError::Json)?;
+        let cache = expect_object(&value, "the cache")?;
+        let version = field(cache, "version", "the cache")?;
+        let cached_key = field(cache, "key", "the cache")?;
+        if *version != number(CACHE_VERSION) || *cached_key != Value::String(key.clone()) {
+            return Ok(Self::new(key));
+        }
+        let mut files = BTreeMap::new();
+        for file in expect_array(field(cache, "files", "the cache")?, "files")? {
+            let file = expect_object(file, "a file")?;
+            let path = expect_string(field(file, "path", "a file")?, "path")?;
+            let stamp = FileStamp {
+                size: parse_number(field(file, "size", path)?, "size")?,
+                modified: parse_number(field(file, "modified", path)?, "modified")?,
+            };
+            let days = expect_array(field(file, "days", path)?, "days")?
+                .iter()
+                .map(|day| parse_day(day, path))
+                .collect::<Result<_, _>>()?;
+            files.insert(PathBuf::from(path), CachedFile { stamp, days });
+        }
+        Ok(Self { key, files })
+    }
+
+    /// The cached days of the usage file at `path`, if it hasn't changed since they were cached.
+    pub fn days(&self, path: &Path, stamp: FileStamp) -> Option<&[CachedDay]> {
+        self.files
+            .get(&file_key(path))
+            .filter(|file| file.stamp == stamp)
+            .map(|file| &file.days[..])
+    }
+
+    /// Caches the days of the usage file at `path`, as of `stamp`.
+    pub fn insert(&mut self, path: &Path, stamp: FileStamp, days: Vec<CachedDay>) {
+        self.files
+            .insert(file_key(path), CachedFile { stamp, days });
+    }
+
+    /// Drops the cached days of usage files that no longer exist.
+    pub fn remove_missing(&mut self) {
+        self.files.retain(|path, _| path.exists());
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let files = self.files.iter().map(|(path, file)| {
+            object([
+                ("path", Value::String(path.to_string_lossy().into_owned())),
+                ("size", number(file.stamp.size)),
+                ("modified", number(file.stamp.modified)),
+                (
+                    "days",
+                    Value::Array(file.days.iter().map(day_to_json).collect()),
+                ),
+            ])
+        });
+        let cache = object([
+            ("version", number(CACHE_VERSION)),
+            ("key", Value::String(self.key.clone())),
+            ("files", Value::Array(files.collect())),
+        ]);
+        std::fs::write(path, format!("{}\n", cache))
+    }
+}
+
+/// The path a usage file is cached under, which is the same however the file was named.
+fn file_key(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// A JSON number with the exact digits of `value`.
+fn number(value: impl Display) -> Value {
+    Value::Number(value.to_string().parse().expect("value is a number"))
+}
+
+fn object<'a>(fields: impl IntoIterator<Item = (&'a str, Value)>) -> Value {
+    Value::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn kwh_to_json(kwh: &BigDecimal) -> Value {
+    number(kwh)
+}
+
+fn day_to_json(day: &CachedDay) -> Value {
+    let summary = &day.summary;
+    let mut fields = vec![
+        ("date", Value::String(summary.date.to_string())),
+        ("entries", number(day.entries)),
+        ("noted", number(day.noted)),
+        ("first_start", Value::String(day.first_start.to_string())),
+        ("last_start", Value::String(day.last_start.to_string())),
+        ("imported", kwh_to_json(&summary.imported)),
+        ("exported", kwh_to_json(&summary.exported)),
+    ];
+    for share in &summary.periods {
+        fields.push((
+            share.tou.name(),
+            Value::Array(vec![
+                kwh_to_json(&share.imported),
+                kwh_to_json(&share.exported),
+            ]),
+        ));
+    }
+    let intervals = summary.intervals.iter().map(number);
+    fields.push(("intervals", Value::Array(intervals.collect())));
+    object(fields)
+}
+
+fn parse_day(value: &Value, path: &str) -> Result<CachedDay, CacheError> {
+    let context = format!("a day of {}", path);
+    let day = expect_object(value, &context)?;
+    let date: Date = parse_text(field(day, "date", &context)?, "date")?;
+    let mut summary = DaySummary::new(date);
+    summary.imported = parse_number(field(day, "imported", &context)?, "imported")?;
+    summary.exported = parse_number(field(day, "exported", &context)?, "exported")?;
+    for (share, tou) in summary.periods.iter_mut().zip(TimeOfUse::ALL) {
+        match expect_array(field(day, tou.name(), &context)?, tou.name())? {
+            [imported, exported] => {
+                share.imported = parse_number(imported, tou.name())?;
+                share.exported = parse_number(exported, tou.name())?;
+            }
+            _ => {
+                return Err(CacheError::Invalid(format!(
+                    "expected the imported and exported KWH for {} in {}",
+                    tou.name(),
+                    context
+                )));
+            }
+        }
+    }
+    let intervals = expect_array(field(day, "intervals", &context)?, "intervals")?;
+    if intervals.len() != summary.intervals.len() {
+        return Err(CacheError::Invalid(format!(
+            "expected an interval count for each TOU period in {}",
+            context
+        )));
+    }
+    for (count, value) in summary.intervals.iter_mut().zip(intervals) {
+        *count = parse_number(value, "intervals")?;
+    }
+    Ok(CachedDay {
+        summary,
+        entries: parse_number(field(day, "entries", &context)?, "entries")?,
+        noted: parse_number(field(day, "noted", &context)?, "noted")?,
+        first_start: parse_text(field(day, "first_start", &context)?, "first_start")?,
+        last_start: parse_text(field(day, "last_start", &context)?, "last_start")?,
+    })
+}
+
+fn field<'a>(
+    entries: &'a Map<String, Value>,
+    key: &str,
+    context: &str,
+) -> Result<&'a Value, CacheError> {
+    entries
+        .get(key)
+        .ok_or_else(|| CacheError::Invalid(format!("missing {:?} in {}", key, context)))
+}
+
+fn expect_object<'a>(
+    value: &'a Value,
+    context: &str,
+) -> Result<&'a Map<String, Value>, CacheError> {
+    match value {
+        Value::Object(entries) => Ok(entries),
+        other => Err(invalid_kind("an object", context, other)),
+    }
+}
+
+fn expect_array<'a>(value: &'a Value, context: &str) -> Result<&'a [Value], CacheError> {
+    match value {
+        Value::Array(values) => Ok(values),
+        other => Err(invalid_kind("an array", context, other)),
+    }
+}
+
+fn expect_string<'a>(value: &'a Value, context: &str) -> Result<&'a str, CacheError> {
+    match value {
+        Value::String(text) => Ok(text),
+        other => Err(invalid_kind("a string", context, other)),
+    }
+}
+
+fn parse_number<T: FromStr>(value: &Value, context: &str) -> Result<T, CacheError> {
+    match value {
+        Value::Number(number) => number
+            .to_string()
+            .parse()
+            .map_err(|_| CacheError::Invalid(format!("invalid number for {}", context))),
+        other => Err(invalid_kind("a number", context, other)),
+    }
+}
+
+fn parse_text<T: FromStr>(value: &Value, context: &str) -> Result<T, CacheError> {
+    let text = expect_string(value, context)?;
+    text.parse()
+        .map_err(|_| CacheError::Invalid(format!("invalid {} {:?}", context, text)))
+}
+
+fn invalid_kind(expected: &str, context: &str, found: &Value) -> CacheError {
+    CacheError::Invalid(format!(
+        "expected {} for {}, found {}",
+        expected,
+        context,
+        json::kind(found)
+    ))
+}
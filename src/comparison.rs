@@ -0,0 +1,1274 @@
+//! Compares the cost of usage under the current rate and TOU rates, as data that can be printed
+//! however it's needed.
+
+use crate::analysis::{
+    ShiftBreakeven, Stats, blended_rate, days_between, find_breakeven_multiplier,
+    find_shift_breakeven, group_by_date, group_by_month, hourly_usage, stats, trailing_average,
+};
+use crate::holidays::is_weekend;
+use crate::json;
+use crate::rate_calculator::{
+    BillingPeriodCosts, CostAccumulator, CostOptions, DaySummary, PeakDemand, PeriodUsage,
+    Rounding, TimeOfUse, TouBreakdown, calculate_current_cost, calculate_tou_breakdown,
+    calculate_tou_cost, count_days, split_usage_by_period, unused_credit,
+};
+use crate::rates::{CurrentRate, DatedTouRates, TouRates};
+use crate::schedule::TouSchedule;
+use crate::usage_data::UsageEntry;
+use bigdecimal::BigDecimal;
+use clap::ValueEnum;
+use jiff::civil::{Date, Time};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// Whether switching to TOU rates saves money.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Verdict {
+    Saves,
+    CostsMore,
+    Same,
+}
+
+impl Verdict {
+    pub fn from_costs(current_cost: &BigDecimal, tou_cost: &BigDecimal) -> Self {
+        match tou_cost.cmp(current_cost) {
+            Ordering::Less => Verdict::Saves,
+            Ordering::Greater => Verdict::CostsMore,
+            Ordering::Equal => Verdict::Same,
+        }
+    }
+}
+
+/// The charges besides the energy, and how the totals are rounded, which [Analyzer] adds to the
+/// cost of the energy under each plan.
+#[derive(Debug, Clone)]
+pub struct ChargeOptions {
+    /// The fixed daily base service charge, added to both plans for each day of usage.
+    pub base_charge_per_day: Option<BigDecimal>,
+    /// The monthly fee for a net meter, added to both plans for each billing period if the usage
+    /// has exports.
+    pub solar_meter_fee: Option<BigDecimal>,
+    /// The TOU plan's monthly demand charge, per kW of the highest demand in each billing period.
+    pub demand_charge_per_kw: Option<BigDecimal>,
+    /// The least each billing period can cost under either plan.
+    pub minimum_bill: Option<BigDecimal>,
+    /// Whether imports and exports are netted once per billing period instead of in each entry,
+    /// so that credit left over at the end of the usage is lost.
+    pub no_export_netting: bool,
+    /// The day of the month billing periods start on.
+    pub billing_day: i8,
+    /// How the totals are rounded, if at all.
+    pub rounding: Option<Rounding>,
+}
+
+impl Default for ChargeOptions {
+    fn default() -> Self {
+        Self {
+            base_charge_per_day: None,
+            solar_meter_fee: None,
+            demand_charge_per_kw: None,
+            minimum_bill: None,
+            no_export_netting: false,
+            billing_day: 1,
+            rounding: None,
+        }
+    }
+}
+
+impl ChargeOptions {
+    /// Rounds a total with [ChargeOptions::rounding].
+    pub fn round(&self, amount: BigDecimal) -> BigDecimal {
+        match self.rounding {
+            Some(rounding) => rounding.round(&amount),
+            None => amount,
+        }
+    }
+}
+
+/// How much the minimum bill adds to each plan's cost, over the billing periods that cost less.
+#[derive(Debug, Clone, Default)]
+pub struct MinimumCharge {
+    pub current: BigDecimal,
+    /// How many billing periods were below the minimum under the current rate.
+    pub current_periods: usize,
+    pub tou: BigDecimal,
+    pub tou_periods: usize,
+}
+
+/// The export credit left unused at the end of the usage under each plan when exports are netted
+/// once per billing period, which is added to its cost.
+#[derive(Debug, Clone, Default)]
+pub struct UnusedCredit {
+    pub current: BigDecimal,
+    pub tou: BigDecimal,
+}
+
+/// The charges besides the energy under each plan.
+#[derive(Debug, Clone, Default)]
+pub struct Charges {
+    /// The base service charge for the days of usage.
+    pub base_charge: BigDecimal,
+    /// The solar meter fee charged each billing period, which is zero without exports.
+    pub solar_meter_fee_per_period: BigDecimal,
+    /// The solar meter fee over all the billing periods.
+    pub solar_meter_fee: BigDecimal,
+    /// How many billing periods the usage touches.
+    pub billing_periods: usize,
+    pub demand_charge: BigDecimal,
+    /// The highest demand in kW in each billing period, by the period's first day, when there's a
+    /// demand charge.
+    pub peak_demand: BTreeMap<Date, BigDecimal>,
+    pub minimum_charge: MinimumCharge,
+    pub unused_credit: UnusedCredit,
+}
+
+impl Charges {
+    /// The charges both plans have.
+    pub fn fixed_charge(&self) -> BigDecimal {
+        &self.base_charge + &self.solar_meter_fee
+    }
+
+    /// Everything added to the TOU cost of the energy. Only the TOU plan has the demand charge.
+    pub fn tou(&self) -> BigDecimal {
+        self.fixed_charge()
+            + &self.demand_charge
+            + &self.minimum_charge.tou
+            + &self.unused_credit.tou
+    }
+}
+
+/// The cost of the usage under each plan with its charges, rounded with
+/// [ChargeOptions::rounding].
+#[derive(Debug, Clone)]
+pub struct Costs {
+    pub current: BigDecimal,
+    /// The current cost if nothing had been exported.
+    pub current_import: BigDecimal,
+    pub tou: BigDecimal,
+    /// The TOU cost if nothing had been exported.
+    pub tou_import: BigDecimal,
+    /// The TOU cost with every period at the average of the TOU rates.
+    pub average_rate_tou: BigDecimal,
+}
+
+impl Costs {
+    pub fn savings(&self) -> BigDecimal {
+        &self.current - &self.tou
+    }
+
+    /// The savings as a fraction of the current cost, which doesn't mean anything unless the
+    /// current cost is positive.
+    pub fn savings_share(&self) -> Option<BigDecimal> {
+        (self.current > BigDecimal::from(0)).then(|| (self.savings() / &self.current).round(6))
+    }
+
+    pub fn current_export_credit(&self) -> BigDecimal {
+        &self.current_import - &self.current
+    }
+
+    pub fn tou_export_credit(&self) -> BigDecimal {
+        &self.tou_import - &self.tou
+    }
+
+    pub fn verdict(&self) -> Verdict {
+        Verdict::from_costs(&self.current, &self.tou)
+    }
+}
+
+/// The usage and cost of a TOU period under each plan.
+#[derive(Debug, Clone)]
+pub struct PeriodCost {
+    pub tou: TimeOfUse,
+    /// How many usage entries start in the period.
+    pub entries: usize,
+    pub kwh: BigDecimal,
+    /// The period's share of the total KWH, see [TouBreakdown::kwh_share].
+    pub kwh_share: Option<BigDecimal>,
+    pub tou_cost: BigDecimal,
+    /// The current cost of the usage in the period, which is `None` when it can't be split by
+    /// TOU period, see [AnalysisResult::current_by_period].
+    pub current_cost: Option<BigDecimal>,
+}
+
+impl PeriodCost {
+    /// How much more the period costs under TOU than the current rate.
+    pub fn cost_difference(&self) -> Option<BigDecimal> {
+        Some(&self.tou_cost - self.current_cost.as_ref()?)
+    }
+
+    pub fn tou_rate(&self) -> Option<BigDecimal> {
+        blended_rate(&self.tou_cost, &self.kwh)
+    }
+
+    pub fn current_rate(&self) -> Option<BigDecimal> {
+        blended_rate(self.current_cost.as_ref()?, &self.kwh)
+    }
+}
+
+/// What to rank the days of [DetailOptions::top_peak_days] by.
+#[derive(ValueEnum, Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum PeakDayRanking {
+    /// Peak-period KWH.
+    #[default]
+    Kwh,
+    /// Peak-period TOU cost.
+    Cost,
+}
+
+/// The analyses of the usage to work out besides the totals. Everything but
+/// [DetailOptions::fixed_monthly] and [DetailOptions::annualize] needs every usage entry.
+#[derive(Debug, Clone, Default)]
+pub struct DetailOptions {
+    /// Net KWH by hour of day.
+    pub histogram: bool,
+    /// [Stats] of the net KWH of each usage entry.
+    pub stats: bool,
+    /// The usage and cost under each plan each day.
+    pub daily: bool,
+    /// Add a trailing moving average over this many days to [DetailOptions::daily].
+    pub smooth_days: Option<i64>,
+    /// The usage and cost under each plan on weekdays and on weekends.
+    pub by_day_type: bool,
+    /// The usage and TOU cost in each calendar month.
+    pub year_over_year: bool,
+    /// The days with the most peak usage, this many of them.
+    pub top_peak_days: Option<usize>,
+    pub rank_peak_days_by: PeakDayRanking,
+    /// How much the TOU rates could uniformly rise before switching stops saving money.
+    pub breakeven: bool,
+    /// The TOU cost with this fraction of peak usage shifted to off-peak.
+    pub shift_peak_to_off: Option<BigDecimal>,
+    /// The least peak usage to shift to off-peak for TOU to cost no more than the current rate.
+    pub shift_breakeven: bool,
+    /// A fixed amount billed each billing period, to total over the usage.
+    pub fixed_monthly: Option<BigDecimal>,
+    /// Scale the savings to a full year.
+    pub annualize: bool,
+}
+
+/// Statistics of the net KWH of each usage entry, over all of them and over the ones starting in
+/// each TOU period.
+#[derive(Debug, Clone)]
+pub struct UsageStats {
+    pub all: Option<Stats>,
+    pub by_period: Vec<(TimeOfUse, Option<Stats>)>,
+}
+
+impl UsageStats {
+    /// The stats of `usage_data`, with the entries counted in the TOU period they start in, like
+    /// [PeriodCost::entries].
+    pub fn new(usage_data: &[UsageEntry], schedule: &TouSchedule) -> Self {
+        let by_period = schedule
+            .shown_periods()
+            .into_iter()
+            .map(|tou| {
+                let in_period = usage_data.iter().filter(|entry| {
+                    TimeOfUse::from_date_time(entry.date, entry.start_time, schedule) == tou
+                });
+                (tou, stats(in_period.map(UsageEntry::kwh_total)))
+            })
+            .collect();
+        Self {
+            all: stats(usage_data.iter().map(UsageEntry::kwh_total)),
+            by_period,
+        }
+    }
+}
+
+/// The usage and cost under each plan on a single day.
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyCost {
+    pub date: Date,
+    #[serde(with = "json::decimal")]
+    pub kwh: BigDecimal,
+    #[serde(with = "json::decimal")]
+    pub current_cost: BigDecimal,
+    #[serde(with = "json::decimal")]
+    pub tou_cost: BigDecimal,
+    #[serde(with = "json::decimal")]
+    pub savings: BigDecimal,
+    /// The [DetailOptions::smooth_days] moving averages of the current and TOU costs.
+    #[serde(
+        with = "json::optional_decimal",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub smoothed_current_cost: Option<BigDecimal>,
+    #[serde(
+        with = "json::optional_decimal",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub smoothed_tou_cost: Option<BigDecimal>,
+}
+
+/// The usage and cost under each plan on weekdays or on weekends.
+#[derive(Debug, Clone, Serialize)]
+pub struct DayTypeCost {
+    /// `weekday` or `weekend`.
+    pub day_type: &'static str,
+    pub days: usize,
+    #[serde(with = "json::decimal")]
+    pub kwh: BigDecimal,
+    #[serde(with = "json::decimal")]
+    pub current_cost: BigDecimal,
+    #[serde(with = "json::decimal")]
+    pub tou_cost: BigDecimal,
+    #[serde(with = "json::decimal")]
+    pub savings: BigDecimal,
+}
+
+/// The usage and TOU cost in a calendar month.
+#[derive(Debug, Clone, Serialize)]
+pub struct MonthCost {
+    pub year: i16,
+    pub month: i8,
+    #[serde(with = "json::decimal")]
+    pub kwh: BigDecimal,
+    #[serde(with = "json::decimal")]
+    pub tou_cost: BigDecimal,
+}
+
+/// The peak-period usage and TOU cost on a single day.
+#[derive(Debug, Clone, Serialize)]
+pub struct PeakDay {
+    pub date: Date,
+    #[serde(rename = "peak_kwh", with = "json::decimal")]
+    pub kwh: BigDecimal,
+    #[serde(rename = "peak_cost", with = "json::decimal")]
+    pub cost: BigDecimal,
+}
+
+/// How much the TOU rates could uniformly rise before switching stops saving money.
+#[derive(Debug, Clone)]
+pub enum Breakeven {
+    /// TOU already costs at least as much as the current rate.
+    AlreadyCostsMore,
+    /// The TOU rates could be multiplied by `multiplier`, to `rates`.
+    Multiplier {
+        multiplier: BigDecimal,
+        rates: Box<DatedTouRates>,
+    },
+    /// Raising the TOU rates never makes switching cost more, e.g. because exports outweigh
+    /// imports.
+    Never,
+}
+
+/// The results of the [DetailOptions] that were asked for.
+#[derive(Debug, Clone, Default)]
+pub struct Details {
+    /// The net KWH of the entries starting in each hour of the day, see [hourly_usage].
+    pub histogram: Option<[BigDecimal; 24]>,
+    pub stats: Option<UsageStats>,
+    /// With a tiered current rate, each day's current cost is its share of the total by KWH.
+    pub daily: Option<Vec<DailyCost>>,
+    /// Weekdays and then weekends, with the current cost split like [Details::daily]'s.
+    pub by_day_type: Option<[DayTypeCost; 2]>,
+    pub year_over_year: Option<Vec<MonthCost>>,
+    /// Most first, with ties broken by the earlier date.
+    pub top_peak_days: Option<Vec<PeakDay>>,
+    pub breakeven: Option<Breakeven>,
+    pub shifted_tou_cost: Option<BigDecimal>,
+    pub shift_breakeven: Option<ShiftBreakeven>,
+    /// The fixed amount over every billing period the usage touches.
+    pub fixed_monthly_cost: Option<BigDecimal>,
+    /// The savings scaled to a year from the days the usage covers, or `None` without usage.
+    pub annualized_savings: Option<BigDecimal>,
+}
+
+/// The totals of comparing usage under the current rate and TOU rates.
+///
+/// The costs are for the energy alone, without any fixed charges, and aren't rounded, except for
+/// [AnalysisResult::costs].
+#[derive(Debug, Clone)]
+pub struct AnalysisResult {
+    pub imported: BigDecimal,
+    pub exported: BigDecimal,
+    /// The distinct days in the usage.
+    pub days: usize,
+    /// The first and last dates in the usage.
+    pub date_range: Option<(Date, Date)>,
+    pub current_cost: BigDecimal,
+    /// The current cost if nothing had been exported.
+    pub current_import_cost: BigDecimal,
+    /// The usage and TOU cost in each TOU period.
+    pub breakdown: TouBreakdown,
+    /// The TOU cost if nothing had been exported.
+    pub tou_import_cost: BigDecimal,
+    /// The TOU cost with every period at the average of the TOU rates, which shows how much the
+    /// usage's timing matters.
+    pub average_rate_tou_cost: BigDecimal,
+    /// The current cost of the usage in each TOU period, or `None` with net metering, which banks
+    /// exports by TOU period. With a tiered current rate, each period's cost is its share of the
+    /// total by KWH, which is also `None` if the KWH can't be split.
+    pub current_by_period: Option<TouBreakdown>,
+    /// The usage and cost of each of the schedule's [TouSchedule::shown_periods].
+    pub periods: Vec<PeriodCost>,
+    pub charges: Charges,
+    /// The costs with the charges.
+    pub costs: Costs,
+    pub details: Details,
+}
+
+impl AnalysisResult {
+    pub fn total_kwh(&self) -> BigDecimal {
+        &self.imported - &self.exported
+    }
+
+    pub fn tou_cost(&self) -> BigDecimal {
+        self.breakdown.total_cost()
+    }
+
+    pub fn savings(&self) -> BigDecimal {
+        &self.current_cost - self.tou_cost()
+    }
+
+    pub fn current_export_credit(&self) -> BigDecimal {
+        &self.current_import_cost - &self.current_cost
+    }
+
+    pub fn tou_export_credit(&self) -> BigDecimal {
+        &self.tou_import_cost - self.tou_cost()
+    }
+
+    pub fn verdict(&self) -> Verdict {
+        Verdict::from_costs(&self.current_cost, &self.tou_cost())
+    }
+
+    /// The number of days from the first to the last date of the usage, inclusive.
+    pub fn covered_days(&self) -> i64 {
+        self.date_range
+            .map_or(0, |(first, last)| days_between(first, last))
+    }
+
+    /// The effective rate per KWH of [Costs::current], see [blended_rate].
+    pub fn current_blended_rate(&self) -> Option<BigDecimal> {
+        blended_rate(&self.costs.current, &self.total_kwh())
+    }
+
+    /// The effective rate per KWH of [Costs::tou], see [blended_rate].
+    pub fn tou_blended_rate(&self) -> Option<BigDecimal> {
+        blended_rate(&self.costs.tou, &self.total_kwh())
+    }
+}
+
+/// The current and TOU rates to compare, along with the other rates [Analyzer] prices the usage
+/// at to explain the difference.
+#[derive(Debug, Clone)]
+pub struct AnalysisRates {
+    pub current: CurrentRate,
+    pub tou: DatedTouRates,
+    average_tou: DatedTouRates,
+    /// A flat current rate as TOU rates, to price each TOU period at the current rate.
+    current_as_tou: Option<DatedTouRates>,
+}
+
+impl AnalysisRates {
+    pub fn new(current: CurrentRate, tou: DatedTouRates) -> Self {
+        let current_as_tou = match &current {
+            CurrentRate::Flat(rate) => Some(TouRates::uniform(rate.clone()).into()),
+            CurrentRate::Tiered(_) => None,
+        };
+        Self {
+            average_tou: tou.averaged(),
+            current,
+            tou,
+            current_as_tou,
+        }
+    }
+}
+
+/// Compares usage under the current rate and TOU rates one usage entry at a time, so the usage
+/// data can be streamed instead of kept in memory. Use [analyze] to compare entries that are
+/// already in memory.
+pub struct Analyzer<'a> {
+    rates: &'a AnalysisRates,
+    schedule: &'a TouSchedule,
+    options: &'a CostOptions,
+    charge_options: &'a ChargeOptions,
+    totals: CostAccumulator<'a>,
+    /// The totals with only the imports, which split the costs into what imports cost and what
+    /// exports credit.
+    import_totals: CostAccumulator<'a>,
+    average_totals: CostAccumulator<'a>,
+    current_totals: Option<CostAccumulator<'a>>,
+    /// The totals of each billing period, for the minimum bill and for netting exports once per
+    /// billing period.
+    billing_periods: Option<BillingPeriodCosts<'a>>,
+    peak_demand: Option<PeakDemand>,
+}
+
+impl<'a> Analyzer<'a> {
+    pub fn new(
+        rates: &'a AnalysisRates,
+        schedule: &'a TouSchedule,
+        options: &'a CostOptions,
+        charge_options: &'a ChargeOptions,
+    ) -> Self {
+        let accumulator =
+            |tou_rates| CostAccumulator::new(&rates.current, tou_rates, schedule, options);
+        let needs_billing_periods =
+            charge_options.minimum_bill.is_some() || charge_options.no_export_netting;
+        Self {
+            rates,
+            schedule,
+            options,
+            charge_options,
+            totals: accumulator(&rates.tou),
+            import_totals: accumulator(&rates.tou),
+            average_totals: accumulator(&rates.average_tou),
+            current_totals: rates.current_as_tou.as_ref().map(accumulator),
+            billing_periods: needs_billing_periods.then(|| {
+                BillingPeriodCosts::new(
+                    &rates.current,
+                    &rates.tou,
+                    schedule,
+                    options,
+                    charge_options.billing_day,
+                )
+            }),
+            peak_demand: charge_options
+                .demand_charge_per_kw
+                .is_some()
+                .then(|| PeakDemand::new(charge_options.billing_day)),
+        }
+    }
+
+    pub fn add(&mut self, entry: &UsageEntry) {
+        self.totals.add(entry);
+        self.import_totals.add(&entry.imports_only());
+        self.average_totals.add(entry);
+        if let Some(current_totals) = &mut self.current_totals {
+            current_totals.add(entry);
+        }
+        if let Some(billing_periods) = &mut self.billing_periods {
+            billing_periods.add(entry);
+        }
+        if let Some(peak_demand) = &mut self.peak_demand {
+            peak_demand.add(entry);
+        }
+    }
+
+    /// Adds a day of usage without its entries, see [CostAccumulator::add_day]. A day has no
+    /// demand, so the demand charge needs the entries.
+    pub fn add_day(&mut self, day: &DaySummary) {
+        self.totals.add_day(day);
+        self.import_totals.add_day(&day.imports_only());
+        self.average_totals.add_day(day);
+        if let Some(current_totals) = &mut self.current_totals {
+            current_totals.add_day(day);
+        }
+        if let Some(billing_periods) = &mut self.billing_periods {
+            billing_periods.add_day(day);
+        }
+    }
+
+    /// The totals of the usage so far at the TOU rates, e.g. for the days it covers.
+    pub fn usage(&self) -> &CostAccumulator<'a> {
+        &self.totals
+    }
+
+    pub fn finish(&self) -> AnalysisResult {
+        self.finish_with(&[], &DetailOptions::default())
+    }
+
+    /// Like [Analyzer::finish], but also works out the `details` of `usage_data`, which should be
+    /// the entries that were added, in order.
+    pub fn finish_with(
+        &self,
+        usage_data: &[UsageEntry],
+        details: &DetailOptions,
+    ) -> AnalysisResult {
+        let breakdown = self.totals.breakdown();
+        let current_cost = self.totals.current_cost();
+        let current_by_period = if self.options.net_metering.is_some() {
+            None
+        } else {
+            match &self.current_totals {
+                Some(current_totals) => Some(current_totals.breakdown()),
+                None => split_cost_by_kwh(&breakdown, &current_cost),
+            }
+        };
+        let periods = self
+            .schedule
+            .shown_periods()
+            .into_iter()
+            .map(|tou| PeriodCost {
+                tou,
+                entries: self.totals.intervals(tou),
+                kwh: breakdown.for_period(tou).kwh.clone(),
+                kwh_share: breakdown.kwh_share(tou),
+                tou_cost: breakdown.for_period(tou).cost.clone(),
+                current_cost: current_by_period
+                    .as_ref()
+                    .map(|current| current.for_period(tou).cost.clone()),
+            })
+            .collect();
+        let charges = self.charges();
+        let current_import_cost = self.import_totals.current_cost();
+        let tou_import_cost = self.import_totals.breakdown().total_cost();
+        let average_rate_tou_cost = self.average_totals.breakdown().total_cost();
+        let round = |amount| self.charge_options.round(amount);
+        // The unused credit isn't part of what the imports alone would cost at the current rate.
+        let current_charge = charges.fixed_charge() + &charges.minimum_charge.current;
+        let tou_charge = charges.tou();
+        let costs = Costs {
+            current: round(&current_cost + &current_charge + &charges.unused_credit.current),
+            current_import: round(&current_import_cost + &current_charge),
+            tou: round(breakdown.total_cost() + &tou_charge),
+            tou_import: round(&tou_import_cost + &tou_charge),
+            average_rate_tou: round(&average_rate_tou_cost + &tou_charge),
+        };
+        let mut result = AnalysisResult {
+            imported: self.totals.imported().clone(),
+            exported: self.totals.exported().clone(),
+            days: self.totals.dates().len(),
+            date_range: self.totals.date_range(),
+            current_import_cost,
+            tou_import_cost,
+            average_rate_tou_cost,
+            current_cost,
+            breakdown,
+            current_by_period,
+            periods,
+            charges,
+            costs,
+            details: Details::default(),
+        };
+        result.details = self.details(&result, usage_data, details);
+        result
+    }
+
+    fn charges(&self) -> Charges {
+        let options = self.charge_options;
+        let base_charge = match &options.base_charge_per_day {
+            Some(charge_per_day) => self.totals.fixed_charge(charge_per_day),
+            None => BigDecimal::from(0),
+        };
+        // Only customers who export have a net meter to pay for.
+        let solar_meter_fee_per_period = match &options.solar_meter_fee {
+            Some(fee) if *self.totals.exported() > BigDecimal::from(0) => fee.clone(),
+            _ => BigDecimal::from(0),
+        };
+        let billing_periods = self.totals.billing_period_count(options.billing_day);
+        let demand_charge = match (&options.demand_charge_per_kw, &self.peak_demand) {
+            (Some(charge_per_kw), Some(peak_demand)) => peak_demand.charge(charge_per_kw),
+            _ => BigDecimal::from(0),
+        };
+        let minimum_charge = match (&options.minimum_bill, &self.billing_periods) {
+            (Some(minimum), Some(periods)) => {
+                self.minimum_charge(periods, minimum, &solar_meter_fee_per_period)
+            }
+            _ => MinimumCharge::default(),
+        };
+        let unused_credit = match &self.billing_periods {
+            Some(periods) if options.no_export_netting => UnusedCredit {
+                current: unused_credit(
+                    periods
+                        .periods()
+                        .values()
+                        .map(|totals| totals.current_cost()),
+                ),
+                tou: unused_credit(
+                    periods
+                        .periods()
+                        .values()
+                        .map(|totals| totals.breakdown().total_cost()),
+                ),
+            },
+            _ => UnusedCredit::default(),
+        };
+        Charges {
+            base_charge,
+            solar_meter_fee: &solar_meter_fee_per_period * BigDecimal::from(billing_periods as u64),
+            solar_meter_fee_per_period,
+            billing_periods,
+            demand_charge,
+            peak_demand: self
+                .peak_demand
+                .as_ref()
+                .map(|peak_demand| peak_demand.peaks().clone())
+                .unwrap_or_default(),
+            minimum_charge,
+            unused_credit,
+        }
+    }
+
+    /// Tops up each billing period in `periods` that costs less than `minimum` under either plan,
+    /// counting the base service charge for its days and, for TOU, its demand charge.
+    fn minimum_charge(
+        &self,
+        periods: &BillingPeriodCosts,
+        minimum: &BigDecimal,
+        solar_meter_fee: &BigDecimal,
+    ) -> MinimumCharge {
+        let options = self.charge_options;
+        let mut charge = MinimumCharge::default();
+        for (start, totals) in periods.periods() {
+            let fixed_charge = match &options.base_charge_per_day {
+                Some(charge_per_day) => totals.fixed_charge(charge_per_day),
+                None => BigDecimal::from(0),
+            } + solar_meter_fee;
+            let demand_charge = match (&options.demand_charge_per_kw, &self.peak_demand) {
+                (Some(charge_per_kw), Some(peak_demand)) => peak_demand
+                    .peaks()
+                    .get(start)
+                    .map_or_else(BigDecimal::default, |kw| kw * charge_per_kw),
+                _ => BigDecimal::from(0),
+            };
+            let tou_cost = totals.breakdown().total_cost() + &fixed_charge + demand_charge;
+            let current_cost = totals.current_cost() + fixed_charge;
+            if current_cost < *minimum {
+                charge.current += minimum - current_cost;
+                charge.current_periods += 1;
+            }
+            if tou_cost < *minimum {
+                charge.tou += minimum - tou_cost;
+                charge.tou_periods += 1;
+            }
+        }
+        charge
+    }
+
+    fn details(
+        &self,
+        result: &AnalysisResult,
+        usage_data: &[UsageEntry],
+        options: &DetailOptions,
+    ) -> Details {
+        let tou_rates = &self.rates.tou;
+        let schedule = self.schedule;
+        let cost_options = self.options;
+        let round = |amount| self.charge_options.round(amount);
+        let charge_per_day = self
+            .charge_options
+            .base_charge_per_day
+            .clone()
+            .unwrap_or_default();
+        let tou_charge = result.charges.tou();
+        let costs = &result.costs;
+        let total_kwh = result.total_kwh();
+        // The current cost of some of the entries, which have `kwh` between them.
+        let current_cost_of = |entries: &[&UsageEntry], kwh: &BigDecimal| {
+            if let CurrentRate::Flat(_) = self.rates.current {
+                calculate_current_cost(&self.rates.current, cost_options, entries.iter().copied())
+            } else if total_kwh != BigDecimal::from(0) {
+                // Tiers apply to the whole billing period, so split its cost by usage.
+                (&result.current_cost * kwh / &total_kwh).round(6)
+            } else {
+                BigDecimal::from(0)
+            }
+        };
+        let tou_cost_of = |entries: Vec<&UsageEntry>| {
+            calculate_tou_cost(tou_rates, schedule, cost_options, entries.into_iter())
+        };
+        // The off-peak rate can differ by season and date, so shifting usage is modeled at each
+        // off-peak rate separately.
+        let by_off_rate =
+            (options.shift_peak_to_off.is_some() || options.shift_breakeven).then(|| {
+                let mut by_off_rate: BTreeMap<&BigDecimal, Vec<&UsageEntry>> = BTreeMap::new();
+                for entry in usage_data {
+                    let off_rate = &tou_rates.for_date(entry.date).off;
+                    by_off_rate.entry(off_rate).or_default().push(entry);
+                }
+                by_off_rate
+                    .into_iter()
+                    .map(|(off_rate, entries)| {
+                        let breakdown = calculate_tou_breakdown(
+                            tou_rates,
+                            schedule,
+                            cost_options,
+                            entries.into_iter(),
+                        );
+                        (off_rate, breakdown)
+                    })
+                    .collect::<Vec<_>>()
+            });
+        let shifted_cost = |fraction: &BigDecimal| -> BigDecimal {
+            by_off_rate
+                .iter()
+                .flatten()
+                .map(|(off_rate, breakdown)| {
+                    breakdown.shift_peak_to_off(fraction, off_rate).total_cost()
+                })
+                .sum()
+        };
+        let daily = options.daily.then(|| {
+            let mut daily = group_by_date(usage_data.iter())
+                .into_iter()
+                .map(|(date, entries)| {
+                    let kwh: BigDecimal = entries.iter().map(|entry| entry.kwh_total()).sum();
+                    let current_cost = current_cost_of(&entries, &kwh);
+                    let tou_cost = tou_cost_of(entries);
+                    let current_cost = current_cost + &charge_per_day;
+                    let tou_cost = tou_cost + &charge_per_day;
+                    DailyCost {
+                        date,
+                        kwh,
+                        savings: &current_cost - &tou_cost,
+                        current_cost,
+                        tou_cost,
+                        smoothed_current_cost: None,
+                        smoothed_tou_cost: None,
+                    }
+                })
+                .collect::<Vec<_>>();
+            if let Some(smooth_days) = options.smooth_days {
+                let average = |cost: fn(&DailyCost) -> &BigDecimal| {
+                    let series: Vec<_> = daily.iter().map(|day| (day.date, cost(day))).collect();
+                    trailing_average(&series, smooth_days)
+                };
+                let current = average(|day| &day.current_cost);
+                let tou = average(|day| &day.tou_cost);
+                for (day, (current, tou)) in daily.iter_mut().zip(current.into_iter().zip(tou)) {
+                    day.smoothed_current_cost = Some(current);
+                    day.smoothed_tou_cost = Some(tou);
+                }
+            }
+            daily
+        });
+        let by_day_type = options.by_day_type.then(|| {
+            let (weekends, weekdays): (Vec<&UsageEntry>, Vec<&UsageEntry>) =
+                usage_data.iter().partition(|entry| is_weekend(entry.date));
+            [("weekday", weekdays), ("weekend", weekends)].map(|(day_type, entries)| {
+                let kwh: BigDecimal = entries.iter().map(|entry| entry.kwh_total()).sum();
+                let days = count_days(entries.iter().copied());
+                let fixed_charge = &charge_per_day * BigDecimal::from(days as u64);
+                let current_cost = current_cost_of(&entries, &kwh) + &fixed_charge;
+                let tou_cost = tou_cost_of(entries) + fixed_charge;
+                DayTypeCost {
+                    day_type,
+                    days,
+                    kwh,
+                    savings: &current_cost - &tou_cost,
+                    current_cost,
+                    tou_cost,
+                }
+            })
+        });
+        let year_over_year = options.year_over_year.then(|| {
+            group_by_month(usage_data.iter())
+                .into_iter()
+                .map(|((year, month), entries)| {
+                    let days = count_days(entries.iter().copied());
+                    let fixed_charge = &charge_per_day * BigDecimal::from(days as u64);
+                    MonthCost {
+                        year,
+                        month,
+                        kwh: entries.iter().map(|entry| entry.kwh_total()).sum(),
+                        tou_cost: tou_cost_of(entries) + fixed_charge,
+                    }
+                })
+                .collect()
+        });
+        let top_peak_days = options.top_peak_days.map(|count| {
+            let mut days: Vec<PeakDay> = group_by_date(usage_data.iter())
+                .into_iter()
+                .map(|(date, entries)| {
+                    let breakdown = calculate_tou_breakdown(
+                        tou_rates,
+                        schedule,
+                        cost_options,
+                        entries.into_iter(),
+                    );
+                    let peak = breakdown.for_period(TimeOfUse::Peak);
+                    PeakDay {
+                        date,
+                        kwh: peak.kwh.clone(),
+                        cost: peak.cost.clone(),
+                    }
+                })
+                .collect();
+            days.sort_by(|a, b| match options.rank_peak_days_by {
+                PeakDayRanking::Kwh => b.kwh.cmp(&a.kwh),
+                PeakDayRanking::Cost => b.cost.cmp(&a.cost),
+            });
+            days.truncate(count);
+            days
+        });
+        let breakeven = options.breakeven.then(|| {
+            let tou_cost_at = |multiplier: &BigDecimal| {
+                round(
+                    calculate_tou_cost(
+                        &tou_rates.scaled(multiplier),
+                        schedule,
+                        cost_options,
+                        usage_data.iter(),
+                    ) + &tou_charge,
+                )
+            };
+            if tou_cost_at(&BigDecimal::from(1)) >= costs.current {
+                Breakeven::AlreadyCostsMore
+            } else {
+                match find_breakeven_multiplier(&costs.current, tou_cost_at) {
+                    Some(multiplier) => Breakeven::Multiplier {
+                        rates: Box::new(tou_rates.scaled(&multiplier)),
+                        multiplier,
+                    },
+                    None => Breakeven::Never,
+                }
+            }
+        });
+        let annualized_savings = options.annualize.then(|| {
+            let days = result.covered_days();
+            (days > 0).then(|| costs.savings() * BigDecimal::from(365) / BigDecimal::from(days))
+        });
+        Details {
+            histogram: options.histogram.then(|| hourly_usage(usage_data.iter())),
+            stats: options.stats.then(|| UsageStats::new(usage_data, schedule)),
+            daily,
+            by_day_type,
+            year_over_year,
+            top_peak_days,
+            breakeven,
+            shifted_tou_cost: options
+                .shift_peak_to_off
+                .as_ref()
+                .map(|fraction| round(shifted_cost(fraction) + &tou_charge)),
+            shift_breakeven: options.shift_breakeven.then(|| {
+                let full_shift_savings =
+                    shifted_cost(&BigDecimal::from(0)) - shifted_cost(&BigDecimal::from(1));
+                find_shift_breakeven(
+                    &(&costs.tou - &costs.current),
+                    &full_shift_savings,
+                    &result.breakdown.peak.kwh,
+                )
+            }),
+            fixed_monthly_cost: options.fixed_monthly.as_ref().map(|amount| {
+                round(amount * BigDecimal::from(result.charges.billing_periods as u64))
+            }),
+            annualized_savings: annualized_savings.flatten(),
+        }
+    }
+}
+
+/// Compares the cost of `entries` under `current_rate` and `tou_rates`.
+pub fn analyze<'a>(
+    entries: impl IntoIterator<Item = &'a UsageEntry>,
+    current_rate: &CurrentRate,
+    tou_rates: &DatedTouRates,
+    schedule: &TouSchedule,
+    options: &CostOptions,
+) -> AnalysisResult {
+    let rates = AnalysisRates::new(current_rate.clone(), tou_rates.clone());
+    let charge_options = ChargeOptions::default();
+    let mut analyzer = Analyzer::new(&rates, schedule, options, &charge_options);
+    for entry in entries {
+        analyzer.add(entry);
+    }
+    analyzer.finish()
+}
+
+/// The TOU costs of the same usage under two sets of TOU rates, like this year's rates and a
+/// proposed increase. The costs are for the energy alone, without any fixed charges.
+#[derive(Debug, Clone)]
+pub struct ScenarioComparison {
+    pub a_cost: BigDecimal,
+    pub b_cost: BigDecimal,
+}
+
+impl ScenarioComparison {
+    /// How much more scenario B costs than scenario A, which is negative if it costs less.
+    pub fn difference(&self) -> BigDecimal {
+        &self.b_cost - &self.a_cost
+    }
+}
+
+/// Compares the TOU cost of `entries` under the rates of scenario A and scenario B.
+pub fn compare_scenarios(
+    entries: &[UsageEntry],
+    a_rates: &DatedTouRates,
+    b_rates: &DatedTouRates,
+    schedule: &TouSchedule,
+    options: &CostOptions,
+) -> ScenarioComparison {
+    ScenarioComparison {
+        a_cost: calculate_tou_cost(a_rates, schedule, options, entries.iter()),
+        b_cost: calculate_tou_cost(b_rates, schedule, options, entries.iter()),
+    }
+}
+
+/// The total KWH and TOU cost worked out entry by entry, separately from [Analyzer], to check its
+/// results against.
+#[derive(Debug, Default)]
+pub struct SelfCheck {
+    kwh: BigDecimal,
+    tou_cost: BigDecimal,
+}
+
+impl SelfCheck {
+    pub fn add(
+        &mut self,
+        entry: &UsageEntry,
+        tou_rates: &DatedTouRates,
+        schedule: &TouSchedule,
+        options: &CostOptions,
+    ) {
+        self.kwh += entry.kwh_total();
+        let rates = tou_rates.for_date(entry.date);
+        for share in split_usage_by_period(entry, schedule) {
+            self.tou_cost += share.cost(rates, options);
+        }
+    }
+
+    /// The ways `result` is inconsistent with itself or with the totals worked out entry by entry.
+    /// Net metering settles each period's cost over billing periods, so the TOU cost can't be
+    /// worked out entry by entry and isn't checked then.
+    pub fn failures(&self, result: &AnalysisResult, options: &CostOptions) -> Vec<String> {
+        let tolerance = BigDecimal::new(1.into(), 6);
+        let mut failures = Vec::new();
+        let mut check = |description: &str, expected: &BigDecimal, actual: BigDecimal| {
+            if (expected - &actual).abs() > tolerance {
+                failures.push(format!(
+                    "{}: expected {}, got {}",
+                    description,
+                    expected.normalized(),
+                    actual.normalized()
+                ));
+            }
+        };
+        let breakdown = &result.breakdown;
+        check("net KWH", &self.kwh, result.total_kwh());
+        check("sum of KWH by TOU period", &self.kwh, breakdown.total_kwh());
+        if options.net_metering.is_none() {
+            check(
+                "sum of TOU cost by TOU period",
+                &self.tou_cost,
+                breakdown.total_cost(),
+            );
+        }
+        if let Some(current_by_period) = &result.current_by_period {
+            check(
+                "sum of current cost by TOU period",
+                &result.current_cost,
+                current_by_period.total_cost(),
+            );
+        }
+        failures
+    }
+}
+
+/// The usage entries of one day split by TOU period, with what each part costs under the TOU
+/// rates, to reconcile the day against a bill.
+#[derive(Debug, Clone)]
+pub struct DayExplanation {
+    pub date: Date,
+    pub off_peak_day: bool,
+    pub entries: usize,
+    pub shares: Vec<ExplainedShare>,
+    pub kwh: BigDecimal,
+    pub tou_cost: BigDecimal,
+    /// The current rate and what the day costs at it. A tiered rate depends on the whole billing
+    /// period's usage, so a single day has no cost of its own under it and this is `None`.
+    pub current: Option<(BigDecimal, BigDecimal)>,
+}
+
+/// The part of a usage entry in one TOU period.
+#[derive(Debug, Clone)]
+pub struct ExplainedShare {
+    pub start_time: Time,
+    pub end_time: Time,
+    pub tou: TimeOfUse,
+    pub rate: BigDecimal,
+    pub kwh: BigDecimal,
+    pub cost: BigDecimal,
+}
+
+impl DayExplanation {
+    /// Explains the usage `entries` on `date`, which should be in order of their start times.
+    pub fn new(
+        date: Date,
+        entries: &[UsageEntry],
+        rates: &AnalysisRates,
+        schedule: &TouSchedule,
+        options: &CostOptions,
+    ) -> Self {
+        let tou_rates = rates.tou.for_date(date);
+        let shares = entries
+            .iter()
+            .flat_map(|entry| {
+                split_usage_by_period(entry, schedule)
+                    .into_iter()
+                    .map(|share| ExplainedShare {
+                        start_time: entry.start_time,
+                        end_time: entry.end_time,
+                        tou: share.tou,
+                        rate: tou_rates.for_period(share.tou).clone(),
+                        kwh: share.kwh_total(),
+                        cost: share.cost(tou_rates, options),
+                    })
+            })
+            .collect();
+        let current = match &rates.current {
+            CurrentRate::Flat(rate) => Some((
+                rate.clone(),
+                calculate_current_cost(&rates.current, options, entries.iter()),
+            )),
+            CurrentRate::Tiered(_) => None,
+        };
+        Self {
+            date,
+            off_peak_day: schedule.is_off_peak_day(date),
+            entries: entries.len(),
+            shares,
+            kwh: entries.iter().map(|entry| entry.kwh_total()).sum(),
+            tou_cost: calculate_tou_cost(&rates.tou, schedule, options, entries.iter()),
+            current,
+        }
+    }
+}
+
+/// Splits `cost` across the TOU periods in proportion to their KWH in `breakdown`, or `None` if
+/// the KWH can't be split, like when a period exported more than it imported.
+fn split_cost_by_kwh(breakdown: &TouBreakdown, cost: &BigDecimal) -> Option<TouBreakdown> {
+    let share = |tou| {
+        let share = breakdown.kwh_share(tou)?;
+        Some(PeriodUsage {
+            kwh: breakdown.for_period(tou).kwh.clone(),
+            cost: (cost * share).round(6),
+        })
+    };
+    let super_off = share(TimeOfUse::SuperOff)?;
+    let off = share(TimeOfUse::Off)?;
+    let mid = share(TimeOfUse::Mid)?;
+    // The peak period gets whatever's left, so the periods add up to exactly `cost`.
+    let peak = PeriodUsage {
+        kwh: breakdown.peak.kwh.clone(),
+        cost: cost - &super_off.cost - &off.cost - &mid.cost,
+    };
+    Some(TouBreakdown {
+        super_off,
+        off,
+        mid,
+        peak,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_calculator::NetMetering;
+    use crate::synthetic::LoadShape;
+    use jiff::civil::{date, time};
+    use std::str::FromStr;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn entry_order_does_not_change_the_result() {
+        let mut shape = LoadShape::flat(decimal("0.6"));
+        shape.hourly_kwh[18] = decimal("2.4");
+        let mut usage = shape.generate(date(2024, 3, 1), date(2024, 3, 20));
+        // Solar exports around noon that grow over the usage, so no two days are the same.
+        for (index, entry) in usage.iter_mut().enumerate() {
+            if (10..14).contains(&entry.start_time.hour()) {
+                entry.exported = BigDecimal::from(index as u64) / BigDecimal::from(1000);
+            }
+        }
+        // Every 7th entry, wrapping around, which visits them all since 7 doesn't divide the count.
+        assert_ne!(usage.len() % 7, 0);
+        let shuffled: Vec<UsageEntry> = (0..usage.len())
+            .map(|index| usage[index * 7 % usage.len()].clone())
+            .collect();
+
+        let rates = AnalysisRates::new(
+            CurrentRate::Flat(decimal("0.12")),
+            DatedTouRates::from(TouRates {
+                super_off: None,
+                off: decimal("0.0828"),
+                mid: decimal("0.1449"),
+                peak: decimal("0.1656"),
+            }),
+        );
+        let schedule = TouSchedule::scl();
+        let charge_options = ChargeOptions {
+            base_charge_per_day: Some(decimal("0.5")),
+            demand_charge_per_kw: Some(decimal("2")),
+            minimum_bill: Some(decimal("40")),
+            no_export_netting: true,
+            billing_day: 15,
+            rounding: Some(Rounding::HalfEven),
+            ..ChargeOptions::default()
+        };
+        let details = DetailOptions {
+            histogram: true,
+            stats: true,
+            daily: true,
+            smooth_days: Some(3),
+            by_day_type: true,
+            year_over_year: true,
+            top_peak_days: Some(3),
+            breakeven: true,
+            shift_breakeven: true,
+            annualize: true,
+            ..DetailOptions::default()
+        };
+        let net_metering = CostOptions {
+            net_metering: Some(NetMetering {
+                excess_credit_rate: decimal("0.05"),
+                billing_day: 15,
+            }),
+            ..CostOptions::default()
+        };
+        for options in [CostOptions::default(), net_metering] {
+            let analyze = |usage: &[UsageEntry]| {
+                let mut analyzer = Analyzer::new(&rates, &schedule, &options, &charge_options);
+                for entry in usage {
+                    analyzer.add(entry);
+                }
+                analyzer.finish_with(usage, &details)
+            };
+            let (in_order, out_of_order) = (analyze(&usage), analyze(&shuffled));
+            assert_eq!(in_order.costs.tou, out_of_order.costs.tou);
+            assert_eq!(in_order.costs.current, out_of_order.costs.current);
+            // The results have no `PartialEq`, but their debug output has every field, down to
+            // the scale of each decimal.
+            assert_eq!(format!("{:?}", in_order), format!("{:?}", out_of_order));
+        }
+    }
+
+    #[test]
+    fn breakeven_compares_rounded_costs() {
+        // A weekday mid-peak hour, which costs 0.1398 under TOU and 0.1449 under the current
+        // rate, both 0.14 once rounded.
+        let usage = [UsageEntry {
+            date: date(2024, 3, 4),
+            start_time: time(10, 0, 0, 0),
+            end_time: time(11, 0, 0, 0),
+            imported: BigDecimal::from(1),
+            exported: BigDecimal::from(0),
+            notes: None,
+        }];
+        let rates = AnalysisRates::new(
+            CurrentRate::Flat(decimal("0.1449")),
+            DatedTouRates::from(TouRates {
+                super_off: None,
+                off: decimal("0.0828"),
+                mid: decimal("0.1398"),
+                peak: decimal("0.1656"),
+            }),
+        );
+        let schedule = TouSchedule::scl();
+        let options = CostOptions::default();
+        let charge_options = ChargeOptions {
+            rounding: Some(Rounding::HalfUp),
+            ..ChargeOptions::default()
+        };
+        let details = DetailOptions {
+            breakeven: true,
+            ..DetailOptions::default()
+        };
+        let mut analyzer = Analyzer::new(&rates, &schedule, &options, &charge_options);
+        for entry in &usage {
+            analyzer.add(entry);
+        }
+        let result = analyzer.finish_with(&usage, &details);
+        assert_eq!(result.costs.tou, result.costs.current);
+        assert!(
+            matches!(result.details.breakeven, Some(Breakeven::AlreadyCostsMore)),
+            "{:?}",
+            result.details.breakeven
+        );
+    }
+}
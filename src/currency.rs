@@ -0,0 +1,58 @@
+//! Formats dollar amounts for display.
+
+use bigdecimal::{BigDecimal, RoundingMode};
+use clap::ValueEnum;
+
+/// How negative amounts are shown.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NegativeStyle {
+    /// With a leading minus sign, like `-$1.23`.
+    #[default]
+    Sign,
+    /// In parentheses, like `($1.23)`, as accountants do.
+    Parentheses,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CurrencyFormat {
+    pub symbol: String,
+    pub negative: NegativeStyle,
+}
+
+impl Default for CurrencyFormat {
+    fn default() -> Self {
+        Self {
+            symbol: "$".to_string(),
+            negative: NegativeStyle::default(),
+        }
+    }
+}
+
+impl CurrencyFormat {
+    /// Formats `amount` rounded to `decimals` places, with thousands separators, like `$1,234.56`.
+    pub fn format(&self, amount: &BigDecimal, decimals: i64) -> String {
+        let rounded = amount.with_scale_round(decimals, RoundingMode::HalfEven);
+        let negative = rounded < BigDecimal::from(0);
+        let digits = rounded.abs().to_plain_string();
+        let (integer, fraction) = match digits.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (digits.as_str(), None),
+        };
+        let mut formatted = self.symbol.clone();
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                formatted.push(',');
+            }
+            formatted.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            formatted.push('.');
+            formatted.push_str(fraction);
+        }
+        match (negative, self.negative) {
+            (false, _) => formatted,
+            (true, NegativeStyle::Sign) => format!("-{}", formatted),
+            (true, NegativeStyle::Parentheses) => format!("({})", formatted),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::json::{self, Value};
+use jiff::civil::{Date, Weekday, date};
+use std::collections::HashSet;
+
+/// The holidays on which off-peak rates apply all day.
+#[derive(Debug, Clone)]
+pub enum Holidays {
+    /// The standard SCL observed holidays, computed for whatever year the date is in.
+    Scl,
+    /// An explicit list of holiday dates.
+    Custom(HashSet<Date>),
+    /// The standard SCL observed holidays and these extra dates.
+    SclAnd(HashSet<Date>),
+}
+
+impl Holidays {
+    pub fn contains(&self, day: Date) -> bool {
+        match self {
+            Holidays::Scl => is_scl_holiday(day),
+            Holidays::Custom(dates) => dates.contains(&day),
+            Holidays::SclAnd(dates) => is_scl_holiday(day) || dates.contains(&day),
+        }
+    }
+
+    /// These holidays with `extra` added.
+    pub fn with(self, extra: HashSet<Date>) -> Self {
+        match self {
+            Holidays::Scl => Holidays::SclAnd(extra),
+            Holidays::Custom(mut dates) => {
+                dates.extend(extra);
+                Holidays::Custom(dates)
+            }
+            Holidays::SclAnd(mut dates) => {
+                dates.extend(extra);
+                Holidays::SclAnd(dates)
+            }
+        }
+    }
+}
+
+/// Parses a list of holidays: one YYYY-MM-DD date per line, ignoring blank lines and lines
+/// starting with `#`, or a JSON array of YYYY-MM-DD strings. Returns a message for each line or
+/// array item that isn't a valid date.
+pub fn parse_holiday_list(contents: &str) -> Result<HashSet<Date>, Vec<String>> {
+    let mut dates = HashSet::new();
+    let mut errors = Vec::new();
+    if contents.trim_start().starts_with('[') {
+        let items = match json::parse(contents) {
+            Ok(Value::Array(items)) => items,
+            Ok(other) => {
+                return Err(vec![format!(
+                    "expected an array, found {}",
+                    json::kind(&other)
+                )]);
+            }
+            Err(e) => return Err(vec![format!("not valid JSON: {}", e)]),
+        };
+        for (index, item) in items.iter().enumerate() {
+            match item {
+                Value::String(text) => match text.parse() {
+                    Ok(date) => {
+                        dates.insert(date);
+                    }
+                    Err(e) => errors.push(format!(
+                        "item {}: invalid date {:?}: {}",
+                        index + 1,
+                        text,
+                        e
+                    )),
+                },
+                other => errors.push(format!(
+                    "item {}: expected a YYYY-MM-DD string, found {}",
+                    index + 1,
+                    json::kind(other)
+                )),
+            }
+        }
+    } else {
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.parse() {
+                Ok(date) => {
+                    dates.insert(date);
+                }
+                Err(e) => errors.push(format!(
+                    "line {}: invalid date {:?}: {}",
+                    index + 1,
+                    line,
+                    e
+                )),
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(dates)
+    } else {
+        Err(errors)
+    }
+}
+
+/// New Year's Day, Memorial Day, Independence Day, Labor Day, Thanksgiving Day, and Christmas Day.
+/// Fixed-date holidays that fall on a Sunday are observed on the following Monday.
+fn is_scl_holiday(day: Date) -> bool {
+    let year = day.year();
+    let fixed = [date(year, 1, 1), date(year, 7, 4), date(year, 12, 25)];
+    let floating = [
+        date(year, 5, 1).nth_weekday_of_month(-1, Weekday::Monday),
+        date(year, 9, 1).nth_weekday_of_month(1, Weekday::Monday),
+        date(year, 11, 1).nth_weekday_of_month(4, Weekday::Thursday),
+    ];
+    fixed.into_iter().any(|holiday| {
+        holiday == day
+            || (holiday.weekday() == Weekday::Sunday && holiday.tomorrow().ok() == Some(day))
+    }) || floating
+        .into_iter()
+        .any(|holiday| holiday.ok() == Some(day))
+}
+
+pub fn is_weekend(day: Date) -> bool {
+    matches!(day.weekday(), Weekday::Saturday | Weekday::Sunday)
+}
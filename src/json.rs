@@ -0,0 +1,69 @@
+//! Helpers for reading and writing JSON with serde.
+//!
+//! Numbers are read with their full text, so rates like `0.0828` aren't rounded through a float,
+//! and decimals are written as strings for the same reason.
+
+use bigdecimal::BigDecimal;
+use serde::{Serialize, Serializer};
+use std::borrow::Borrow;
+
+pub use serde_json::{Error, Map, Value};
+
+/// A short name for the kind of value, for error messages.
+pub fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Parses `input`, keeping the keys of objects in the order they're given.
+pub fn parse(input: &str) -> Result<Value, Error> {
+    serde_json::from_str(input)
+}
+
+/// Writes `value` as compact JSON.
+pub fn to_string(value: &impl Serialize) -> String {
+    serde_json::to_string(value).expect("results can always be written as JSON")
+}
+
+/// Serializes a list of key and value pairs as an object with the keys in the same order, for use
+/// with `#[serde(serialize_with = "json::map")]`.
+pub fn map<S: Serializer, K: Serialize, V: Serialize>(
+    entries: &[(K, V)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(entries.iter().map(|(key, value)| (key, value)))
+}
+
+/// Serializes a decimal as a string of its plain, normalized digits, like `"0.0828"`, for use with
+/// `#[serde(with = "json::decimal")]`.
+pub mod decimal {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        value: &impl Borrow<BigDecimal>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.borrow().normalized().to_plain_string())
+    }
+}
+
+/// Like [decimal], but for an optional decimal, which is written as `null` when it's `None`.
+pub mod optional_decimal {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<impl Borrow<BigDecimal>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => decimal::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Tool that might be used when you need to compare SCL TOU rates.
+//!
+//! Given usage data and rates, calculates what the usage costs under a static KWH rate and under
+//! TOU rates.
+//!
+//! ```
+//! use bigdecimal::BigDecimal;
+//! use jiff::civil::{date, time};
+//! use std::str::FromStr;
+//! use ttmbuwyntcstr::schedule::TouSchedule;
+//! use ttmbuwyntcstr::{CostOptions, DatedTouRates, TouRates, UsageEntry, calculate_tou_cost};
+//!
+//! let usage = vec![UsageEntry {
+//!     date: date(2024, 3, 15),
+//!     start_time: time(17, 0, 0, 0),
+//!     end_time: time(18, 0, 0, 0),
+//!     imported: BigDecimal::from(2),
+//!     exported: BigDecimal::from(0),
+//!     notes: None,
+//! }];
+//! let rates = DatedTouRates::from(TouRates {
+//!     super_off: None,
+//!     off: BigDecimal::from_str("0.0828").unwrap(),
+//!     mid: BigDecimal::from_str("0.1449").unwrap(),
+//!     peak: BigDecimal::from_str("0.1656").unwrap(),
+//! });
+//! let options = CostOptions::default();
+//! let cost = calculate_tou_cost(&rates, &TouSchedule::scl(), &options, usage.iter());
+//! assert_eq!(cost, BigDecimal::from_str("0.3312").unwrap());
+//! ```
+
+pub mod analysis;
+pub mod cache;
+pub mod comparison;
+pub mod currency;
+pub mod holidays;
+pub mod json;
+pub mod rate_calculator;
+pub mod rates;
+pub mod schedule;
+#[cfg(test)]
+mod synthetic;
+pub mod usage_data;
+pub mod usage_files;
+
+pub use comparison::{AnalysisResult, Verdict, analyze};
+pub use rate_calculator::{CostOptions, TimeOfUse, calculate_base_cost, calculate_tou_cost};
+pub use rates::{DatedTouRates, SeasonalTouRates, TouRates};
+pub use schedule::TouSchedule;
+pub use usage_data::{UsageEntry, read_usage_data, read_usage_data_from};
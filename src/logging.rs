@@ -0,0 +1,87 @@
+//! Controls how much is printed to stderr besides the results.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Only errors and the results.
+    Quiet,
+    /// Progress messages, warnings, and the totals.
+    Normal,
+    /// Also extra detail, like per-period subtotals.
+    Verbose,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Normal as u8);
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: LogLevel) -> bool {
+    LEVEL.load(Ordering::Relaxed) >= level as u8
+}
+
+/// Prints to stderr unless `--quiet` is given.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::LogLevel::Normal) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Prints to stderr only if `--verbose` is given.
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::LogLevel::Verbose) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use {info, verbose};
+
+/// A counter of how many usage entries have been read, updated in place on one line of stderr.
+pub struct Progress {
+    enabled: bool,
+    count: usize,
+    /// The length of the last line printed, so it can be cleared.
+    printed: usize,
+}
+
+impl Progress {
+    /// How many entries to read between updates.
+    const INTERVAL: usize = 5000;
+
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            count: 0,
+            printed: 0,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.count += 1;
+        if self.enabled && self.count.is_multiple_of(Self::INTERVAL) {
+            let line = format!("Read {} usage entries...", self.count);
+            eprint!("\r{}", line);
+            self.printed = line.len();
+        }
+    }
+
+    /// Clears the counter, so it doesn't get in the way of what's printed next.
+    pub fn finish(&mut self) {
+        if self.printed > 0 {
+            eprint!("\r{}\r", " ".repeat(self.printed));
+            self.printed = 0;
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
@@ -1,215 +1,1628 @@
-mod rate_calculator;
-mod usage_data;
+mod logging;
+mod output;
+mod report;
+mod warnings;
 
-use crate::rate_calculator::calculate_base_cost;
-use crate::usage_data::UsageEntry;
 use bigdecimal::BigDecimal;
-use clap::{Args, Parser, ValueEnum};
-use csv::StringRecord;
-use jiff::civil::Time;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Cursor, Read};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{
+    ArgGroup, ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+};
+use jiff::civil::{Date, Time};
+use logging::{LogLevel, Progress, info, verbose};
+use report::{
+    UsageSummary, print_details, print_explanation, print_location_ranking, print_only,
+    print_rates, print_result, print_sample, print_scenarios, write_per_entry_csv,
+    write_profile_csv,
+};
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
-use std::sync::LazyLock;
+use std::process::ExitCode;
+use ttmbuwyntcstr::analysis::{
+    CoverageChecker, IntervalLengthChecker, USUAL_INTERVAL_MINUTES, check_coverage,
+    check_interval_lengths, date_range, unpriced_rate_changes,
+};
+use ttmbuwyntcstr::cache::{UsageCache, cache_key};
+use ttmbuwyntcstr::comparison::{
+    AnalysisRates, Analyzer, ChargeOptions, DayExplanation, DetailOptions, PeakDayRanking,
+    SelfCheck, Verdict, compare_scenarios,
+};
+use ttmbuwyntcstr::currency::{CurrencyFormat, NegativeStyle};
+use ttmbuwyntcstr::holidays::{Holidays, parse_holiday_list};
+use ttmbuwyntcstr::rate_calculator::{DaySummary, NetMetering, Rounding};
+use ttmbuwyntcstr::rates::{CurrentRate, RatesConfig, RatesConfigError, TieredRate};
+use ttmbuwyntcstr::usage_data::ReadOptions;
+use ttmbuwyntcstr::usage_files::{UsageFiles, UsageFilter};
+use ttmbuwyntcstr::{
+    AnalysisResult, CostOptions, DatedTouRates, TimeOfUse, TouRates, TouSchedule, UsageEntry,
+};
+use warnings::{
+    print_read_report, warn_coverage_issues, warn_implausible_rates, warn_short_usage,
+    warn_stale_rates, warn_unexpected_intervals, warn_unpriced_rate_change, warn_usage,
+};
 
 /// Tool that might be used when you need to compare SCL TOU rates.
 /// Given your SCL usage data with its static KWH rate, and your TOU rates, calculates
 /// the total cost of your usage if you switched to the TOU rates.
 #[derive(Parser, Debug)]
-#[command(version, long_about)]
+#[command(
+    version,
+    long_about,
+    after_help = EXIT_STATUS_HELP,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Ttmbuwyntcstr {
-    /// CSV file with fine-grained data, exported using the "Green Button" in SCL.
-    /// It's under "View Usage" > "View Usage Details".
-    #[arg(long_help)]
-    usage_csv: PathBuf,
-    /// Your current static KWH rate, in dollars per KWH.
-    /// This can be found in your SCL bill.
-    #[arg(long_help)]
-    current_rate: BigDecimal,
+    #[command(subcommand)]
+    command: Option<Command>,
+    // Without a subcommand, the arguments are for `compare`.
+    #[command(flatten)]
+    compare: CompareArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compare the cost of your usage under your current rate and TOU rates. This is the default.
+    #[command(after_help = EXIT_STATUS_HELP)]
+    Compare(Box<CompareArgs>),
+    /// Print which TOU period a time of day falls in.
+    Classify(ClassifyArgs),
+    /// Compare the TOU cost of your usage under two sets of TOU rates, like this year's rates and a
+    /// proposed increase, without your current rate.
+    Scenarios(Box<ScenariosArgs>),
+    /// Print the TOU rates of every location in the plan and the TOU schedule, to check them
+    /// against the utility's published rates.
+    Rates(RatesArgs),
+}
+
+#[derive(Args, Debug)]
+struct CompareArgs {
+    #[command(flatten)]
+    usage: UsageArgs,
+    #[command(flatten)]
+    current_rate: CurrentRateInfo,
     #[command(flatten)]
     tou_rates: TouRateInfo,
+    /// The unit of the rates given on the command line, like `--current-rate 8.28` for 8.28 cents
+    /// per KWH. Rates in `--rates-file` are always in dollars, so this doesn't apply to them.
+    #[arg(long, value_enum, default_value_t = RateUnit::Dollars, long_help)]
+    rate_unit: RateUnit,
+    /// The rate exported energy is credited at, per KWH in `--rate-unit`.
+    /// By default, exports are credited at the same rate that imports are charged at.
+    #[arg(long, value_parser = parse_non_negative_amount, long_help)]
+    export_credit_rate: Option<BigDecimal>,
+    /// Ignore exported energy entirely, as if nothing had been exported, for plans where exports
+    /// earn no credit.
+    /// Unlike `--export-credit-rate 0`, exports don't offset the KWH used either, so the usage
+    /// and the TOU periods' shares of it are of imports only.
+    #[arg(
+        long,
+        conflicts_with_all = ["export_credit_rate", "net_metering", "solar_meter_fee"],
+        long_help
+    )]
+    ignore_exports: bool,
+    /// Net meter the usage, crediting excess exports at this rate, per KWH in `--rate-unit`.
+    /// Exports offset imports over each monthly billing period, and if a TOU period exports more
+    /// than it imports, the excess rolls forward to offset that TOU period's usage in later
+    /// billing periods. Only the excess left at the end of the usage data is credited, at this
+    /// rate, which should be SCL's avoided cost. This can make a big difference with solar.
+    #[arg(
+        long,
+        value_name = "EXCESS_CREDIT_RATE",
+        value_parser = parse_non_negative_amount,
+        long_help,
+        conflicts_with_all = [
+            "export_credit_rate",
+            "tier1_rate",
+            "round_per_entry",
+            "daily",
+            "by_day_type",
+            "compare_year_over_year",
+            "top_peak_days",
+            "per_entry_csv",
+            "shift_peak_to_off",
+            "shift_breakeven",
+            "by_period",
+        ]
+    )]
+    net_metering: Option<BigDecimal>,
+    /// The day of the month billing periods start on for net metering, demand charges, the
+    /// minimum bill and tiered rates, from 1 to 28.
+    #[arg(
+        long,
+        visible_alias = "cycle-start-day",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(i8).range(1..=28)
+    )]
+    billing_day: i8,
+    /// The fixed daily base service charge, in dollars per day.
+    /// It's charged for each day in the usage data, and added to both the current and TOU costs.
+    #[arg(long, value_parser = parse_non_negative_amount, long_help)]
+    base_charge_per_day: Option<BigDecimal>,
+    /// The minimum bill for each billing period, in dollars. A billing period whose cost under
+    /// either plan, including its fixed charges, comes to less is charged the minimum instead.
+    /// Billing periods start on `--billing-day`, so by default each calendar month is one. Every
+    /// billing period the usage data touches gets the whole minimum, even if the data only covers
+    /// a few days of it, so use `--from` and `--to` to cover whole billing periods.
+    #[arg(
+        long,
+        value_name = "DOLLARS",
+        value_parser = parse_positive_rate,
+        conflicts_with = "net_metering",
+        long_help
+    )]
+    minimum_bill: Option<BigDecimal>,
+    /// Net imports and exports once per billing period, like SCL's bills, instead of in each
+    /// usage entry.
+    /// Each billing period's imports are charged at their TOU period's rates, or the current rate,
+    /// and its exports are credited, and the two are netted at the end of the period. A period
+    /// whose credits exceed its charges costs nothing, and banks the excess credit toward later
+    /// periods. Any credit still banked at the end of the usage data is lost. Netting each entry
+    /// instead lets the excess lower the cost below zero, so it's the same unless exports exceed
+    /// imports in value over a billing period. The fixed charges aren't netted against credits.
+    #[arg(long, conflicts_with_all = ["net_metering", "minimum_bill"], long_help)]
+    no_export_netting: bool,
+    /// A monthly demand charge, for TOU plans that have one, in dollars per kW of the highest
+    /// demand in each billing period.
+    /// Demand is the average kW over a usage interval, so it's only as fine-grained as the usage
+    /// data. It's only added to the TOU cost.
+    #[arg(long, value_name = "DOLLARS_PER_KW", value_parser = parse_positive_rate, long_help)]
+    demand_charge_per_kw: Option<BigDecimal>,
+    /// A monthly fee for the net meter solar customers have, in dollars per billing period.
+    /// It's only charged if the usage data has exports, for every billing period the data touches,
+    /// and added to both the current and TOU costs.
+    #[arg(long, value_name = "DOLLARS", value_parser = parse_positive_rate, long_help)]
+    solar_meter_fee: Option<BigDecimal>,
+    /// A fixed amount billed each billing period instead of the usage, like a budget billing
+    /// quote, in dollars, to compare both plans against.
+    /// It's charged for every billing period the usage data touches, so use `--from` and `--to`
+    /// to cover whole billing periods.
+    #[arg(
+        long,
+        value_name = "DOLLARS",
+        value_parser = parse_positive_rate,
+        conflicts_with = "compare_all",
+        long_help
+    )]
+    fixed_monthly: Option<BigDecimal>,
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// Print the first N usage entries as they were read, with their TOU periods, and exit
+    /// without comparing costs.
+    /// This is useful to check that the usage CSV was read correctly.
+    #[arg(long, value_name = "N", long_help)]
+    sample: Option<usize>,
+    /// Print every usage entry on this date (YYYY-MM-DD) with its TOU period, rate, KWH and TOU
+    /// cost, and the day's totals, and exit without comparing costs.
+    /// This is useful to reconcile a day against SCL's detailed usage view.
+    #[arg(
+        long,
+        value_name = "DATE",
+        conflicts_with_all = ["compare_all", "net_metering", "sample"],
+        long_help
+    )]
+    explain: Option<Date>,
+    /// List the usage entries that have notes, which SCL uses to flag estimated or adjusted
+    /// readings.
+    #[arg(long)]
+    show_flagged: bool,
+    /// Print a chart of KWH used by hour of day to stderr, or with `--format json`, put each hour's
+    /// KWH in the summary.
+    #[arg(long)]
+    histogram: bool,
+    /// Print statistics of the net KWH of each usage entry: the minimum, maximum, mean, median
+    /// and 95th percentile, over all entries and the entries starting in each TOU period.
+    /// Spiky usage shows up as a 95th percentile or maximum well above the median.
+    #[arg(long, long_help)]
+    stats: bool,
+    /// Write the TOU cost of each usage entry to this CSV file.
+    /// Entries that span more than one TOU period get a row for each period, with their imported
+    /// and exported KWH both split by the time spent in each.
+    #[arg(long, long_help)]
+    per_entry_csv: Option<PathBuf>,
+    /// Write the average net KWH in each hour of the day to this CSV file, with a row for each
+    /// hour.
+    /// Each hour's average is its total KWH divided by the number of days with usage in that hour,
+    /// so it's the load shape of an average day.
+    #[arg(long, value_name = "PATH", long_help)]
+    profile_csv: Option<PathBuf>,
+    /// Print a table of the usage and cost under each plan for each day.
+    /// With a tiered current rate, each day's current cost is its share of the total by KWH.
+    #[arg(long, long_help)]
+    daily: bool,
+    /// Add the trailing moving average of each day's cost under each plan to `--daily`, averaged
+    /// over that day and the N-1 days before it, to smooth out day-to-day swings and see trends.
+    /// Days missing from the usage data are left out of the averages.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(i64).range(1..),
+        requires = "daily",
+        long_help
+    )]
+    smooth_days: Option<i64>,
+    /// Print a table of the usage and cost under each plan on weekdays and on weekends, which are
+    /// off-peak all day, to see how much of the difference comes from each.
+    /// With a tiered current rate, each group's current cost is its share of the total by KWH.
+    #[arg(long, long_help)]
+    by_day_type: bool,
+    /// Print a table of the usage and TOU cost in each month of the year, with a column for each
+    /// year the usage data covers, to see how they changed from one year to the next.
+    /// Months are calendar months, not billing periods.
+    #[arg(long, long_help)]
+    compare_year_over_year: bool,
+    /// Print the N days with the most peak usage, with their peak KWH and peak TOU cost, to see
+    /// which days to change habits on.
+    #[arg(long, value_name = "N", long_help)]
+    top_peak_days: Option<usize>,
+    /// What to rank the days by for `--top-peak-days`.
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = PeakDayRanking::Kwh,
+        requires = "top_peak_days"
+    )]
+    rank_peak_days_by: PeakDayRanking,
+    /// Find how much the TOU rates could uniformly rise before switching stops saving money.
+    #[arg(long)]
+    breakeven: bool,
+    /// Also estimate the savings over a full year, scaled from the dates the usage data covers.
+    #[arg(long)]
+    annualize: bool,
+    /// Also show the TOU cost if this fraction of peak usage were shifted to off-peak, e.g. `0.25`
+    /// to model running a quarter of your peak usage at night instead.
+    #[arg(long, value_parser = parse_fraction, long_help)]
+    shift_peak_to_off: Option<BigDecimal>,
+    /// Find the least peak usage that would have to be shifted to off-peak for TOU to cost no
+    /// more than your current rate, in KWH and as a percentage of your peak usage.
+    #[arg(long, long_help)]
+    shift_breakeven: bool,
+    /// Print a table comparing each TOU period's cost under TOU rates and under your current
+    /// rate, to see which periods make TOU cheaper or more expensive, along with the effective
+    /// rate per KWH each plan charges in the period.
+    /// With a tiered current rate, each period's current cost is its share of the total by KWH.
+    #[arg(long, long_help)]
+    by_period: bool,
+    /// Round the costs to whole cents with this rounding mode.
+    /// By default, totals keep their full precision and are only rounded for display.
+    #[arg(long, value_enum, long_help)]
+    rounding: Option<Rounding>,
+    /// Also round the cost of each usage entry before summing, like SCL rounds each line item.
+    /// This can change the totals by a few cents compared to only rounding the totals.
+    #[arg(long, requires = "rounding", long_help)]
+    round_per_entry: bool,
+    /// The currency symbol to show amounts with.
+    #[arg(long, default_value = "$")]
+    currency_symbol: String,
+    /// How to show negative amounts, like savings when switching costs more.
+    #[arg(long, value_enum, default_value_t = NegativeStyle::Sign)]
+    negative_style: NegativeStyle,
+    /// Only print errors and the final verdict.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Print only this value to stdout, as a plain number, and nothing else but errors, for
+    /// scripts to capture.
+    /// Costs are in dollars, and savings are negative when switching costs more.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "VALUE",
+        conflicts_with_all = [
+            "format",
+            "output",
+            "verbose",
+            "compare_all",
+            "sample",
+            "explain",
+            "show_flagged",
+            "histogram",
+            "stats",
+            "daily",
+            "by_day_type",
+            "compare_year_over_year",
+            "top_peak_days",
+            "by_period",
+            "breakeven",
+            "shift_peak_to_off",
+            "shift_breakeven",
+        ],
+        long_help
+    )]
+    only: Option<OnlyValue>,
+    /// How many decimal places to round the `--only` value to. By default it has its full
+    /// precision.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(i64).range(0..),
+        requires = "only"
+    )]
+    decimals: Option<i64>,
+    /// Also print extra detail, like the usage and cost in each TOU period.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Cache each usage file's daily usage by TOU period in this file, so later runs only read the
+    /// usage files that changed since.
+    /// The cache only has KWH, so it still applies when the rates change, but it's rebuilt when
+    /// the TOU schedule, holidays, `--usage-type`, `--skip-bad-rows` or `--date-format` change.
+    /// Usage read from standard input isn't cached. It can't be used with options that need every
+    /// usage entry.
+    #[arg(
+        long,
+        value_name = "PATH",
+        long_help,
+        conflicts_with_all = [
+            "sample",
+            "explain",
+            "show_flagged",
+            "histogram",
+            "stats",
+            "per_entry_csv",
+            "profile_csv",
+            "daily",
+            "by_day_type",
+            "compare_year_over_year",
+            "top_peak_days",
+            "breakeven",
+            "shift_peak_to_off",
+            "shift_breakeven",
+            "round_per_entry",
+            "demand_charge_per_kw",
+            "skip_excess_exports",
+        ]
+    )]
+    cache: Option<PathBuf>,
+    /// Ignore what's in `--cache` and rebuild it from the usage files.
+    #[arg(long, requires = "cache")]
+    rebuild_cache: bool,
+    /// After comparing, check that the results are consistent and fail if they aren't: that the
+    /// TOU periods' KWH add up to the total KWH, that their costs add up to the TOU cost worked out
+    /// entry by entry, and that the current cost of each period adds up to the current cost.
+    /// This is a way to double check a result that looks wrong.
+    #[arg(long, conflicts_with_all = ["cache", "compare_all"], long_help)]
+    self_check: bool,
+    /// How to output the results.
+    /// `text` prints a human-readable summary to stderr, `json` prints a JSON object to stdout,
+    /// unless `--output` is given. With `json`, the results of `--breakeven`, `--histogram`,
+    /// `--stats` and `--show-flagged` are fields of the JSON object.
+    /// The JSON object's `schema_version` is a "MAJOR.MINOR" string. The minor version goes up
+    /// when fields are added, and the major version goes up when fields are removed, renamed, or
+    /// change meaning, so consumers that know a major version can rely on its fields.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, long_help)]
+    format: OutputFormat,
+    /// Write the results to this file instead of stdout or stderr.
+    /// Warnings and progress, like how many usage entries were found, are still printed to stderr,
+    /// so they don't end up in the file.
+    #[arg(long, value_name = "PATH", long_help)]
+    output: Option<PathBuf>,
+}
+
+impl CompareArgs {
+    /// Whether the options need every usage entry kept in memory. A plain comparison only needs
+    /// totals, so it reads the usage in a single pass instead.
+    fn needs_all_entries(&self) -> bool {
+        self.sample.is_some()
+            || self.histogram
+            || self.stats
+            || self.per_entry_csv.is_some()
+            || self.profile_csv.is_some()
+            || self.daily
+            || self.by_day_type
+            || self.compare_year_over_year
+            || self.top_peak_days.is_some()
+            || self.breakeven
+            || self.shift_peak_to_off.is_some()
+            || self.shift_breakeven
+    }
+
+    /// What to work out besides the totals. A ranking of every location only has the details that
+    /// don't depend on the TOU rates.
+    fn detail_options(&self) -> DetailOptions {
+        let stats = DetailOptions {
+            histogram: self.histogram,
+            stats: self.stats,
+            ..DetailOptions::default()
+        };
+        if self.tou_rates.compare_all {
+            return stats;
+        }
+        DetailOptions {
+            daily: self.daily,
+            smooth_days: self.smooth_days,
+            by_day_type: self.by_day_type,
+            year_over_year: self.compare_year_over_year,
+            top_peak_days: self.top_peak_days,
+            rank_peak_days_by: self.rank_peak_days_by,
+            breakeven: self.breakeven,
+            shift_peak_to_off: self.shift_peak_to_off.clone(),
+            shift_breakeven: self.shift_breakeven,
+            fixed_monthly: self.fixed_monthly.clone(),
+            annualize: self.annualize,
+            ..stats
+        }
+    }
+}
+
+/// Which usage files to read and which of their entries to keep, shared by the subcommands that
+/// read usage.
+#[derive(Args, Debug)]
+struct UsageArgs {
+    /// CSV files with fine-grained data, exported using the "Green Button" in SCL.
+    /// It's under "View Usage" > "View Usage Details".
+    /// Give more than one file to analyze them together, e.g. one export per month.
+    /// Give `-` to read the CSV from standard input.
+    #[arg(required = true, num_args = 1.., long_help)]
+    usage_csv: Vec<PathBuf>,
+    /// The TYPE of usage row to read from the usage CSV, ignoring case.
+    #[arg(long, default_value = "Electric usage")]
+    usage_type: String,
+    /// Skip rows of the usage CSV that can't be parsed, with a warning, instead of failing.
+    #[arg(long)]
+    skip_bad_rows: bool,
+    /// The format of the usage CSV's DATE column, strftime-style, like `%m/%d/%Y` for 03/01/2024.
+    /// By default, ISO dates like 2024-03-01 and US-style dates like 03/01/2024 or 03/01/24 are
+    /// both accepted.
+    #[arg(long, value_name = "FORMAT", long_help)]
+    date_format: Option<String>,
+    /// Only include usage on or after this date (YYYY-MM-DD).
+    #[arg(long)]
+    from: Option<Date>,
+    /// Only include usage on or before this date (YYYY-MM-DD).
+    #[arg(long)]
+    to: Option<Date>,
+    /// The interval lengths the usage entries are expected to have, in minutes, separated by
+    /// commas. Entries of any other length are warned about, since they can mean rows were merged
+    /// or corrupted, which puts their usage in the wrong TOU periods.
+    #[arg(
+        long,
+        value_name = "MINUTES",
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(i64).range(1..),
+        default_values_t = USUAL_INTERVAL_MINUTES,
+        long_help
+    )]
+    interval_minutes: Vec<i64>,
+    /// The most a solar system could export, in KW. Usage entries that exported more than this
+    /// for their whole interval are warned about, since their export readings are likely corrupt.
+    /// The usage of files taken from `--cache` isn't checked again.
+    #[arg(long, value_name = "KW", value_parser = parse_positive_rate, long_help)]
+    max_system_kw: Option<BigDecimal>,
+    /// Skip the usage entries that exported more than `--max-system-kw` allows, instead of only
+    /// warning about them.
+    #[arg(long, requires = "max_system_kw")]
+    skip_excess_exports: bool,
+    /// Show a count of the usage entries read so far while reading the usage files.
+    /// This is on by default when stderr is a terminal, unless `--quiet` is given.
+    #[arg(long, long_help)]
+    progress: bool,
+}
+
+impl UsageArgs {
+    fn files(&self) -> UsageFiles {
+        UsageFiles {
+            paths: self.usage_csv.clone(),
+            options: ReadOptions {
+                usage_type: self.usage_type.clone(),
+                skip_bad_rows: self.skip_bad_rows,
+                date_format: self.date_format.clone(),
+            },
+            filter: UsageFilter {
+                from: self.from,
+                to: self.to,
+                max_system_kw: self.max_system_kw.clone(),
+                skip_excess_exports: self.skip_excess_exports,
+            },
+        }
+    }
+
+    fn check_date_range(&self) -> Result<(), String> {
+        match (self.from, self.to) {
+            (Some(from), Some(to)) if from > to => {
+                Err(format!("--from {} is after --to {}", from, to))
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
+/// Where the rates and TOU schedule come from, shared by every subcommand.
 #[derive(Args, Debug)]
-#[group(required = true)]
+struct ConfigArgs {
+    /// The plan to use, which has the locations and their TOU rates, the TOU schedule, and which
+    /// days are off-peak all day.
+    /// The built-in plan is `scl`. Other plans are JSON files named `<NAME>.json` in
+    /// `--plans-dir`, in the same format as `--rates-file`, but with only the locations they give.
+    /// A plan file can also set `"weekends_off_peak": false` and its `"holidays"`, either `"scl"`
+    /// or a list of dates. Anything else it leaves out is the same as SCL's.
+    /// A plan file elsewhere can be given by its path, or a `file://` URL.
+    #[arg(long, value_name = "NAME", default_value = "scl", long_help)]
+    plan: String,
+    /// The directory to look for plan files in.
+    /// Defaults to `ttmbuwyntcstr/plans` in `$XDG_CONFIG_HOME`, or in `~/.config` if that isn't
+    /// set.
+    #[arg(long, value_name = "DIR", long_help)]
+    plans_dir: Option<PathBuf>,
+    /// A JSON file with per-location TOU rates, layered over the `--plan`'s rates.
+    /// It looks like `{ "locations": { "seattle": { "off": 0.0828, "mid": 0.1449, "peak": 0.1656 } } }`,
+    /// and any location or rate left out keeps its built-in value.
+    /// Its rates are always in dollars per KWH, even with `--rate-unit cents`.
+    /// It can also replace the TOU schedule, with weekday time ranges for each period, e.g.
+    /// `{ "schedule": { "off": ["00:00-06:00"], "mid": ["06:00-17:00", "21:00-24:00"], "peak": ["17:00-21:00"] } }`.
+    /// The ranges can start and end on any minute, like `17:30`.
+    /// Plans with a super off-peak period can give it as `super_off` in both the schedule and the
+    /// rates. Plans with only off-peak and peak periods can leave `mid` out of both.
+    /// A location can have different summer rates in a nested `summer` object, and
+    /// `"summer_months": [5, 9]` sets which months are summer, May through September by default.
+    /// Rates that change on a date go in `rate_changes`, e.g.
+    /// `{ "rate_changes": [{ "date": "2025-01-01", "locations": { "seattle": { "peak": 0.1756 } } }] }`.
+    #[arg(long, long_help)]
+    rates_file: Option<PathBuf>,
+    /// Comma-separated list of holiday dates (YYYY-MM-DD) on which off-peak rates apply all day.
+    /// Defaults to the standard SCL observed holidays: New Year's Day, Memorial Day,
+    /// Independence Day, Labor Day, Thanksgiving Day, and Christmas Day.
+    #[arg(long, value_delimiter = ',', long_help)]
+    holidays: Option<Vec<Date>>,
+    /// A file of holiday dates on which off-peak rates apply all day, added to the plan's
+    /// holidays, which are the SCL observed holidays unless the plan gives its own.
+    /// The file has one YYYY-MM-DD date per line, with blank lines and lines starting with `#`
+    /// ignored, or is a JSON array of YYYY-MM-DD strings.
+    #[arg(long, value_name = "PATH", conflicts_with = "holidays", long_help)]
+    holidays_file: Option<PathBuf>,
+    /// Use only the holidays in `--holidays-file`, instead of adding them to the plan's.
+    #[arg(long, requires = "holidays_file")]
+    replace_holidays: bool,
+}
+
+#[derive(Args, Debug)]
+struct ClassifyArgs {
+    /// The time of day to classify, as HH:MM.
+    time: Time,
+    /// The date to classify the time on (YYYY-MM-DD), since weekends and holidays are off-peak
+    /// all day. By default, the time is classified as on a weekday.
+    #[arg(long, long_help)]
+    date: Option<Date>,
+    #[command(flatten)]
+    config: ConfigArgs,
+}
+
+#[derive(Args, Debug)]
+struct RatesArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// The currency symbol to show rates with.
+    #[arg(long, default_value = "$")]
+    currency_symbol: String,
+}
+
+#[derive(Args, Debug)]
+struct ScenariosArgs {
+    #[command(flatten)]
+    usage: UsageArgs,
+    /// The TOU rates of scenario A: either a location in the `--plan`, or the off-peak, mid-peak
+    /// and peak rates separated by commas, per KWH in `--rate-unit`, like `0.0828,0.1449,0.1656`.
+    /// Give a fourth rate first for plans with a super off-peak period, like
+    /// `0.05,0.0828,0.1449,0.1656`.
+    #[arg(long, value_name = "RATES", long_help)]
+    scenario_a: String,
+    /// The TOU rates of scenario B, given the same way as `--scenario-a`.
+    #[arg(long, value_name = "RATES")]
+    scenario_b: String,
+    /// A name to show scenario A's results with, like `2025`.
+    #[arg(long, default_value = "A")]
+    label_a: String,
+    /// A name to show scenario B's results with, like `proposed`.
+    #[arg(long, default_value = "B")]
+    label_b: String,
+    /// The unit of the rates given on the command line.
+    #[arg(long, value_enum, default_value_t = RateUnit::Dollars)]
+    rate_unit: RateUnit,
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// The currency symbol to show amounts with.
+    #[arg(long, default_value = "$")]
+    currency_symbol: String,
+    /// How to output the results, as for `compare`.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    /// Write the results to this file instead of stdout or stderr, as for `compare`.
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+const EXIT_STATUS_HELP: &str = "\
+Exit status:
+  0  Switching to TOU rates would save money
+  1  Switching to TOU rates would cost more
+  2  Switching to TOU rates would cost the same
+  3  An error occurred";
+
+/// The exit status that reports `verdict`.
+fn exit_code(verdict: Verdict) -> ExitCode {
+    ExitCode::from(match verdict {
+        Verdict::Saves => 0,
+        Verdict::CostsMore => 1,
+        Verdict::Same => 2,
+    })
+}
+
+const EXIT_ERROR: u8 = 3;
+
+#[derive(ValueEnum, Copy, Clone, PartialEq, Eq, Debug)]
+enum RateUnit {
+    Dollars,
+    Cents,
+}
+
+impl RateUnit {
+    /// Converts a rate given in this unit to dollars, which every calculation uses.
+    fn to_dollars(self, rate: &BigDecimal) -> BigDecimal {
+        match self {
+            RateUnit::Dollars => rate.clone(),
+            RateUnit::Cents => rate / BigDecimal::from(100),
+        }
+    }
+}
+
+#[derive(ValueEnum, Copy, Clone, PartialEq, Eq, Debug)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// The values `--only` can print.
+#[derive(ValueEnum, Copy, Clone, PartialEq, Eq, Debug)]
+enum OnlyValue {
+    CurrentCost,
+    TouCost,
+    Savings,
+    TotalKwh,
+}
+
+/// Whether the value of the option `id` came from its environment variable.
+fn from_env(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::EnvVariable)
+}
+
+fn conflict_error(argument: &str, other: &str) -> clap::Error {
+    Ttmbuwyntcstr::command().error(
+        ErrorKind::ArgumentConflict,
+        format!(
+            "the argument '{}' cannot be used with '{}'",
+            argument, other
+        ),
+    )
+}
+
+#[derive(Args, Debug)]
+#[group(required = true, multiple = true)]
+struct CurrentRateInfo {
+    /// Your current static KWH rate, per KWH in `--rate-unit`.
+    /// This can be found in your SCL bill.
+    /// You must specify this or the tiered rate. A tiered rate on the command line takes
+    /// precedence over `SCL_CURRENT_RATE`.
+    #[arg(
+        short,
+        long,
+        env = "SCL_CURRENT_RATE",
+        value_parser = parse_positive_rate,
+        long_help
+    )]
+    current_rate: Option<BigDecimal>,
+    /// Your current rate for the first block of KWH in each billing period, per KWH in
+    /// `--rate-unit`.
+    /// The tiers start over in each billing period, which start on `--billing-day` of each month,
+    /// so use `--from` and `--to` to cover whole billing periods.
+    #[arg(
+        long,
+        value_parser = parse_positive_rate,
+        requires_all = ["tier1_limit", "tier2_rate"],
+        long_help
+    )]
+    tier1_rate: Option<BigDecimal>,
+    /// How many KWH in the billing period are charged at `--tier1-rate`.
+    #[arg(long, value_parser = parse_non_negative_amount, requires = "tier1_rate")]
+    tier1_limit: Option<BigDecimal>,
+    /// Your current rate for KWH over `--tier1-limit`, per KWH in `--rate-unit`.
+    #[arg(long, value_parser = parse_positive_rate, requires = "tier1_rate")]
+    tier2_rate: Option<BigDecimal>,
+}
+
+impl CurrentRateInfo {
+    /// Drops a current rate from `SCL_CURRENT_RATE` if a tiered rate is given on the command line,
+    /// and otherwise checks that they aren't both given. clap can't tell the two apart when
+    /// checking conflicts.
+    fn resolve_env(&mut self, matches: &ArgMatches) -> Result<(), clap::Error> {
+        if self.current_rate.is_none() || self.tier1_rate.is_none() {
+            return Ok(());
+        }
+        if from_env(matches, "current_rate") {
+            self.current_rate = None;
+            return Ok(());
+        }
+        Err(conflict_error("--current-rate", "--tier1-rate"))
+    }
+}
+
+#[derive(Args, Debug)]
+#[group(required = true, multiple = true)]
+#[command(group(
+    ArgGroup::new("manual_tou_rates")
+        .multiple(true)
+        .args(["tou_rate_list", "off_peak_rate"])
+))]
 struct TouRateInfo {
-    /// Your location, used to determine the TOU rates.
-    /// You must specify this or the individual TOU rates.
+    /// Your location in the `--plan`, used to determine the TOU rates.
+    /// You must specify this or the TOU rates.
+    /// The SCL plan's locations are seattle, lake-forest-park, normandy-park, tukwila, renton, and
+    /// other (short for "Burien, SeaTac, Shoreline, Uninc. King County").
     /// See https://www.seattle.gov/city-light/residential-services/billing-information/time-of-use.
-    #[arg(short = 'l', long, value_enum, conflicts_with = "tou_rates")]
-    tou_location: Option<TouLocation>,
-    #[arg(short, long, group = "tou_rates", long_help = tou_rate_help("off-peak"))]
+    #[arg(short = 'l', long, conflicts_with = "tou_rate_list", long_help)]
+    tou_location: Option<String>,
+    /// Compare the TOU rates of every location in the `--plan` instead of a single one, ranked by
+    /// cost.
+    #[arg(long, conflicts_with_all = ["tou_location", "tou_rate_list"])]
+    compare_all: bool,
+    /// Your off-peak, mid-peak and peak TOU rates separated by commas, per KWH in `--rate-unit`,
+    /// like `0.0828,0.1449,0.1656`, instead of giving each with its own option.
+    #[arg(
+        long = "tou-rates",
+        value_name = "OFF,MID,PEAK",
+        value_parser = parse_tou_rate_list,
+        long_help
+    )]
+    tou_rate_list: Option<[BigDecimal; 3]>,
+    #[arg(
+        short,
+        long,
+        env = "SCL_OFF_PEAK_RATE",
+        value_parser = parse_positive_rate,
+        requires = "peak_rate",
+        long_help = tou_rate_help("off-peak")
+    )]
     off_peak_rate: Option<BigDecimal>,
-    #[arg(short, long, group = "tou_rates", long_help = tou_rate_help("mid-peak"))]
+    #[arg(
+        short,
+        long,
+        env = "SCL_MID_PEAK_RATE",
+        value_parser = parse_positive_rate,
+        requires_all = ["off_peak_rate", "peak_rate"],
+        required_unless_present_any = [
+            "tou_location",
+            "compare_all",
+            "tou_rate_list",
+            "plan",
+            "rates_file",
+        ],
+        long_help = tou_rate_help("mid-peak")
+    )]
     mid_peak_rate: Option<BigDecimal>,
-    #[arg(short, long, group = "tou_rates", long_help = tou_rate_help("peak"))]
+    #[arg(
+        short,
+        long,
+        env = "SCL_PEAK_RATE",
+        value_parser = parse_positive_rate,
+        requires = "off_peak_rate",
+        long_help = tou_rate_help("peak")
+    )]
     peak_rate: Option<BigDecimal>,
+    /// Your super off-peak TOU rate, per KWH in `--rate-unit`, for plans with a super off-peak
+    /// period. The TOU schedule needs a `super_off` period for it to apply, which can be given in
+    /// `--rates-file`.
+    #[arg(
+        long,
+        value_parser = parse_positive_rate,
+        requires = "manual_tou_rates",
+        conflicts_with_all = ["tou_location", "compare_all"],
+        long_help
+    )]
+    super_off_peak_rate: Option<BigDecimal>,
+}
+
+impl TouRateInfo {
+    /// Drops the TOU rates from their environment variables if the TOU rates are given another way
+    /// on the command line. clap can't tell rates from the command line and the environment apart
+    /// when checking conflicts, so this checks them instead.
+    fn resolve_env(&mut self, matches: &ArgMatches) -> Result<(), clap::Error> {
+        let other = if self.tou_location.is_some() {
+            "--tou-location"
+        } else if self.compare_all {
+            "--compare-all"
+        } else if self.tou_rate_list.is_some() {
+            "--tou-rates"
+        } else {
+            return Ok(());
+        };
+        let rates = [
+            ("off_peak_rate", "--off-peak-rate", &mut self.off_peak_rate),
+            ("mid_peak_rate", "--mid-peak-rate", &mut self.mid_peak_rate),
+            ("peak_rate", "--peak-rate", &mut self.peak_rate),
+        ];
+        for (id, flag, rate) in rates {
+            if rate.is_some() {
+                if !from_env(matches, id) {
+                    return Err(conflict_error(other, flag));
+                }
+                *rate = None;
+            }
+        }
+        Ok(())
+    }
 }
 
 fn tou_rate_help(peak: &str) -> String {
     format!(
-        "Your {} TOU rates, in dollars per KWH. \
+        "Your {} TOU rates, per KWH in `--rate-unit`. \
          Typically you can just give your location with `--tou-location` and the program will use \
-         its built-in rates. However, if SCL has changed their rates, you need to specify them \
-         manually.",
+         its built-in rates. However, if the rates have changed since, you need to specify them \
+         manually. \
+         The off-peak, mid-peak and peak rates are all needed to give the rates manually. \
+         Only a `--plan` or `--rates-file` whose schedule has no mid-peak period can leave out the \
+         mid-peak rate. \
+         The rate can also be given in its environment variable, but TOU rates given on the \
+         command line, including with `--tou-location`, take precedence over it.",
         peak
     )
 }
 
-#[derive(ValueEnum, Copy, Clone, PartialEq, Eq, Debug)]
-enum TouLocation {
-    Seattle,
-    LakeForestPark,
-    NormandyPark,
-    Tukwila,
-    Renton,
-    /// Short for "Burien, SeaTac, Shoreline, Uninc. King County".
-    Other,
-}
-
-#[derive(Debug, Clone)]
-struct TouRates {
-    pub off: BigDecimal,
-    pub mid: BigDecimal,
-    pub peak: BigDecimal,
-}
-
-impl TouRates {
-    fn from_args(args: &Ttmbuwyntcstr) -> Self {
-        if let Some(location) = args.tou_rates.tou_location {
-            match location {
-                TouLocation::Seattle => Self {
-                    off: BigDecimal::from_str("0.0828").unwrap(),
-                    mid: BigDecimal::from_str("0.1449").unwrap(),
-                    peak: BigDecimal::from_str("0.1656").unwrap(),
-                },
-                TouLocation::LakeForestPark => Self {
-                    off: BigDecimal::from_str("0.0895").unwrap(),
-                    mid: BigDecimal::from_str("0.1565").unwrap(),
-                    peak: BigDecimal::from_str("0.1789").unwrap(),
-                },
-                TouLocation::NormandyPark => Self {
-                    off: BigDecimal::from_str("0.0881").unwrap(),
-                    mid: BigDecimal::from_str("0.1541").unwrap(),
-                    peak: BigDecimal::from_str("0.1762").unwrap(),
-                },
-                TouLocation::Tukwila => Self {
-                    off: BigDecimal::from_str("0.0886").unwrap(),
-                    mid: BigDecimal::from_str("0.1551").unwrap(),
-                    peak: BigDecimal::from_str("0.1773").unwrap(),
-                },
-                TouLocation::Renton => Self {
-                    off: BigDecimal::from_str("0.0828").unwrap(),
-                    mid: BigDecimal::from_str("0.1449").unwrap(),
-                    peak: BigDecimal::from_str("0.1656").unwrap(),
-                },
-                TouLocation::Other => Self {
-                    off: BigDecimal::from_str("0.0894").unwrap(),
-                    mid: BigDecimal::from_str("0.1565").unwrap(),
-                    peak: BigDecimal::from_str("0.1788").unwrap(),
-                },
-            }
-        } else {
-            Self {
-                off: args
-                    .tou_rates
-                    .off_peak_rate
-                    .clone()
-                    .expect("off-peak rate is required"),
-                mid: args
-                    .tou_rates
-                    .mid_peak_rate
-                    .clone()
-                    .expect("mid-peak rate is required"),
-                peak: args
-                    .tou_rates
-                    .peak_rate
-                    .clone()
-                    .expect("peak rate is required"),
-            }
+fn parse_positive_rate(s: &str) -> Result<BigDecimal, String> {
+    match s.parse::<BigDecimal>() {
+        Ok(rate) if rate > BigDecimal::from(0) => Ok(rate),
+        _ => Err(format!("rate must be a positive amount, got {:?}", s)),
+    }
+}
+
+/// Like [parse_positive_rate], but also takes zero, for credits and amounts where zero means
+/// there's none, like `--export-credit-rate 0` for exports that earn nothing.
+fn parse_non_negative_amount(s: &str) -> Result<BigDecimal, String> {
+    match s.parse::<BigDecimal>() {
+        Ok(amount) if amount >= BigDecimal::from(0) => Ok(amount),
+        _ => Err(format!("expected zero or a positive amount, got {:?}", s)),
+    }
+}
+
+fn parse_tou_rate_list(s: &str) -> Result<[BigDecimal; 3], String> {
+    let rates: Vec<BigDecimal> = s
+        .split(',')
+        .map(|rate| parse_positive_rate(rate.trim()))
+        .collect::<Result<_, _>>()?;
+    <[BigDecimal; 3]>::try_from(rates).map_err(|rates| {
+        format!(
+            "expected off-peak, mid-peak and peak rates separated by commas, like 0.0828,0.1449,0.1656, got {} rates",
+            rates.len()
+        )
+    })
+}
+
+fn parse_fraction(s: &str) -> Result<BigDecimal, String> {
+    match s.parse::<BigDecimal>() {
+        Ok(fraction) if fraction >= BigDecimal::from(0) && fraction <= BigDecimal::from(1) => {
+            Ok(fraction)
         }
+        _ => Err(format!("expected a fraction from 0 to 1, got {:?}", s)),
     }
 }
 
-fn main() {
-    let args = Ttmbuwyntcstr::parse();
+fn tou_rates_from_args(args: &CompareArgs, config: &RatesConfig) -> Result<DatedTouRates, String> {
+    if let Some(location) = &args.tou_rates.tou_location {
+        config.rates(location).cloned().ok_or_else(|| {
+            let known: Vec<&str> = config.location_names().collect();
+            format!(
+                "Plan {:?} has no location {:?}, expected one of: {}",
+                args.config.plan,
+                location,
+                known.join(", ")
+            )
+        })
+    } else {
+        let rates = &args.tou_rates;
+        let to_dollars = |rate: &BigDecimal| args.rate_unit.to_dollars(rate);
+        let super_off = rates.super_off_peak_rate.as_ref().map(to_dollars);
+        if let Some([off, mid, peak]) = &rates.tou_rate_list {
+            return Ok(DatedTouRates::from(TouRates {
+                super_off,
+                off: to_dollars(off),
+                mid: to_dollars(mid),
+                peak: to_dollars(peak),
+            }));
+        }
+        let off = to_dollars(
+            rates
+                .off_peak_rate
+                .as_ref()
+                .expect("off-peak rate is required"),
+        );
+        // Plans without a mid-peak period don't need a mid-peak rate.
+        let mid = match &rates.mid_peak_rate {
+            Some(mid) => to_dollars(mid),
+            None if !config.schedule().has_period(TimeOfUse::Mid) => off.clone(),
+            None => {
+                return Err(
+                    "the TOU schedule has a mid-peak period, so --mid-peak-rate is required"
+                        .to_string(),
+                );
+            }
+        };
+        Ok(DatedTouRates::from(TouRates {
+            super_off,
+            off,
+            mid,
+            peak: to_dollars(rates.peak_rate.as_ref().expect("peak rate is required")),
+        }))
+    }
+}
 
-    let tou_rates = TouRates::from_args(&args);
-    let usage_data = read_usage_data(&args.usage_csv);
-    eprintln!("Found {} usage entries", usage_data.len());
-    let total_kwh: BigDecimal = usage_data.iter().map(|entry| entry.kwh_total()).sum();
-    eprintln!("Total KWH used: {:.2}", total_kwh);
-    let current_cost = calculate_base_cost(&args.current_rate, usage_data.iter());
-    eprintln!("Current cost: ${:.2}", current_cost);
-    let tou_cost = rate_calculator::calculate_tou_cost(&tou_rates, usage_data.iter());
-    eprintln!("TOU cost: ${:.2}", tou_cost);
-    if tou_cost < current_cost {
-        eprintln!(
-            "You would save ${:.2} by switching to TOU rates!",
-            current_cost - tou_cost
+fn current_rate_from_args(args: &CompareArgs) -> CurrentRate {
+    let rate = &args.current_rate;
+    let unit = args.rate_unit;
+    match (&rate.current_rate, &rate.tier1_rate) {
+        (Some(current_rate), _) => CurrentRate::Flat(unit.to_dollars(current_rate)),
+        (None, Some(tier1_rate)) => CurrentRate::Tiered(TieredRate {
+            tier1_rate: unit.to_dollars(tier1_rate),
+            tier1_limit: rate.tier1_limit.clone().expect("tier 1 limit is required"),
+            tier2_rate: unit.to_dollars(rate.tier2_rate.as_ref().expect("tier 2 rate is required")),
+            billing_day: args.billing_day,
+        }),
+        (None, None) => unreachable!("a current rate is required"),
+    }
+}
+
+/// Reads the usage files, passing each usage entry that's kept to `visit` as [UsageFiles::read]
+/// does, with progress shown while they're read and what was dropped reported once they have been.
+fn read_usage_files(usage: &UsageArgs, visit: impl FnMut(UsageEntry)) -> Result<(), String> {
+    let files = usage.files();
+    let mut progress = usage_progress(usage);
+    let report = files
+        .read(|| progress.tick(), visit)
+        .map_err(|e| e.to_string())?;
+    progress.finish();
+    print_read_report(&report, usage, &files.options);
+    Ok(())
+}
+
+/// Like [read_usage_files], but reads the usage a day at a time with [UsageFiles::read_cached],
+/// using and updating the cache at `cache_path`.
+fn read_cached_usage_files(
+    args: &CompareArgs,
+    schedule: &TouSchedule,
+    cache_path: &Path,
+    check: impl FnMut(&UsageEntry),
+    visit: impl FnMut(&DaySummary),
+) -> Result<(), String> {
+    let files = args.usage.files();
+    let key = cache_key(schedule, &files.options);
+    let mut cache = if args.rebuild_cache {
+        UsageCache::new(key)
+    } else {
+        UsageCache::load(cache_path, key.clone()).unwrap_or_else(|e| {
+            info!(
+                "Warning: ignoring the cache in {}: {}",
+                cache_path.display(),
+                e
+            );
+            UsageCache::new(key)
+        })
+    };
+    let mut progress = usage_progress(&args.usage);
+    let report = files
+        .read_cached(schedule, &mut cache, || progress.tick(), check, visit)
+        .map_err(|e| e.to_string())?;
+    progress.finish();
+    if report.cached_files > 0 {
+        verbose!(
+            "Took {} of the {} usage files from the cache",
+            report.cached_files,
+            files.paths.len()
         );
-    } else if tou_cost > current_cost {
-        eprintln!(
-            "You would pay ${:.2} more by switching to TOU rates!",
-            tou_cost - current_cost
+    }
+    cache.remove_missing();
+    if let Err(e) = cache.save(cache_path) {
+        info!(
+            "Warning: failed to write the cache to {}: {}",
+            cache_path.display(),
+            e
         );
+    }
+    print_read_report(&report, &args.usage, &files.options);
+    Ok(())
+}
+
+fn usage_progress(usage: &UsageArgs) -> Progress {
+    let shown_by_default = logging::enabled(LogLevel::Normal) && std::io::stderr().is_terminal();
+    Progress::new(usage.progress || shown_by_default)
+}
+
+/// Parses the command line. The rates can also be given in the `SCL_*_RATE` environment variables,
+/// which take precedence over the built-in rates but not over rates given on the command line.
+fn parse_args() -> Result<Ttmbuwyntcstr, clap::Error> {
+    let matches = Ttmbuwyntcstr::command().try_get_matches()?;
+    let mut args = Ttmbuwyntcstr::from_arg_matches(&matches)?;
+    let (compare_args, compare_matches) = match &mut args.command {
+        Some(Command::Compare(compare_args)) => (
+            &mut **compare_args,
+            matches
+                .subcommand_matches("compare")
+                .expect("compare was given"),
+        ),
+        Some(_) => return Ok(args),
+        None => (&mut args.compare, &matches),
+    };
+    compare_args.current_rate.resolve_env(compare_matches)?;
+    compare_args.tou_rates.resolve_env(compare_matches)?;
+    Ok(args)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        // Help and version output exit successfully.
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            let _ = e.print();
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    let exit_code = match &args.command {
+        Some(Command::Compare(compare_args)) => compare(compare_args),
+        Some(Command::Classify(classify_args)) => classify(classify_args),
+        Some(Command::Rates(rates_args)) => show_rates(rates_args),
+        Some(Command::Scenarios(scenarios_args)) => match scenarios(scenarios_args) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                ExitCode::from(EXIT_ERROR)
+            }
+        },
+        None => compare(&args.compare),
+    };
+    if let Err(e) = output::finish() {
+        eprintln!("Error: failed to write the output: {}", e);
+        return ExitCode::from(EXIT_ERROR);
+    }
+    exit_code
+}
+
+/// Sends the results to the file at `path` if it's given, or else to the standard stream for
+/// `format`.
+fn open_output(path: Option<&Path>, format: OutputFormat) -> Result<(), String> {
+    let stream = match format {
+        OutputFormat::Text => output::Stream::Stderr,
+        OutputFormat::Json => output::Stream::Stdout,
+    };
+    output::init(path, stream).map_err(|e| match path {
+        Some(path) => format!("failed to create {}: {}", path.display(), e),
+        None => e.to_string(),
+    })
+}
+
+/// Loads the plan's rates with the rates file layered over them, and the TOU schedule with any
+/// custom holidays.
+fn load_config(config: &ConfigArgs) -> Result<(RatesConfig, TouSchedule), String> {
+    let plans_dir = config.plans_dir.clone().or_else(default_plans_dir);
+    let mut rates_config =
+        RatesConfig::load_plan(&config.plan, plans_dir.as_deref()).map_err(|e| match e {
+            RatesConfigError::UnknownPlan { .. } => e.to_string(),
+            e => format!("plan {:?}: {}", config.plan, e),
+        })?;
+    if let Some(rates_file) = &config.rates_file {
+        rates_config
+            .merge_file(rates_file)
+            .map_err(|e| format!("{}: {}", rates_file.display(), e))?;
+    }
+    let mut schedule = rates_config.schedule().clone();
+    if let Some(dates) = &config.holidays {
+        schedule.holidays = Holidays::Custom(dates.iter().copied().collect());
+    }
+    if let Some(path) = &config.holidays_file {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let dates = parse_holiday_list(&contents).map_err(|errors| {
+            format!(
+                "{} has invalid holidays: {}",
+                path.display(),
+                errors.join("; ")
+            )
+        })?;
+        schedule.holidays = if config.replace_holidays {
+            Holidays::Custom(dates)
+        } else {
+            schedule.holidays.with(dates)
+        };
+    }
+    Ok((rates_config, schedule))
+}
+
+/// Where plan files are looked for when `--plans-dir` isn't given.
+fn default_plans_dir() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("ttmbuwyntcstr").join("plans"))
+}
+
+fn classify(args: &ClassifyArgs) -> ExitCode {
+    let (_, schedule) = match load_config(&args.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    let tou = match args.date {
+        Some(date) => TimeOfUse::from_date_time(date, args.time, &schedule),
+        None => TimeOfUse::from_time(args.time, &schedule),
+    };
+    println!("{:?}", tou);
+    ExitCode::SUCCESS
+}
+
+fn show_rates(args: &RatesArgs) -> ExitCode {
+    let (rates_config, schedule) = match load_config(&args.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    let currency = CurrencyFormat {
+        symbol: args.currency_symbol.clone(),
+        negative: NegativeStyle::Sign,
+    };
+    print_rates(&rates_config, &schedule, &currency);
+    ExitCode::SUCCESS
+}
+
+/// The TOU rates of a scenario, given as a location in the plan or as comma-separated off-peak,
+/// mid-peak and peak rates in `unit`.
+fn scenario_rates(
+    scenario: &str,
+    unit: RateUnit,
+    plan: &str,
+    config: &RatesConfig,
+) -> Result<DatedTouRates, String> {
+    if let Some(rates) = config.rates(scenario) {
+        return Ok(rates.clone());
+    }
+    if !scenario.contains(',') {
+        let known: Vec<&str> = config.location_names().collect();
+        return Err(format!(
+            "Plan {:?} has no location {:?}, expected one of: {}, or off-peak, mid-peak and peak rates like 0.0828,0.1449,0.1656",
+            plan,
+            scenario,
+            known.join(", ")
+        ));
+    }
+    let rates: Vec<BigDecimal> = scenario
+        .split(',')
+        .map(|rate| parse_positive_rate(rate.trim()).map(|rate| unit.to_dollars(&rate)))
+        .collect::<Result<_, _>>()?;
+    match rates[..] {
+        [ref off, ref peak] if !config.schedule().has_period(TimeOfUse::Mid) => Ok(TouRates {
+            super_off: None,
+            off: off.clone(),
+            mid: off.clone(),
+            peak: peak.clone(),
+        }
+        .into()),
+        [ref off, ref mid, ref peak] => Ok(TouRates {
+            super_off: None,
+            off: off.clone(),
+            mid: mid.clone(),
+            peak: peak.clone(),
+        }
+        .into()),
+        [ref super_off, ref off, ref mid, ref peak] => Ok(TouRates {
+            super_off: Some(super_off.clone()),
+            off: off.clone(),
+            mid: mid.clone(),
+            peak: peak.clone(),
+        }
+        .into()),
+        _ => Err(format!(
+            "expected off-peak, mid-peak and peak rates, optionally after a super off-peak rate, got {} rates in {:?}",
+            rates.len(),
+            scenario
+        )),
+    }
+}
+
+fn scenarios(args: &ScenariosArgs) -> Result<(), String> {
+    args.usage.check_date_range()?;
+    open_output(args.output.as_deref(), args.format)?;
+    let (rates_config, schedule) = load_config(&args.config)?;
+    let plan = &args.config.plan;
+    let a_rates = scenario_rates(&args.scenario_a, args.rate_unit, plan, &rates_config)
+        .map_err(|e| format!("--scenario-a: {}", e))?;
+    let b_rates = scenario_rates(&args.scenario_b, args.rate_unit, plan, &rates_config)
+        .map_err(|e| format!("--scenario-b: {}", e))?;
+    let mut usage_data: Vec<UsageEntry> = Vec::new();
+    read_usage_files(&args.usage, |entry| usage_data.push(entry))?;
+    warn_coverage_issues(&check_coverage(&usage_data));
+    warn_unexpected_intervals(&check_interval_lengths(
+        &usage_data,
+        &args.usage.interval_minutes,
+    ));
+    // Scenarios given as rates are the user's own, so only the plan's locations can be stale.
+    let locations = [&args.scenario_a, &args.scenario_b]
+        .into_iter()
+        .filter(|scenario| rates_config.rates(scenario).is_some())
+        .map(String::as_str);
+    warn_stale_rates(
+        plan,
+        &rates_config,
+        locations,
+        date_range(usage_data.iter()),
+    );
+    let comparison = compare_scenarios(
+        &usage_data,
+        &a_rates,
+        &b_rates,
+        &schedule,
+        &CostOptions::default(),
+    );
+    let total_kwh: BigDecimal = usage_data.iter().map(|entry| entry.kwh_total()).sum();
+    let currency = CurrencyFormat {
+        symbol: args.currency_symbol.clone(),
+        negative: NegativeStyle::Sign,
+    };
+    print_scenarios(args, &comparison, total_kwh, &currency);
+    Ok(())
+}
+
+fn compare(args: &CompareArgs) -> ExitCode {
+    logging::set_level(if args.quiet || args.only.is_some() {
+        LogLevel::Quiet
+    } else if args.verbose {
+        LogLevel::Verbose
     } else {
-        eprintln!("You would pay the same amount with TOU rates. Try another bill?");
-    }
-}
-
-static EXPECTED_HEADERS: LazyLock<StringRecord> = LazyLock::new(|| {
-    StringRecord::from(vec![
-        "TYPE",
-        "DATE",
-        "START TIME",
-        "END TIME",
-        "IMPORT (kWh)",
-        "EXPORT (kWh)",
-        "NOTES",
-    ])
-});
-
-fn read_usage_data(usage_csv: &Path) -> Vec<UsageEntry> {
-    // Annoyingly, the usage CSV comes with extra rows at the start that don't mean anything,
-    // so we need to skip them.
-    let mut reader = BufReader::new(File::open(usage_csv).expect("Usage file not found"));
-    let mut line_buf = String::new();
-    loop {
-        line_buf.clear();
-        if reader
-            .read_line(&mut line_buf)
-            .expect("Failed to read line")
-            == 0
-        {
-            panic!("Usage file is empty or malformed");
-        }
-        if line_buf.starts_with("TYPE,DATE,") {
-            break; // Found the header row, stop reading
-        }
-    }
-    let reader_with_headers = Cursor::new(line_buf).chain(reader);
-    let mut csv_reader = csv::ReaderBuilder::new()
-        .flexible(true)
-        .from_reader(reader_with_headers);
-    let headers = csv_reader.headers().expect("CSV headers not found").clone();
-    if headers != *EXPECTED_HEADERS {
-        panic!(
-            "Unexpected headers in usage CSV: {:?}. Expected: {:?}",
-            headers, *EXPECTED_HEADERS
-        );
+        LogLevel::Normal
+    });
+    if let Err(e) = args
+        .usage
+        .check_date_range()
+        .and_then(|()| open_output(args.output.as_deref(), args.format))
+    {
+        eprintln!("Error: {}", e);
+        return ExitCode::from(EXIT_ERROR);
     }
-    csv_reader
-        .into_records()
-        .filter_map(|r| {
-            let record = r.expect("Usage file could not be deserialized");
-            (record[0] == *"Electric usage").then(|| UsageEntry {
-                start_time: Time::from_str(&record[2]).expect("Invalid start time format"),
-                end_time: Time::from_str(&record[3]).expect("Invalid end time format"),
-                imported: record[4].parse().expect("Invalid imported kWh value"),
-                exported: record[5].parse().expect("Invalid exported kWh value"),
+
+    let (rates_config, schedule) = match load_config(&args.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    let current_rate = current_rate_from_args(args);
+    let cost_options = CostOptions {
+        export_credit_rate: args
+            .export_credit_rate
+            .as_ref()
+            .map(|rate| args.rate_unit.to_dollars(rate)),
+        entry_rounding: args.rounding.filter(|_| args.round_per_entry),
+        net_metering: args
+            .net_metering
+            .as_ref()
+            .map(|excess_credit_rate| NetMetering {
+                excess_credit_rate: args.rate_unit.to_dollars(excess_credit_rate),
+                billing_day: args.billing_day,
+            }),
+    };
+    let charge_options = ChargeOptions {
+        base_charge_per_day: args.base_charge_per_day.clone(),
+        solar_meter_fee: args.solar_meter_fee.clone(),
+        demand_charge_per_kw: args.demand_charge_per_kw.clone(),
+        minimum_bill: args.minimum_bill.clone(),
+        no_export_netting: args.no_export_netting,
+        billing_day: args.billing_day,
+        rounding: args.rounding,
+    };
+    // With `--compare-all`, each location's TOU rates are analyzed, and otherwise just the one.
+    let locations: Vec<&str> = if args.tou_rates.compare_all {
+        rates_config.location_names().collect()
+    } else {
+        Vec::new()
+    };
+    let analysis_rates: Vec<AnalysisRates> = if args.tou_rates.compare_all {
+        locations
+            .iter()
+            .map(|&location| {
+                let tou_rates = rates_config.rates(location).expect("location is known");
+                AnalysisRates::new(current_rate.clone(), tou_rates.clone())
             })
+            .collect()
+    } else {
+        match tou_rates_from_args(args, &rates_config) {
+            Ok(tou_rates) => vec![AnalysisRates::new(current_rate.clone(), tou_rates)],
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return ExitCode::from(EXIT_ERROR);
+            }
+        }
+    };
+    warn_implausible_rates(&current_rate, &cost_options, &analysis_rates);
+    let mut analyzers: Vec<Analyzer> = analysis_rates
+        .iter()
+        .map(|rates| Analyzer::new(rates, &schedule, &cost_options, &charge_options))
+        .collect();
+    let keep_entries = args.needs_all_entries();
+    let mut self_check = args.self_check.then(SelfCheck::default);
+    let mut flagged: Vec<UsageEntry> = Vec::new();
+    let mut explained: Vec<UsageEntry> = Vec::new();
+    let mut usage_data: Vec<UsageEntry> = Vec::new();
+    let mut coverage = CoverageChecker::default();
+    let mut interval_lengths = IntervalLengthChecker::new(args.usage.interval_minutes.clone());
+    let read = match &args.cache {
+        Some(cache_path) => read_cached_usage_files(
+            args,
+            &schedule,
+            cache_path,
+            |entry| {
+                coverage.add(entry);
+                interval_lengths.add(entry);
+            },
+            |day| {
+                let imports_only;
+                let day = if args.ignore_exports {
+                    imports_only = day.imports_only();
+                    &imports_only
+                } else {
+                    day
+                };
+                for analyzer in &mut analyzers {
+                    analyzer.add_day(day);
+                }
+            },
+        ),
+        None => read_usage_files(&args.usage, |mut entry| {
+            if args.ignore_exports {
+                entry.exported = BigDecimal::from(0);
+            }
+            for analyzer in &mut analyzers {
+                analyzer.add(&entry);
+            }
+            coverage.add(&entry);
+            interval_lengths.add(&entry);
+            if let Some(self_check) = &mut self_check {
+                self_check.add(&entry, &analysis_rates[0].tou, &schedule, &cost_options);
+            }
+            if args.show_flagged && entry.notes.is_some() {
+                flagged.push(entry.clone());
+            }
+            if args.explain == Some(entry.date) {
+                explained.push(entry.clone());
+            }
+            if keep_entries {
+                usage_data.push(entry);
+            }
+        }),
+    };
+    if let Err(e) = read {
+        eprintln!("Error: {}", e);
+        return ExitCode::from(EXIT_ERROR);
+    }
+    if let Some(count) = args.sample {
+        print_sample(&usage_data[..count.min(usage_data.len())], &schedule);
+        return ExitCode::SUCCESS;
+    }
+    // The totals are exact sums, so they don't depend on the order the usage was read in, but put
+    // the entries in order so anything listed entry by entry comes out the same however the usage
+    // files are given.
+    usage_data.sort_by_key(|entry| (entry.date, entry.start_time));
+    flagged.sort_by_key(|entry| (entry.date, entry.start_time));
+    explained.sort_by_key(|entry| entry.start_time);
+    warn_coverage_issues(&coverage.finish());
+    warn_unexpected_intervals(&interval_lengths.finish());
+    let currency = CurrencyFormat {
+        symbol: args.currency_symbol.clone(),
+        negative: args.negative_style,
+    };
+    // Only the first result has the details, which with `--compare-all` are only the ones that
+    // don't depend on the TOU rates.
+    let detail_options = args.detail_options();
+    let results: Vec<AnalysisResult> = analyzers
+        .iter()
+        .enumerate()
+        .map(|(index, analyzer)| match index {
+            0 => analyzer.finish_with(&usage_data, &detail_options),
+            _ => analyzer.finish(),
         })
-        .collect()
+        .collect();
+    let Some(result) = results.first() else {
+        eprintln!("Error: plan {:?} has no locations", args.config.plan);
+        return ExitCode::from(EXIT_ERROR);
+    };
+    let details = &result.details;
+    // The JSON summaries have these in them, but `--explain` is only ever text.
+    if args.format == OutputFormat::Text || args.explain.is_some() {
+        print_details(args, details, &flagged);
+    }
+    let rates = &analysis_rates[0];
+    if let Some(date) = args.explain {
+        let explanation = DayExplanation::new(date, &explained, rates, &schedule, &cost_options);
+        print_explanation(&explanation, &currency);
+        return ExitCode::SUCCESS;
+    }
+    warn_usage(args, &rates_config, result);
+    if args.tou_rates.compare_all {
+        let mut unpriced: BTreeMap<Date, Vec<&str>> = BTreeMap::new();
+        for (location, rates) in locations.iter().zip(&analysis_rates) {
+            let changes = rates_config.change_dates();
+            for date in unpriced_rate_changes(&rates.tou, changes, result.date_range) {
+                unpriced.entry(date).or_default().push(*location);
+            }
+        }
+        for (date, locations) in unpriced {
+            warn_unpriced_rate_change(date, &format!("the rates for {}", locations.join(", ")));
+        }
+        // The current cost is the same whichever TOU rates it's worked out with.
+        let current_cost = &result.costs.current;
+        let mut ranked: Vec<(&str, BigDecimal)> = locations
+            .iter()
+            .zip(&results)
+            .map(|(&location, result)| (location, result.costs.tou.clone()))
+            .collect();
+        ranked.sort_by(|(_, a), (_, b)| a.cmp(b));
+        let usage = UsageSummary::new(details, args.show_flagged.then_some(&flagged[..]));
+        print_location_ranking(args.format, &currency, current_cost, &ranked, usage);
+        return match ranked.first() {
+            Some((_, tou_cost)) => exit_code(Verdict::from_costs(current_cost, tou_cost)),
+            None => ExitCode::from(EXIT_ERROR),
+        };
+    }
+    if let Some(self_check) = &self_check {
+        let failures = self_check.failures(result, &cost_options);
+        if !failures.is_empty() {
+            eprintln!("Error: the self-check failed, so the results can't be trusted:");
+            for failure in &failures {
+                eprintln!("  {}", failure);
+            }
+            return ExitCode::from(EXIT_ERROR);
+        }
+        info!("Self-check passed");
+    }
+    for date in unpriced_rate_changes(&rates.tou, rates_config.change_dates(), result.date_range) {
+        warn_unpriced_rate_change(date, "the TOU rates");
+    }
+    if let Some(path) = &args.per_entry_csv
+        && let Err(e) = write_per_entry_csv(path, &rates.tou, &schedule, &cost_options, &usage_data)
+    {
+        eprintln!("Error: failed to write {}: {}", path.display(), e);
+        return ExitCode::from(EXIT_ERROR);
+    }
+    if let Some(path) = &args.profile_csv
+        && let Err(e) = write_profile_csv(path, &usage_data)
+    {
+        eprintln!("Error: failed to write {}: {}", path.display(), e);
+        return ExitCode::from(EXIT_ERROR);
+    }
+    warn_short_usage(args, result);
+    let verdict = result.costs.verdict();
+    match args.only {
+        Some(only) => print_only(only, args.decimals, result),
+        None => print_result(args, result, &flagged, &currency),
+    }
+    exit_code(verdict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Ttmbuwyntcstr, clap::Error> {
+        Ttmbuwyntcstr::try_parse_from(
+            ["ttmbuwyntcstr", "--current-rate", "0.12"]
+                .iter()
+                .chain(args)
+                .chain(&["usage.csv"]),
+        )
+    }
+
+    #[test]
+    fn partial_manual_rates_are_rejected() {
+        for args in [
+            &["--off-peak-rate", "0.08"][..],
+            &["--peak-rate", "0.16"],
+            &["--off-peak-rate", "0.08", "--mid-peak-rate", "0.14"],
+            &["--mid-peak-rate", "0.14", "--peak-rate", "0.16"],
+            &["--off-peak-rate", "0.08", "--peak-rate", "0.16"],
+            &["--plan", "scl", "--off-peak-rate", "0.08"],
+        ] {
+            let error = parse(args).expect_err("partial rates should be rejected");
+            assert_eq!(
+                error.kind(),
+                ErrorKind::MissingRequiredArgument,
+                "{:?}",
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn rates_must_be_positive_amounts() {
+        assert_eq!(
+            parse_positive_rate("0.0828"),
+            Ok("0.0828".parse::<BigDecimal>().unwrap())
+        );
+        for rate in ["-0.0828", "0", "abc", ""] {
+            assert_eq!(
+                parse_positive_rate(rate),
+                Err(format!("rate must be a positive amount, got {:?}", rate))
+            );
+        }
+        let error = parse(&["--peak-rate=-0.16"]).expect_err("negative rates are rejected");
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+        let error = Ttmbuwyntcstr::try_parse_from(["ttmbuwyntcstr", "--current-rate", "abc"])
+            .expect_err("non-numeric rates are rejected");
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+        assert!(error.to_string().contains("rate must be a positive amount"));
+    }
+
+    #[test]
+    fn credits_and_charges_can_be_zero_but_not_negative() {
+        for flag in [
+            "--export-credit-rate",
+            "--net-metering",
+            "--base-charge-per-day",
+        ] {
+            let zero = parse(&["--tou-location", "seattle", flag, "0"]);
+            assert!(zero.is_ok(), "{}: {:?}", flag, zero.err());
+            let error = parse(&[&format!("{}=-0.05", flag)]).expect_err("negative amounts");
+            assert_eq!(error.kind(), ErrorKind::ValueValidation, "{}", flag);
+            assert!(
+                error
+                    .to_string()
+                    .contains("expected zero or a positive amount, got \"-0.05\""),
+                "{}",
+                error
+            );
+        }
+        let tiers = ["--tier1-rate", "0.1", "--tier2-rate", "0.14"];
+        let error = parse(&[&tiers[..], &["--tier1-limit", "lots"]].concat())
+            .expect_err("non-numeric limits are rejected");
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn all_three_manual_rates_are_enough() {
+        let args = parse(&[
+            "--off-peak-rate",
+            "0.08",
+            "--mid-peak-rate",
+            "0.14",
+            "--peak-rate",
+            "0.16",
+        ])
+        .unwrap();
+        assert!(args.compare.tou_rates.mid_peak_rate.is_some());
+    }
+
+    #[test]
+    fn mid_peak_rate_can_be_left_out_for_another_plan() {
+        // Only a plan or rates file can have a schedule without a mid-peak period, which is
+        // checked once it's loaded.
+        for args in [
+            &[
+                "--plan",
+                "off-and-peak",
+                "--off-peak-rate",
+                "0.08",
+                "--peak-rate",
+                "0.16",
+            ][..],
+            &[
+                "--rates-file",
+                "rates.json",
+                "--off-peak-rate",
+                "0.08",
+                "--peak-rate",
+                "0.16",
+            ],
+        ] {
+            let args = parse(args).unwrap();
+            assert!(args.compare.tou_rates.mid_peak_rate.is_none());
+        }
+    }
 }
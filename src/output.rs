@@ -0,0 +1,130 @@
+//! Where the results are written: the `--output` file, or standard output or error.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+/// The standard stream the results go to without `--output`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+enum Sink {
+    Stream(Stream),
+    File(BufWriter<File>),
+}
+
+struct Output {
+    sink: Sink,
+    /// The first error writing to the `--output` file, reported by [finish].
+    error: Option<io::Error>,
+}
+
+static OUTPUT: Mutex<Output> = Mutex::new(Output {
+    sink: Sink::Stream(Stream::Stderr),
+    error: None,
+});
+
+fn lock() -> MutexGuard<'static, Output> {
+    OUTPUT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Writes the results to a new file at `path` if it's given, or else to `stream`.
+pub fn init(path: Option<&Path>, stream: Stream) -> io::Result<()> {
+    let sink = match path {
+        Some(path) => Sink::File(BufWriter::new(File::create(path)?)),
+        None => Sink::Stream(stream),
+    };
+    lock().sink = sink;
+    Ok(())
+}
+
+pub fn write_line(args: fmt::Arguments) {
+    let output = &mut *lock();
+    match &mut output.sink {
+        Sink::Stream(Stream::Stdout) => println!("{}", args),
+        Sink::Stream(Stream::Stderr) => eprintln!("{}", args),
+        Sink::File(file) => {
+            if output.error.is_none()
+                && let Err(e) = writeln!(file, "{}", args)
+            {
+                output.error = Some(e);
+            }
+        }
+    }
+}
+
+/// Flushes the `--output` file, returning the first error writing to it.
+pub fn finish() -> io::Result<()> {
+    let output = &mut *lock();
+    if let Some(e) = output.error.take() {
+        return Err(e);
+    }
+    match &mut output.sink {
+        Sink::File(file) => file.flush(),
+        Sink::Stream(_) => Ok(()),
+    }
+}
+
+/// The colors results can be highlighted with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Green,
+    Red,
+}
+
+/// Whether the results go to a terminal that should get colors, which it shouldn't if `NO_COLOR`
+/// is set, as described in https://no-color.org.
+fn colors_enabled(sink: &Sink) -> bool {
+    let is_terminal = match sink {
+        Sink::Stream(Stream::Stdout) => io::stdout().is_terminal(),
+        Sink::Stream(Stream::Stderr) => io::stderr().is_terminal(),
+        Sink::File(_) => false,
+    };
+    is_terminal && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// The text in `color`, if the results go to a terminal that gets colors.
+pub fn colored(color: Option<Color>, text: &str) -> String {
+    match color {
+        Some(color) if colors_enabled(&lock().sink) => {
+            let code = match color {
+                Color::Green => 32,
+                Color::Red => 31,
+            };
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        }
+        _ => text.to_string(),
+    }
+}
+
+/// Writes a line of the results.
+macro_rules! output {
+    ($($arg:tt)*) => {
+        $crate::output::write_line(format_args!($($arg)*))
+    };
+}
+
+/// Writes a line of the results unless `--quiet` is given.
+macro_rules! output_info {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::LogLevel::Normal) {
+            $crate::output::output!($($arg)*);
+        }
+    };
+}
+
+/// Writes a line of the results only if `--verbose` is given.
+macro_rules! output_verbose {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::LogLevel::Verbose) {
+            $crate::output::output!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use {output, output_info, output_verbose};
@@ -1,51 +1,1347 @@
-use crate::TouRates;
+use crate::rates::{CurrentRate, DatedTouRates, TieredRate, TouRates};
+use crate::schedule::{self, TouSchedule};
 use crate::usage_data::UsageEntry;
-use bigdecimal::BigDecimal;
-use jiff::civil::Time;
+use bigdecimal::{BigDecimal, RoundingMode};
+use clap::ValueEnum;
+use jiff::civil::{Date, Time};
+use std::collections::{BTreeMap, BTreeSet};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TimeOfUse {
+    /// The cheapest period, usually overnight, on plans that have one. SCL's plan doesn't, and
+    /// without a super off-peak rate it's charged at the off-peak rate.
+    SuperOff,
     Off,
     Mid,
     Peak,
 }
 
 impl TimeOfUse {
-    pub fn from_time(time: Time) -> Self {
-        let hour = time.hour();
-        match hour {
-            0..=5 => TimeOfUse::Off,
-            6..=16 | 21..=23 => TimeOfUse::Mid,
-            17..=20 => TimeOfUse::Peak,
-            ..0 | 24.. => panic!("Invalid hour: {}", hour),
+    /// Every period, in the order of their variants.
+    pub const ALL: [TimeOfUse; 4] = [
+        TimeOfUse::SuperOff,
+        TimeOfUse::Off,
+        TimeOfUse::Mid,
+        TimeOfUse::Peak,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TimeOfUse::SuperOff => "super_off",
+            TimeOfUse::Off => "off",
+            TimeOfUse::Mid => "mid",
+            TimeOfUse::Peak => "peak",
+        }
+    }
+
+    pub fn from_time(time: Time, schedule: &TouSchedule) -> Self {
+        schedule.period_at(time)
+    }
+
+    /// Like [TimeOfUse::from_time], but weekends and holidays are off-peak all day, except for
+    /// any super off-peak hours, which are still super off-peak.
+    pub fn from_date_time(date: Date, time: Time, schedule: &TouSchedule) -> Self {
+        match Self::from_time(time, schedule) {
+            TimeOfUse::SuperOff => TimeOfUse::SuperOff,
+            _ if schedule.is_off_peak_day(date) => TimeOfUse::Off,
+            tou => tou,
         }
     }
 }
 
-pub fn calculate_tou_cost<'a>(
-    rate: &TouRates,
+/// How to round currency amounts to whole cents.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round halves away from zero.
+    HalfUp,
+    /// Round halves to the nearest even cent, also known as banker's rounding.
+    HalfEven,
+    /// Drop any fraction of a cent.
+    Truncate,
+}
+
+impl Rounding {
+    pub fn round(self, amount: &BigDecimal) -> BigDecimal {
+        let mode = match self {
+            Rounding::HalfUp => RoundingMode::HalfUp,
+            Rounding::HalfEven => RoundingMode::HalfEven,
+            Rounding::Truncate => RoundingMode::Down,
+        };
+        amount.with_scale_round(2, mode)
+    }
+}
+
+/// Options for how usage is charged, which apply to both the current and TOU rates.
+#[derive(Debug, Clone, Default)]
+pub struct CostOptions {
+    /// The rate exported energy is credited at. If `None`, exports are credited at the same rate
+    /// imports are charged at.
+    pub export_credit_rate: Option<BigDecimal>,
+    /// If set, the cost of each usage entry is rounded to cents before it's summed, like a
+    /// utility rounds each line item. This can change the total by a few cents compared to only
+    /// rounding the total. Entries that span more than one TOU period are rounded once per period.
+    pub entry_rounding: Option<Rounding>,
+    /// If set, exports offset imports over each billing period instead of being credited as they
+    /// happen. [CostOptions::export_credit_rate] and [CostOptions::entry_rounding] don't apply
+    /// when net metering.
+    pub net_metering: Option<NetMetering>,
+}
+
+/// Net metering, where exports offset imports over a billing period and any excess rolls forward
+/// to later billing periods. This materially changes the costs for solar customers, since excess
+/// exports are no longer credited at the full rate. It assumes that:
+///
+/// - Billing periods are a month long, starting on [NetMetering::billing_day] of each month.
+/// - Within a billing period, the net KWH of each TOU period is charged at that period's rate, or
+///   the net KWH of the whole billing period at a flat rate. If the rate changes within a billing
+///   period, the net KWH is charged at the average of the rates it was used at.
+/// - If a TOU period exports more than it imports over a billing period, the excess KWH is banked
+///   and offsets the same TOU period's usage in later billing periods. Excess exported off-peak
+///   doesn't offset peak usage, for example.
+/// - Any excess still banked at the end of the usage data is credited at
+///   [NetMetering::excess_credit_rate], the utility's avoided cost.
+#[derive(Debug, Clone)]
+pub struct NetMetering {
+    pub excess_credit_rate: BigDecimal,
+    /// The day of the month billing periods start on, from 1 to 28.
+    pub billing_day: i8,
+}
+
+impl NetMetering {
+    /// The first day of the billing period `date` is in.
+    pub fn billing_period_start(&self, date: Date) -> Date {
+        billing_period_start(date, self.billing_day)
+    }
+
+    /// Settles the usage of one TOU period, or all of the usage at a flat rate, over consecutive
+    /// billing periods.
+    fn settle<'a>(&self, billing_periods: impl Iterator<Item = &'a PeriodUsage>) -> PeriodUsage {
+        let zero = BigDecimal::from(0);
+        let mut banked = zero.clone();
+        let mut settled = PeriodUsage::default();
+        for period in billing_periods {
+            settled.kwh += &period.kwh;
+            let net = &period.kwh - &banked;
+            if net >= zero {
+                // Only the usage left after the banked excess is charged, at its average rate.
+                settled.cost += if period.kwh > zero {
+                    &period.cost * &net / &period.kwh
+                } else {
+                    period.cost.clone()
+                };
+                banked = zero.clone();
+            } else {
+                banked = -net;
+            }
+        }
+        settled.cost -= banked * &self.excess_credit_rate;
+        settled
+    }
+}
+
+/// Nets the cost of each billing period at the end of the period, in order, banking a negative
+/// cost as a credit toward later periods' costs. Returns the credit left unused at the end, which
+/// is how much more netting each billing period costs than netting each usage entry, where credits
+/// can lower the cost below zero.
+pub fn unused_credit(period_costs: impl Iterator<Item = BigDecimal>) -> BigDecimal {
+    let zero = BigDecimal::from(0);
+    let mut banked = zero.clone();
+    for cost in period_costs {
+        let net = cost - &banked;
+        banked = if net < zero { -net } else { zero.clone() };
+    }
+    banked
+}
+
+/// The first day of the billing period `date` is in, when billing periods start on `billing_day`
+/// of each month, from 1 to 28.
+pub fn billing_period_start(date: Date, billing_day: i8) -> Date {
+    let month = if date.day() >= billing_day {
+        date
+    } else {
+        date.first_of_month()
+            .yesterday()
+            .expect("date out of range")
+    };
+    month
+        .with()
+        .day(billing_day)
+        .build()
+        .expect("billing day is in every month")
+}
+
+/// Groups the usage entries by the billing period they're in, by the period's first day, when
+/// billing periods start on `billing_day` of each month.
+pub fn group_by_billing_period<'a>(
     usage_data: impl Iterator<Item = &'a UsageEntry>,
-) -> BigDecimal {
-    usage_data
-        .map(|entry| {
-            let tou_start = TimeOfUse::from_time(entry.start_time);
-            let tou_end = TimeOfUse::from_time(entry.end_time);
-            assert_eq!(
-                tou_start, tou_end,
-                "Start and end times must be in the same TOU period"
-            );
-            match tou_start {
-                TimeOfUse::Off => &rate.off * entry.kwh_total(),
-                TimeOfUse::Mid => &rate.mid * entry.kwh_total(),
-                TimeOfUse::Peak => &rate.peak * entry.kwh_total(),
+    billing_day: i8,
+) -> BTreeMap<Date, Vec<&'a UsageEntry>> {
+    let mut periods: BTreeMap<Date, Vec<&UsageEntry>> = BTreeMap::new();
+    for entry in usage_data {
+        periods
+            .entry(billing_period_start(entry.date, billing_day))
+            .or_default()
+            .push(entry);
+    }
+    periods
+}
+
+/// The highest demand in each billing period, for plans with a demand charge.
+///
+/// Usage data only has the energy used in each interval, so an interval's demand is its average
+/// kW: its imported KWH divided by its length in hours. Short spikes within an interval can't be
+/// seen.
+#[derive(Debug, Clone)]
+pub struct PeakDemand {
+    billing_day: i8,
+    /// The highest kW in each billing period, by the period's first day.
+    peaks: BTreeMap<Date, BigDecimal>,
+}
+
+impl PeakDemand {
+    /// Tracks demand over billing periods starting on `billing_day` of each month.
+    pub fn new(billing_day: i8) -> Self {
+        Self {
+            billing_day,
+            peaks: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, entry: &UsageEntry) {
+        let seconds = entry.end().duration_since(entry.start()).as_secs();
+        if seconds <= 0 {
+            return;
+        }
+        let kw = (&entry.imported * BigDecimal::from(3600) / BigDecimal::from(seconds)).round(6);
+        let peak = self
+            .peaks
+            .entry(billing_period_start(entry.date, self.billing_day))
+            .or_default();
+        if kw > *peak {
+            *peak = kw;
+        }
+    }
+
+    /// The highest kW in each billing period, by the period's first day.
+    pub fn peaks(&self) -> &BTreeMap<Date, BigDecimal> {
+        &self.peaks
+    }
+
+    /// The demand charge over every billing period, at `charge_per_kw` of each period's peak.
+    pub fn charge(&self, charge_per_kw: &BigDecimal) -> BigDecimal {
+        self.peaks.values().sum::<BigDecimal>() * charge_per_kw
+    }
+}
+
+const MINUTES_PER_DAY: i32 = schedule::MINUTES_PER_DAY as i32;
+
+fn minute_of_day(time: Time) -> i32 {
+    i32::from(time.hour()) * 60 + i32::from(time.minute())
+}
+
+/// Splits the interval from `start` to `end` on `date` into the number of minutes spent in each
+/// TOU period. If `end` is before `start`, the interval is assumed to wrap past midnight into the
+/// next day.
+pub fn split_by_period(
+    date: Date,
+    start: Time,
+    end: Time,
+    schedule: &TouSchedule,
+) -> Vec<(TimeOfUse, i32)> {
+    let start = minute_of_day(start);
+    let mut end = minute_of_day(end);
+    if end < start {
+        end += MINUTES_PER_DAY;
+    }
+    let mut split: Vec<(TimeOfUse, i32)> = Vec::new();
+    let mut minute = start;
+    while minute < end {
+        // The period only changes at the schedule's boundaries and at midnight, so step from one
+        // to the next.
+        let (day, minute_in_day) = if minute >= MINUTES_PER_DAY {
+            (
+                date.tomorrow().expect("date out of range"),
+                minute - MINUTES_PER_DAY,
+            )
+        } else {
+            (date, minute)
+        };
+        let period_end = schedule.period_end(minute_in_day as usize) as i32;
+        let next = end.min(minute - minute_in_day + period_end);
+        let time = Time::constant((minute_in_day / 60) as i8, (minute_in_day % 60) as i8, 0, 0);
+        let tou = TimeOfUse::from_date_time(day, time, schedule);
+        match split.last_mut() {
+            Some((last_tou, minutes)) if *last_tou == tou => *minutes += next - minute,
+            _ => split.push((tou, next - minute)),
+        }
+        minute = next;
+    }
+    split
+}
+
+/// The part of a usage entry that falls in a single TOU period.
+#[derive(Debug, Clone)]
+pub struct PeriodShare {
+    pub tou: TimeOfUse,
+    pub imported: BigDecimal,
+    pub exported: BigDecimal,
+}
+
+impl PeriodShare {
+    pub fn kwh_total(&self) -> BigDecimal {
+        &self.imported - &self.exported
+    }
+
+    pub fn cost(&self, rate: &TouRates, options: &CostOptions) -> BigDecimal {
+        net_cost(
+            rate.for_period(self.tou),
+            options,
+            &self.imported,
+            &self.exported,
+        )
+    }
+}
+
+/// The usage on one day, totaled by TOU period. It costs the same as the day's entries, unless
+/// [CostOptions::entry_rounding] rounds each entry, so days can be kept instead of every entry.
+#[derive(Debug, Clone)]
+pub struct DaySummary {
+    pub date: Date,
+    pub imported: BigDecimal,
+    pub exported: BigDecimal,
+    /// The usage in each TOU period, in the order of [TimeOfUse::ALL].
+    pub periods: [PeriodShare; 4],
+    /// How many entries start in each TOU period, in the order of [TimeOfUse::ALL].
+    pub intervals: [usize; 4],
+}
+
+impl DaySummary {
+    pub fn new(date: Date) -> Self {
+        Self {
+            date,
+            imported: BigDecimal::from(0),
+            exported: BigDecimal::from(0),
+            periods: TimeOfUse::ALL.map(|tou| PeriodShare {
+                tou,
+                imported: BigDecimal::from(0),
+                exported: BigDecimal::from(0),
+            }),
+            intervals: [0; 4],
+        }
+    }
+
+    /// Adds an entry on the summary's date.
+    pub fn add(&mut self, entry: &UsageEntry, schedule: &TouSchedule) {
+        self.imported += &entry.imported;
+        self.exported += &entry.exported;
+        self.intervals
+            [TimeOfUse::from_date_time(entry.date, entry.start_time, schedule) as usize] += 1;
+        for share in split_usage_by_period(entry, schedule) {
+            let period = &mut self.periods[share.tou as usize];
+            period.imported += share.imported;
+            period.exported += share.exported;
+        }
+    }
+
+    /// The same day with only its imported energy, as if nothing had been exported.
+    pub fn imports_only(&self) -> DaySummary {
+        DaySummary {
+            date: self.date,
+            imported: self.imported.clone(),
+            exported: BigDecimal::from(0),
+            periods: self.periods.clone().map(|share| PeriodShare {
+                exported: BigDecimal::from(0),
+                ..share
+            }),
+            intervals: self.intervals,
+        }
+    }
+}
+
+/// Splits the imported and exported kWh of `entry` across the TOU periods it overlaps,
+/// proportional to the minutes spent in each.
+pub fn split_usage_by_period(entry: &UsageEntry, schedule: &TouSchedule) -> Vec<PeriodShare> {
+    let split = split_by_period(entry.date, entry.start_time, entry.end_time, schedule);
+    let total_minutes: i32 = split.iter().map(|(_, minutes)| minutes).sum();
+    if total_minutes == 0 {
+        // Zero-length interval, there's nothing to split so charge it where it starts.
+        return vec![PeriodShare {
+            tou: TimeOfUse::from_date_time(entry.date, entry.start_time, schedule),
+            imported: entry.imported.clone(),
+            exported: entry.exported.clone(),
+        }];
+    }
+    let total_minutes = BigDecimal::from(total_minutes);
+    split
+        .into_iter()
+        .map(|(tou, minutes)| {
+            let minutes = BigDecimal::from(minutes);
+            PeriodShare {
+                tou,
+                imported: &entry.imported * &minutes / &total_minutes,
+                exported: &entry.exported * &minutes / &total_minutes,
             }
         })
-        .sum()
+        .collect()
+}
+
+/// Charges `imported` at `rate` and credits `exported` at the export credit rate, or at `rate` if
+/// exports aren't credited separately.
+fn net_cost(
+    rate: &BigDecimal,
+    options: &CostOptions,
+    imported: &BigDecimal,
+    exported: &BigDecimal,
+) -> BigDecimal {
+    let cost = rate * imported - options.export_credit_rate.as_ref().unwrap_or(rate) * exported;
+    match options.entry_rounding {
+        Some(rounding) => rounding.round(&cost),
+        None => cost,
+    }
+}
+
+/// Calculates the TOU cost of the usage, using the rates in effect for each entry's date and
+/// season.
+pub fn calculate_tou_cost<'a>(
+    rate: &DatedTouRates,
+    schedule: &TouSchedule,
+    options: &CostOptions,
+    usage_data: impl Iterator<Item = &'a UsageEntry>,
+) -> BigDecimal {
+    calculate_tou_breakdown(rate, schedule, options, usage_data).total_cost()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PeriodUsage {
+    pub kwh: BigDecimal,
+    pub cost: BigDecimal,
+}
+
+/// Usage and TOU cost, broken down by TOU period.
+#[derive(Debug, Clone, Default)]
+pub struct TouBreakdown {
+    pub super_off: PeriodUsage,
+    pub off: PeriodUsage,
+    pub mid: PeriodUsage,
+    pub peak: PeriodUsage,
+}
+
+impl TouBreakdown {
+    pub fn for_period(&self, tou: TimeOfUse) -> &PeriodUsage {
+        match tou {
+            TimeOfUse::SuperOff => &self.super_off,
+            TimeOfUse::Off => &self.off,
+            TimeOfUse::Mid => &self.mid,
+            TimeOfUse::Peak => &self.peak,
+        }
+    }
+
+    pub fn total_cost(&self) -> BigDecimal {
+        &self.super_off.cost + &self.off.cost + &self.mid.cost + &self.peak.cost
+    }
+
+    pub fn total_kwh(&self) -> BigDecimal {
+        &self.super_off.kwh + &self.off.kwh + &self.mid.kwh + &self.peak.kwh
+    }
+
+    /// The fraction of the total KWH that was used in `tou`, or `None` if the usage can't be
+    /// divided up that way, because some period exported more than it imported or no energy was
+    /// used on net.
+    pub fn kwh_share(&self, tou: TimeOfUse) -> Option<BigDecimal> {
+        let zero = BigDecimal::from(0);
+        let total = self.total_kwh();
+        let divisible = total > zero
+            && TimeOfUse::ALL
+                .iter()
+                .all(|&tou| self.for_period(tou).kwh >= zero);
+        divisible.then(|| &self.for_period(tou).kwh / total)
+    }
+
+    /// Models moving `fraction` of the peak usage to off-peak, charging the moved KWH at
+    /// `off_rate`. The peak cost shrinks in proportion to the usage moved.
+    pub fn shift_peak_to_off(&self, fraction: &BigDecimal, off_rate: &BigDecimal) -> Self {
+        let moved_kwh = &self.peak.kwh * fraction;
+        let kept = BigDecimal::from(1) - fraction;
+        Self {
+            off: PeriodUsage {
+                cost: &self.off.cost + &moved_kwh * off_rate,
+                kwh: &self.off.kwh + &moved_kwh,
+            },
+            super_off: self.super_off.clone(),
+            mid: self.mid.clone(),
+            peak: PeriodUsage {
+                kwh: &self.peak.kwh * &kept,
+                cost: &self.peak.cost * &kept,
+            },
+        }
+    }
+
+    fn for_period_mut(&mut self, tou: TimeOfUse) -> &mut PeriodUsage {
+        match tou {
+            TimeOfUse::SuperOff => &mut self.super_off,
+            TimeOfUse::Off => &mut self.off,
+            TimeOfUse::Mid => &mut self.mid,
+            TimeOfUse::Peak => &mut self.peak,
+        }
+    }
+
+    fn add(&mut self, shares: &[PeriodShare], rate: &TouRates, options: &CostOptions) {
+        for share in shares {
+            let period = self.for_period_mut(share.tou);
+            period.cost += share.cost(rate, options);
+            period.kwh += share.kwh_total();
+        }
+    }
+}
+
+/// Accumulates a [TouBreakdown], keeping each billing period separate if net metering.
+#[derive(Debug, Clone, Default)]
+struct BreakdownAccumulator {
+    breakdown: TouBreakdown,
+    billing_periods: BTreeMap<Date, TouBreakdown>,
+}
+
+impl BreakdownAccumulator {
+    fn add(
+        &mut self,
+        entry: &UsageEntry,
+        rate: &TouRates,
+        schedule: &TouSchedule,
+        options: &CostOptions,
+    ) {
+        let shares = split_usage_by_period(entry, schedule);
+        self.add_shares(entry.date, &shares, rate, options);
+    }
+
+    /// Adds usage on `date` that's already split by TOU period.
+    fn add_shares(
+        &mut self,
+        date: Date,
+        shares: &[PeriodShare],
+        rate: &TouRates,
+        options: &CostOptions,
+    ) {
+        let Some(net_metering) = &options.net_metering else {
+            self.breakdown.add(shares, rate, options);
+            return;
+        };
+        let billing_period = self
+            .billing_periods
+            .entry(net_metering.billing_period_start(date))
+            .or_default();
+        for share in shares {
+            let period = billing_period.for_period_mut(share.tou);
+            let kwh = share.kwh_total();
+            period.cost += rate.for_period(share.tou) * &kwh;
+            period.kwh += kwh;
+        }
+    }
+
+    fn finish(&self, options: &CostOptions) -> TouBreakdown {
+        let Some(net_metering) = &options.net_metering else {
+            return self.breakdown.clone();
+        };
+        let settle = |tou| {
+            net_metering.settle(
+                self.billing_periods
+                    .values()
+                    .map(|billing_period| billing_period.for_period(tou)),
+            )
+        };
+        TouBreakdown {
+            super_off: settle(TimeOfUse::SuperOff),
+            off: settle(TimeOfUse::Off),
+            mid: settle(TimeOfUse::Mid),
+            peak: settle(TimeOfUse::Peak),
+        }
+    }
+}
+
+pub fn calculate_tou_breakdown<'a>(
+    rate: &DatedTouRates,
+    schedule: &TouSchedule,
+    options: &CostOptions,
+    usage_data: impl Iterator<Item = &'a UsageEntry>,
+) -> TouBreakdown {
+    let mut breakdown = BreakdownAccumulator::default();
+    for entry in usage_data {
+        breakdown.add(entry, rate.for_date(entry.date), schedule, options);
+    }
+    breakdown.finish(options)
+}
+
+/// Calculates the TOU cost of only the imported energy, as if nothing had been exported.
+pub fn calculate_import_cost<'a>(
+    rate: &DatedTouRates,
+    schedule: &TouSchedule,
+    options: &CostOptions,
+    usage_data: impl Iterator<Item = &'a UsageEntry>,
+) -> BigDecimal {
+    let mut breakdown = BreakdownAccumulator::default();
+    for entry in usage_data {
+        breakdown.add(
+            &entry.imports_only(),
+            rate.for_date(entry.date),
+            schedule,
+            options,
+        );
+    }
+    breakdown.finish(options).total_cost()
+}
+
+/// Calculates the TOU credit for the exported energy, as how much less the usage costs than its
+/// [calculate_import_cost]. This way the credit accounts for how exports are credited, e.g. at
+/// [CostOptions::export_credit_rate] or by net metering.
+pub fn calculate_export_credit<'a>(
+    rate: &DatedTouRates,
+    schedule: &TouSchedule,
+    options: &CostOptions,
+    usage_data: impl Iterator<Item = &'a UsageEntry> + Clone,
+) -> BigDecimal {
+    calculate_import_cost(rate, schedule, options, usage_data.clone())
+        - calculate_tou_cost(rate, schedule, options, usage_data)
+}
+
+/// Accumulates the cost at a flat rate, keeping each billing period separate if net metering.
+#[derive(Debug, Clone, Default)]
+struct FlatCostAccumulator {
+    cost: BigDecimal,
+    billing_periods: BTreeMap<Date, PeriodUsage>,
+}
+
+impl FlatCostAccumulator {
+    fn add(&mut self, entry: &UsageEntry, rate: &BigDecimal, options: &CostOptions) {
+        self.add_usage(entry.date, &entry.imported, &entry.exported, rate, options);
+    }
+
+    fn add_usage(
+        &mut self,
+        date: Date,
+        imported: &BigDecimal,
+        exported: &BigDecimal,
+        rate: &BigDecimal,
+        options: &CostOptions,
+    ) {
+        let Some(net_metering) = &options.net_metering else {
+            self.cost += net_cost(rate, options, imported, exported);
+            return;
+        };
+        let billing_period = self
+            .billing_periods
+            .entry(net_metering.billing_period_start(date))
+            .or_default();
+        let kwh = imported - exported;
+        billing_period.cost += rate * &kwh;
+        billing_period.kwh += kwh;
+    }
+
+    fn finish(&self, options: &CostOptions) -> BigDecimal {
+        match &options.net_metering {
+            Some(net_metering) => net_metering.settle(self.billing_periods.values()).cost,
+            None => self.cost.clone(),
+        }
+    }
 }
 
 pub fn calculate_base_cost<'a>(
     rate: &BigDecimal,
+    options: &CostOptions,
+    usage_data: impl Iterator<Item = &'a UsageEntry>,
+) -> BigDecimal {
+    let mut cost = FlatCostAccumulator::default();
+    for entry in usage_data {
+        cost.add(entry, rate, options);
+    }
+    cost.finish(options)
+}
+
+/// Calculates the cost of the usage under a tiered rate, with the tiers starting over in each
+/// billing period.
+///
+/// The tiers apply to each billing period's total imported KWH if exports are credited at a
+/// separate rate, or to its total net KWH otherwise. The tiers are applied to the totals, so
+/// [CostOptions::entry_rounding] and [CostOptions::net_metering] don't apply.
+pub fn calculate_tiered_cost<'a>(
+    rate: &TieredRate,
+    options: &CostOptions,
+    usage_data: impl Iterator<Item = &'a UsageEntry>,
+) -> BigDecimal {
+    let mut usage = TieredUsage::default();
+    for entries in group_by_billing_period(usage_data, rate.billing_day).into_values() {
+        for entry in entries {
+            usage.add(entry.date, &entry.imported, &entry.exported, rate);
+        }
+    }
+    usage.cost(rate, options)
+}
+
+/// The imported and exported KWH of each billing period, by the period's first day, to charge a
+/// tiered rate on.
+#[derive(Debug, Clone, Default)]
+struct TieredUsage {
+    billing_periods: BTreeMap<Date, (BigDecimal, BigDecimal)>,
+}
+
+impl TieredUsage {
+    fn add(&mut self, date: Date, imported: &BigDecimal, exported: &BigDecimal, rate: &TieredRate) {
+        let (period_imported, period_exported) = self
+            .billing_periods
+            .entry(billing_period_start(date, rate.billing_day))
+            .or_default();
+        *period_imported += imported;
+        *period_exported += exported;
+    }
+
+    fn cost(&self, rate: &TieredRate, options: &CostOptions) -> BigDecimal {
+        self.billing_periods
+            .values()
+            .map(|(imported, exported)| tiered_cost(rate, options, imported, exported))
+            .sum()
+    }
+}
+
+fn tiered_cost(
+    rate: &TieredRate,
+    options: &CostOptions,
+    imported: &BigDecimal,
+    exported: &BigDecimal,
+) -> BigDecimal {
+    match &options.export_credit_rate {
+        Some(export_credit_rate) => rate.charge(imported) - export_credit_rate * exported,
+        None => rate.charge(&(imported - exported)),
+    }
+}
+
+pub fn calculate_current_cost<'a>(
+    rate: &CurrentRate,
+    options: &CostOptions,
+    usage_data: impl Iterator<Item = &'a UsageEntry>,
+) -> BigDecimal {
+    match rate {
+        CurrentRate::Flat(rate) => calculate_base_cost(rate, options, usage_data),
+        CurrentRate::Tiered(rate) => calculate_tiered_cost(rate, options, usage_data),
+    }
+}
+
+/// Calculates a fixed daily charge over every distinct day present in the usage data.
+pub fn calculate_fixed_charge<'a>(
+    charge_per_day: &BigDecimal,
     usage_data: impl Iterator<Item = &'a UsageEntry>,
 ) -> BigDecimal {
-    usage_data.map(|entry| rate * entry.kwh_total()).sum()
+    charge_per_day * BigDecimal::from(count_days(usage_data) as u64)
+}
+
+pub fn count_days<'a>(usage_data: impl Iterator<Item = &'a UsageEntry>) -> usize {
+    usage_data
+        .map(|entry| entry.date)
+        .collect::<BTreeSet<_>>()
+        .len()
+}
+
+/// Accumulates the totals needed to compare the current and TOU costs one usage entry at a time,
+/// so the usage data can be streamed instead of kept in memory.
+///
+/// The totals match what [calculate_current_cost], [calculate_tou_breakdown] and
+/// [calculate_fixed_charge] compute over the same entries.
+pub struct CostAccumulator<'a> {
+    current_rate: &'a CurrentRate,
+    tou_rates: &'a DatedTouRates,
+    schedule: &'a TouSchedule,
+    options: &'a CostOptions,
+    imported: BigDecimal,
+    exported: BigDecimal,
+    /// The cost at a flat current rate so far. Tiered rates are charged on the totals of each
+    /// billing period instead.
+    flat_cost: FlatCostAccumulator,
+    tiered_usage: TieredUsage,
+    breakdown: BreakdownAccumulator,
+    dates: BTreeSet<Date>,
+    intervals: [usize; 4],
+}
+
+impl<'a> CostAccumulator<'a> {
+    pub fn new(
+        current_rate: &'a CurrentRate,
+        tou_rates: &'a DatedTouRates,
+        schedule: &'a TouSchedule,
+        options: &'a CostOptions,
+    ) -> Self {
+        Self {
+            current_rate,
+            tou_rates,
+            schedule,
+            options,
+            imported: BigDecimal::from(0),
+            exported: BigDecimal::from(0),
+            flat_cost: FlatCostAccumulator::default(),
+            tiered_usage: TieredUsage::default(),
+            breakdown: BreakdownAccumulator::default(),
+            dates: BTreeSet::new(),
+            intervals: [0; 4],
+        }
+    }
+
+    pub fn add(&mut self, entry: &UsageEntry) {
+        self.imported += &entry.imported;
+        self.exported += &entry.exported;
+        match self.current_rate {
+            CurrentRate::Flat(rate) => self.flat_cost.add(entry, rate, self.options),
+            CurrentRate::Tiered(rate) => {
+                self.tiered_usage
+                    .add(entry.date, &entry.imported, &entry.exported, rate)
+            }
+        }
+        let rate = self.tou_rates.for_date(entry.date);
+        self.breakdown.add(entry, rate, self.schedule, self.options);
+        self.dates.insert(entry.date);
+        let tou = TimeOfUse::from_date_time(entry.date, entry.start_time, self.schedule);
+        self.intervals[tou as usize] += 1;
+    }
+
+    /// Adds a day of usage without its entries, which totals the same as adding its entries.
+    pub fn add_day(&mut self, day: &DaySummary) {
+        self.imported += &day.imported;
+        self.exported += &day.exported;
+        match self.current_rate {
+            CurrentRate::Flat(rate) => {
+                self.flat_cost
+                    .add_usage(day.date, &day.imported, &day.exported, rate, self.options)
+            }
+            CurrentRate::Tiered(rate) => {
+                self.tiered_usage
+                    .add(day.date, &day.imported, &day.exported, rate)
+            }
+        }
+        let rate = self.tou_rates.for_date(day.date);
+        self.breakdown
+            .add_shares(day.date, &day.periods, rate, self.options);
+        self.dates.insert(day.date);
+        for (count, day_count) in self.intervals.iter_mut().zip(day.intervals) {
+            *count += day_count;
+        }
+    }
+
+    pub fn total_kwh(&self) -> BigDecimal {
+        &self.imported - &self.exported
+    }
+
+    pub fn imported(&self) -> &BigDecimal {
+        &self.imported
+    }
+
+    pub fn exported(&self) -> &BigDecimal {
+        &self.exported
+    }
+
+    /// The cost of the usage so far at the current rate, not including any fixed charge.
+    pub fn current_cost(&self) -> BigDecimal {
+        match self.current_rate {
+            CurrentRate::Flat(_) => self.flat_cost.finish(self.options),
+            CurrentRate::Tiered(rate) => self.tiered_usage.cost(rate, self.options),
+        }
+    }
+
+    pub fn breakdown(&self) -> TouBreakdown {
+        self.breakdown.finish(self.options)
+    }
+
+    /// How many entries so far start in `tou`. An entry that spans more than one TOU period is
+    /// only counted in the one it starts in.
+    pub fn intervals(&self, tou: TimeOfUse) -> usize {
+        self.intervals[tou as usize]
+    }
+
+    /// The distinct days present in the usage so far.
+    pub fn dates(&self) -> &BTreeSet<Date> {
+        &self.dates
+    }
+
+    /// The first and last dates in the usage so far.
+    pub fn date_range(&self) -> Option<(Date, Date)> {
+        Some((*self.dates.first()?, *self.dates.last()?))
+    }
+
+    pub fn fixed_charge(&self, charge_per_day: &BigDecimal) -> BigDecimal {
+        charge_per_day * BigDecimal::from(self.dates.len() as u64)
+    }
+
+    /// How many billing periods starting on `billing_day` the usage so far touches.
+    pub fn billing_period_count(&self, billing_day: i8) -> usize {
+        self.dates
+            .iter()
+            .map(|&date| billing_period_start(date, billing_day))
+            .collect::<BTreeSet<_>>()
+            .len()
+    }
+}
+
+/// Accumulates a [CostAccumulator] for each billing period separately, e.g. to charge a minimum
+/// bill in each one.
+pub struct BillingPeriodCosts<'a> {
+    current_rate: &'a CurrentRate,
+    tou_rates: &'a DatedTouRates,
+    schedule: &'a TouSchedule,
+    options: &'a CostOptions,
+    billing_day: i8,
+    /// The totals of each billing period, by the period's first day.
+    periods: BTreeMap<Date, CostAccumulator<'a>>,
+}
+
+impl<'a> BillingPeriodCosts<'a> {
+    /// Tracks billing periods starting on `billing_day` of each month.
+    pub fn new(
+        current_rate: &'a CurrentRate,
+        tou_rates: &'a DatedTouRates,
+        schedule: &'a TouSchedule,
+        options: &'a CostOptions,
+        billing_day: i8,
+    ) -> Self {
+        Self {
+            current_rate,
+            tou_rates,
+            schedule,
+            options,
+            billing_day,
+            periods: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, entry: &UsageEntry) {
+        self.period(entry.date).add(entry);
+    }
+
+    pub fn add_day(&mut self, day: &DaySummary) {
+        self.period(day.date).add_day(day);
+    }
+
+    /// The totals of each billing period with usage, by the period's first day.
+    pub fn periods(&self) -> &BTreeMap<Date, CostAccumulator<'a>> {
+        &self.periods
+    }
+
+    fn period(&mut self, date: Date) -> &mut CostAccumulator<'a> {
+        self.periods
+            .entry(billing_period_start(date, self.billing_day))
+            .or_insert_with(|| {
+                CostAccumulator::new(
+                    self.current_rate,
+                    self.tou_rates,
+                    self.schedule,
+                    self.options,
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jiff::civil::{date, time};
+    use std::str::FromStr;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn rates() -> DatedTouRates {
+        DatedTouRates::from(TouRates {
+            super_off: None,
+            off: decimal("0.0828"),
+            mid: decimal("0.1449"),
+            peak: decimal("0.1656"),
+        })
+    }
+
+    fn entry(date: Date, start_time: Time, end_time: Time, imported: &str) -> UsageEntry {
+        UsageEntry {
+            date,
+            start_time,
+            end_time,
+            imported: decimal(imported),
+            exported: BigDecimal::from(0),
+            notes: None,
+        }
+    }
+
+    #[test]
+    fn every_hour_is_classified() {
+        let schedule = TouSchedule::scl();
+        for hour in 0..24 {
+            let expected = match hour {
+                0..6 => TimeOfUse::Off,
+                17..21 => TimeOfUse::Peak,
+                _ => TimeOfUse::Mid,
+            };
+            for minute in [0, 30, 59] {
+                let tou = TimeOfUse::from_time(time(hour, minute, 0, 0), &schedule);
+                assert_eq!(tou, expected, "{:02}:{:02}", hour, minute);
+            }
+        }
+    }
+
+    #[test]
+    fn periods_change_on_the_minute() {
+        let schedule = TouSchedule::scl();
+        for (hour, minute, expected) in [
+            (5, 59, TimeOfUse::Off),
+            (6, 0, TimeOfUse::Mid),
+            (16, 59, TimeOfUse::Mid),
+            (17, 0, TimeOfUse::Peak),
+            (20, 59, TimeOfUse::Peak),
+            (21, 0, TimeOfUse::Mid),
+            (23, 59, TimeOfUse::Mid),
+        ] {
+            let tou = TimeOfUse::from_time(time(hour, minute, 0, 0), &schedule);
+            assert_eq!(tou, expected, "{:02}:{:02}", hour, minute);
+        }
+    }
+
+    #[test]
+    fn intervals_are_split_at_period_boundaries() {
+        let schedule = TouSchedule::scl();
+        // A Monday.
+        let monday = date(2024, 3, 4);
+        let split = |start, end| split_by_period(monday, start, end, &schedule);
+        assert_eq!(
+            split(time(5, 45, 0, 0), time(6, 15, 0, 0)),
+            [(TimeOfUse::Off, 15), (TimeOfUse::Mid, 15)]
+        );
+        assert_eq!(
+            split(time(16, 30, 0, 0), time(21, 30, 0, 0)),
+            [
+                (TimeOfUse::Mid, 30),
+                (TimeOfUse::Peak, 240),
+                (TimeOfUse::Mid, 30)
+            ]
+        );
+        assert_eq!(
+            split(time(17, 0, 0, 0), time(17, 15, 0, 0)),
+            [(TimeOfUse::Peak, 15)]
+        );
+        // Midnight ends the interval rather than wrapping around the whole day.
+        assert_eq!(
+            split(time(23, 45, 0, 0), time(0, 0, 0, 0)),
+            [(TimeOfUse::Mid, 15)]
+        );
+        assert_eq!(
+            split(time(23, 30, 0, 0), time(0, 30, 0, 0)),
+            [(TimeOfUse::Mid, 30), (TimeOfUse::Off, 30)]
+        );
+    }
+
+    #[test]
+    fn intervals_past_midnight_use_the_next_day() {
+        // Usage files can't have intervals like these, but split_by_period documents that it
+        // wraps them into the next day.
+        let schedule = TouSchedule::scl();
+        // From a Sunday, which is off-peak all day, into a Monday morning, which is off-peak too.
+        assert_eq!(
+            split_by_period(
+                date(2024, 3, 3),
+                time(23, 30, 0, 0),
+                time(0, 30, 0, 0),
+                &schedule
+            ),
+            [(TimeOfUse::Off, 60)]
+        );
+        // From a Friday into a Saturday, which is off-peak all day.
+        assert_eq!(
+            split_by_period(
+                date(2024, 3, 1),
+                time(20, 0, 0, 0),
+                time(1, 0, 0, 0),
+                &schedule
+            ),
+            [
+                (TimeOfUse::Peak, 60),
+                (TimeOfUse::Mid, 180),
+                (TimeOfUse::Off, 60)
+            ]
+        );
+    }
+
+    #[test]
+    fn usage_is_split_by_the_minutes_in_each_period() {
+        let schedule = TouSchedule::scl();
+        let entry = UsageEntry {
+            exported: decimal("0.4"),
+            ..entry(
+                date(2024, 3, 4),
+                time(16, 45, 0, 0),
+                time(17, 45, 0, 0),
+                "1.2",
+            )
+        };
+        let shares = split_usage_by_period(&entry, &schedule);
+        assert_eq!(shares.len(), 2);
+        assert_eq!(shares[0].tou, TimeOfUse::Mid);
+        assert_eq!(shares[0].imported, decimal("0.3"));
+        assert_eq!(shares[0].exported, decimal("0.1"));
+        assert_eq!(shares[1].tou, TimeOfUse::Peak);
+        assert_eq!(shares[1].imported, decimal("0.9"));
+        assert_eq!(shares[1].exported, decimal("0.3"));
+    }
+
+    #[test]
+    fn exports_are_split_by_the_same_fractions_as_imports() {
+        let schedule = TouSchedule::scl();
+        // Solar still exporting as the peak period starts, a quarter of the interval before it.
+        let usage = [UsageEntry {
+            exported: decimal("1.6"),
+            ..entry(
+                date(2024, 3, 4),
+                time(16, 45, 0, 0),
+                time(17, 45, 0, 0),
+                "0.4",
+            )
+        }];
+        let shares = split_usage_by_period(&usage[0], &schedule);
+        assert_eq!(shares[0].tou, TimeOfUse::Mid);
+        assert_eq!(
+            (&shares[0].imported, &shares[0].exported),
+            (&decimal("0.1"), &decimal("0.4"))
+        );
+        assert_eq!(shares[1].tou, TimeOfUse::Peak);
+        assert_eq!(
+            (&shares[1].imported, &shares[1].exported),
+            (&decimal("0.3"), &decimal("1.2"))
+        );
+
+        // Exports credited at their own rate are credited in the period they were exported in.
+        let options = CostOptions {
+            export_credit_rate: Some(decimal("0.05")),
+            ..CostOptions::default()
+        };
+        let breakdown = calculate_tou_breakdown(&rates(), &schedule, &options, usage.iter());
+        assert_eq!(breakdown.mid.cost, decimal("-0.00551"));
+        assert_eq!(breakdown.peak.cost, decimal("-0.01032"));
+
+        // Net metering banks each period's excess separately, crediting it at the excess rate.
+        let options = CostOptions {
+            net_metering: Some(NetMetering {
+                excess_credit_rate: decimal("0.05"),
+                billing_day: 1,
+            }),
+            ..CostOptions::default()
+        };
+        let breakdown = calculate_tou_breakdown(&rates(), &schedule, &options, usage.iter());
+        assert_eq!(breakdown.mid.kwh, decimal("-0.3"));
+        assert_eq!(breakdown.mid.cost, decimal("-0.015"));
+        assert_eq!(breakdown.peak.kwh, decimal("-0.9"));
+        assert_eq!(breakdown.peak.cost, decimal("-0.045"));
+    }
+
+    #[test]
+    fn zero_length_usage_is_charged_where_it_starts() {
+        let schedule = TouSchedule::scl();
+        let entry = entry(
+            date(2024, 3, 4),
+            time(17, 0, 0, 0),
+            time(17, 0, 0, 0),
+            "0.5",
+        );
+        let shares = split_usage_by_period(&entry, &schedule);
+        assert_eq!(shares.len(), 1);
+        assert_eq!(shares[0].tou, TimeOfUse::Peak);
+        assert_eq!(shares[0].imported, decimal("0.5"));
+    }
+
+    #[test]
+    fn entry_spanning_mid_to_peak_is_charged_at_both_rates() {
+        let schedule = TouSchedule::scl();
+        let usage = [entry(
+            date(2024, 3, 4),
+            time(16, 45, 0, 0),
+            time(17, 15, 0, 0),
+            "0.6",
+        )];
+        let options = CostOptions::default();
+        let breakdown = calculate_tou_breakdown(&rates(), &schedule, &options, usage.iter());
+        assert_eq!(breakdown.mid.kwh, decimal("0.3"));
+        assert_eq!(breakdown.peak.kwh, decimal("0.3"));
+        // 0.3 KWH at 0.1449 and 0.3 KWH at 0.1656.
+        assert_eq!(breakdown.total_cost(), decimal("0.09315"));
+    }
+
+    #[test]
+    fn entry_wrapping_past_midnight_is_charged_at_the_next_days_rate() {
+        // The usage reader rejects intervals like this, but a UsageEntry that ends before it
+        // starts wraps past midnight, the same as split_by_period, for usage built in code.
+        let schedule = TouSchedule::scl();
+        // From a Friday evening into Saturday, which is off-peak all day.
+        let usage = [entry(
+            date(2024, 3, 1),
+            time(23, 30, 0, 0),
+            time(0, 30, 0, 0),
+            "0.6",
+        )];
+        let options = CostOptions::default();
+        let breakdown = calculate_tou_breakdown(&rates(), &schedule, &options, usage.iter());
+        assert_eq!(breakdown.mid.kwh, decimal("0.3"));
+        assert_eq!(breakdown.off.kwh, decimal("0.3"));
+        // 0.3 KWH at 0.1449 and 0.3 KWH at 0.0828.
+        assert_eq!(breakdown.total_cost(), decimal("0.06831"));
+    }
+
+    #[test]
+    fn last_interval_of_the_day_is_charged_at_mid_peak() {
+        let schedule = TouSchedule::scl();
+        // A Friday, so the interval would be off-peak if it were taken to be on Saturday.
+        let usage = [entry(
+            date(2024, 3, 1),
+            time(23, 45, 0, 0),
+            time(0, 0, 0, 0),
+            "0.4",
+        )];
+        let options = CostOptions::default();
+        let breakdown = calculate_tou_breakdown(&rates(), &schedule, &options, usage.iter());
+        assert_eq!(breakdown.mid.kwh, decimal("0.4"));
+        assert_eq!(breakdown.off.kwh, BigDecimal::from(0));
+        assert_eq!(
+            calculate_tou_cost(&rates(), &schedule, &options, usage.iter()),
+            decimal("0.05796")
+        );
+    }
+
+    #[test]
+    fn net_metering_banks_excess_exports_for_later_billing_periods() {
+        let net_metering = NetMetering {
+            excess_credit_rate: decimal("0.05"),
+            billing_day: 1,
+        };
+        let billing_periods = [
+            PeriodUsage {
+                kwh: decimal("-10"),
+                cost: decimal("-1.0"),
+            },
+            // The 10 KWH banked offsets 10 of the 15 used, so a third of the cost is charged.
+            PeriodUsage {
+                kwh: decimal("15"),
+                cost: decimal("1.5"),
+            },
+            // Still banked at the end, so credited at the excess credit rate.
+            PeriodUsage {
+                kwh: decimal("-4"),
+                cost: decimal("-0.4"),
+            },
+        ];
+        let settled = net_metering.settle(billing_periods.iter());
+        assert_eq!(settled.kwh, decimal("1"));
+        assert_eq!(settled.cost, decimal("0.3"));
+    }
+
+    #[test]
+    fn net_metering_only_banks_what_was_exported_on_net() {
+        let net_metering = NetMetering {
+            excess_credit_rate: decimal("0.05"),
+            billing_day: 1,
+        };
+        let billing_periods = [
+            PeriodUsage {
+                kwh: decimal("-2"),
+                cost: decimal("-0.2"),
+            },
+            // Uses up the banked excess, so none of it carries over to the next billing period.
+            PeriodUsage {
+                kwh: decimal("4"),
+                cost: decimal("0.4"),
+            },
+            PeriodUsage {
+                kwh: decimal("3"),
+                cost: decimal("0.3"),
+            },
+        ];
+        let settled = net_metering.settle(billing_periods.iter());
+        assert_eq!(settled.kwh, decimal("5"));
+        assert_eq!(settled.cost, decimal("0.5"));
+    }
+
+    #[test]
+    fn tiers_start_over_in_each_billing_period() {
+        let rate = TieredRate {
+            tier1_rate: decimal("0.1"),
+            tier1_limit: decimal("10"),
+            tier2_rate: decimal("0.2"),
+            billing_day: 1,
+        };
+        let midnight = time(0, 0, 0, 0);
+        let usage = [
+            entry(date(2024, 3, 4), midnight, time(1, 0, 0, 0), "8"),
+            entry(date(2024, 3, 5), midnight, time(1, 0, 0, 0), "6"),
+            entry(date(2024, 4, 2), midnight, time(1, 0, 0, 0), "5"),
+        ];
+        // 10 KWH at tier 1 and 4 at tier 2 in March, and 5 at tier 1 in April.
+        let cost = calculate_tiered_cost(&rate, &CostOptions::default(), usage.iter());
+        assert_eq!(cost, decimal("2.3"));
+    }
+
+    #[test]
+    fn tiers_apply_to_imports_when_exports_are_credited_separately() {
+        let rate = TieredRate {
+            tier1_rate: decimal("0.1"),
+            tier1_limit: decimal("10"),
+            tier2_rate: decimal("0.2"),
+            billing_day: 1,
+        };
+        let usage = [UsageEntry {
+            exported: decimal("2"),
+            ..entry(date(2024, 3, 4), time(12, 0, 0, 0), time(13, 0, 0, 0), "14")
+        }];
+        let netted = calculate_tiered_cost(&rate, &CostOptions::default(), usage.iter());
+        assert_eq!(netted, decimal("1.4"));
+        let options = CostOptions {
+            export_credit_rate: Some(decimal("0.05")),
+            ..CostOptions::default()
+        };
+        let credited = calculate_tiered_cost(&rate, &options, usage.iter());
+        assert_eq!(credited, decimal("1.7"));
+    }
+
+    #[test]
+    fn billing_periods_are_totaled_separately() {
+        let current_rate = CurrentRate::Flat(decimal("0.1"));
+        let tou_rates = rates();
+        let schedule = TouSchedule::scl();
+        let options = CostOptions::default();
+        let usage = [
+            // Billed from February 15th.
+            entry(date(2024, 3, 4), time(17, 0, 0, 0), time(18, 0, 0, 0), "2"),
+            // Billed from March 15th.
+            entry(date(2024, 3, 20), time(1, 0, 0, 0), time(2, 0, 0, 0), "3"),
+            entry(date(2024, 4, 10), time(12, 0, 0, 0), time(13, 0, 0, 0), "1"),
+        ];
+        let mut billing_periods =
+            BillingPeriodCosts::new(&current_rate, &tou_rates, &schedule, &options, 15);
+        let mut all = CostAccumulator::new(&current_rate, &tou_rates, &schedule, &options);
+        for entry in &usage {
+            billing_periods.add(entry);
+            all.add(entry);
+        }
+        let periods = billing_periods.periods();
+        assert_eq!(
+            periods.keys().copied().collect::<Vec<_>>(),
+            [date(2024, 2, 15), date(2024, 3, 15)]
+        );
+        let february = &periods[&date(2024, 2, 15)];
+        assert_eq!(february.total_kwh(), decimal("2"));
+        assert_eq!(february.current_cost(), decimal("0.2"));
+        assert_eq!(february.breakdown().total_cost(), decimal("0.3312"));
+        assert_eq!(february.intervals(TimeOfUse::Peak), 1);
+        let march = &periods[&date(2024, 3, 15)];
+        assert_eq!(march.total_kwh(), decimal("4"));
+        assert_eq!(march.current_cost(), decimal("0.4"));
+        assert_eq!(march.breakdown().total_cost(), decimal("0.3933"));
+        assert_eq!(
+            march.date_range(),
+            Some((date(2024, 3, 20), date(2024, 4, 10)))
+        );
+        // Together, the billing periods add up to the totals over all the usage.
+        let total_cost: BigDecimal = periods
+            .values()
+            .map(|period| period.breakdown().total_cost())
+            .sum();
+        assert_eq!(total_cost, all.breakdown().total_cost());
+        let current_cost: BigDecimal = periods.values().map(|period| period.current_cost()).sum();
+        assert_eq!(current_cost, all.current_cost());
+    }
 }
@@ -0,0 +1,1119 @@
+use crate::holidays::Holidays;
+use crate::json::{self, Map, Value};
+use crate::rate_calculator::TimeOfUse;
+use crate::schedule::{MINUTES_PER_DAY, TouSchedule, format_minute, parse_time_range};
+use bigdecimal::BigDecimal;
+use jiff::civil::Date;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TouRates {
+    /// The super off-peak rate, for plans with a super off-peak period. Without it, super off-peak
+    /// usage is charged at the off-peak rate, so the usual three-period plans don't need one.
+    pub super_off: Option<BigDecimal>,
+    pub off: BigDecimal,
+    pub mid: BigDecimal,
+    pub peak: BigDecimal,
+}
+
+impl TouRates {
+    /// The same `rate` for every period.
+    pub fn uniform(rate: BigDecimal) -> Self {
+        Self {
+            super_off: None,
+            off: rate.clone(),
+            mid: rate.clone(),
+            peak: rate,
+        }
+    }
+
+    pub fn for_period(&self, tou: TimeOfUse) -> &BigDecimal {
+        match tou {
+            TimeOfUse::SuperOff => self.super_off.as_ref().unwrap_or(&self.off),
+            TimeOfUse::Off => &self.off,
+            TimeOfUse::Mid => &self.mid,
+            TimeOfUse::Peak => &self.peak,
+        }
+    }
+
+    /// The rates that are set, starting with the super off-peak rate if there is one.
+    pub fn all(&self) -> impl Iterator<Item = &BigDecimal> {
+        self.super_off
+            .iter()
+            .chain([&self.off, &self.mid, &self.peak])
+    }
+
+    /// All the rates multiplied by `factor`.
+    pub fn scaled(&self, factor: &BigDecimal) -> Self {
+        Self {
+            super_off: self.super_off.as_ref().map(|rate| rate * factor),
+            off: &self.off * factor,
+            mid: &self.mid * factor,
+            peak: &self.peak * factor,
+        }
+    }
+
+    /// The simple average of the rates that are set, rounded to a millionth of a dollar.
+    pub fn average(&self) -> BigDecimal {
+        let count = self.all().count();
+        (self.all().sum::<BigDecimal>() / BigDecimal::from(count as u64)).round(6)
+    }
+
+    /// The same rate for every period, set to [TouRates::average].
+    pub fn averaged(&self) -> Self {
+        Self::uniform(self.average())
+    }
+}
+
+/// A current rate that charges the first `tier1_limit` KWH of a billing period at `tier1_rate`,
+/// and the rest at `tier2_rate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TieredRate {
+    pub tier1_rate: BigDecimal,
+    pub tier1_limit: BigDecimal,
+    pub tier2_rate: BigDecimal,
+    /// The day of the month billing periods start on, from 1 to 28. The tiers start over in each
+    /// billing period.
+    pub billing_day: i8,
+}
+
+impl TieredRate {
+    /// The charge for using `kwh` over a billing period. Negative usage is credited at the tier 1
+    /// rate.
+    pub fn charge(&self, kwh: &BigDecimal) -> BigDecimal {
+        if *kwh <= self.tier1_limit {
+            kwh * &self.tier1_rate
+        } else {
+            &self.tier1_limit * &self.tier1_rate + (kwh - &self.tier1_limit) * &self.tier2_rate
+        }
+    }
+}
+
+/// The non-TOU rate that usage is currently charged at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CurrentRate {
+    /// The same rate for every KWH.
+    Flat(BigDecimal),
+    Tiered(TieredRate),
+}
+
+/// May through September.
+const DEFAULT_SUMMER_MONTHS: (i8, i8) = (5, 9);
+
+/// TOU rates that may differ in the summer months.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeasonalTouRates {
+    pub winter: TouRates,
+    pub summer: TouRates,
+    /// The first and last months, from 1 to 12, that the summer rates apply in. If the first month
+    /// is after the last, summer wraps around the new year.
+    pub summer_months: (i8, i8),
+}
+
+impl SeasonalTouRates {
+    /// The same rates all year.
+    pub fn flat(rates: TouRates) -> Self {
+        Self {
+            summer: rates.clone(),
+            winter: rates,
+            summer_months: DEFAULT_SUMMER_MONTHS,
+        }
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.summer == self.winter
+    }
+
+    pub fn is_summer(&self, date: Date) -> bool {
+        let (first, last) = self.summer_months;
+        let month = date.month();
+        if first <= last {
+            (first..=last).contains(&month)
+        } else {
+            month >= first || month <= last
+        }
+    }
+
+    pub fn for_date(&self, date: Date) -> &TouRates {
+        if self.is_summer(date) {
+            &self.summer
+        } else {
+            &self.winter
+        }
+    }
+
+    /// All the rates, in both seasons, multiplied by `factor`.
+    pub fn scaled(&self, factor: &BigDecimal) -> Self {
+        Self {
+            winter: self.winter.scaled(factor),
+            summer: self.summer.scaled(factor),
+            summer_months: self.summer_months,
+        }
+    }
+
+    /// Each season's rates replaced with their average, see [TouRates::averaged].
+    pub fn averaged(&self) -> Self {
+        Self {
+            winter: self.winter.averaged(),
+            summer: self.summer.averaged(),
+            summer_months: self.summer_months,
+        }
+    }
+}
+
+impl From<TouRates> for SeasonalTouRates {
+    fn from(rates: TouRates) -> Self {
+        Self::flat(rates)
+    }
+}
+
+/// TOU rates that change on specific dates, like when SCL updates its rates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatedTouRates {
+    /// The rates in effect before the first change.
+    pub initial: SeasonalTouRates,
+    /// The dates the rates change on and the rates from then on, in date order.
+    pub changes: Vec<(Date, SeasonalTouRates)>,
+}
+
+impl DatedTouRates {
+    /// The rates in effect on `date`.
+    pub fn in_effect(&self, date: Date) -> &SeasonalTouRates {
+        self.changes
+            .iter()
+            .rev()
+            .find(|(change, _)| *change <= date)
+            .map_or(&self.initial, |(_, rates)| rates)
+    }
+
+    /// The rates for the season on `date`, from the rates in effect on `date`.
+    pub fn for_date(&self, date: Date) -> &TouRates {
+        self.in_effect(date).for_date(date)
+    }
+
+    /// Every set of rates, starting with the initial ones.
+    pub fn all(&self) -> impl Iterator<Item = &SeasonalTouRates> {
+        std::iter::once(&self.initial).chain(self.changes.iter().map(|(_, rates)| rates))
+    }
+
+    /// Sets the rates from `date` on, replacing any change already on that date.
+    pub fn set_change(&mut self, date: Date, rates: SeasonalTouRates) {
+        match self
+            .changes
+            .binary_search_by_key(&date, |(change, _)| *change)
+        {
+            Ok(index) => self.changes[index].1 = rates,
+            Err(index) => self.changes.insert(index, (date, rates)),
+        }
+    }
+
+    /// All the rates, in every season and at every date, multiplied by `factor`.
+    pub fn scaled(&self, factor: &BigDecimal) -> Self {
+        Self {
+            initial: self.initial.scaled(factor),
+            changes: self
+                .changes
+                .iter()
+                .map(|(date, rates)| (*date, rates.scaled(factor)))
+                .collect(),
+        }
+    }
+
+    /// The rates in every season and at every date replaced with their average, see
+    /// [TouRates::averaged].
+    pub fn averaged(&self) -> Self {
+        Self {
+            initial: self.initial.averaged(),
+            changes: self
+                .changes
+                .iter()
+                .map(|(date, rates)| (*date, rates.averaged()))
+                .collect(),
+        }
+    }
+}
+
+impl From<SeasonalTouRates> for DatedTouRates {
+    fn from(rates: SeasonalTouRates) -> Self {
+        Self {
+            initial: rates,
+            changes: Vec::new(),
+        }
+    }
+}
+
+impl From<TouRates> for DatedTouRates {
+    fn from(rates: TouRates) -> Self {
+        SeasonalTouRates::flat(rates).into()
+    }
+}
+
+/// The built-in rates, embedded so there's a single place to update them when SCL does.
+const DEFAULT_RATES: &str = include_str!("default_rates.json");
+
+/// The names of the built-in plans, which can be selected without a plan file.
+pub const BUILTIN_PLANS: &[&str] = &["scl"];
+
+/// A plan: TOU rates for every location, and when each TOU period applies.
+///
+/// The config file format is a JSON object mapping location names (as given to `--tou-location`)
+/// to their off/mid/peak rates in dollars per KWH:
+///
+/// ```json
+/// { "locations": { "seattle": { "off": 0.0828, "mid": 0.1449, "peak": 0.1656 } } }
+/// ```
+///
+/// Rates may be given as JSON numbers or strings, and are always in dollars, not cents. They can
+/// be zero but not negative. When layered over another config, any location or rate left out keeps
+/// its existing value.
+///
+/// It may also replace the TOU schedule, by listing the weekday time ranges of each period:
+///
+/// ```json
+/// { "schedule": { "off": ["00:00-06:00"], "mid": ["06:00-17:00", "21:00-24:00"], "peak": ["17:00-21:00"] } }
+/// ```
+///
+/// The times can be on any minute, and every minute of the day must belong to exactly one
+/// period. Plans with a super off-peak period can give it as `super_off`, along with a
+/// `super_off` rate for each location, which is otherwise the off-peak rate. Super off-peak hours
+/// stay super off-peak on weekends and holidays. Plans without a mid-peak or peak period can
+/// leave it out of the schedule, and then their locations don't need a rate for it. Weekends are
+/// off-peak all day unless `"weekends_off_peak": false`, and so are holidays, which are either
+/// `"scl"` for the SCL observed holidays or a list of dates.
+///
+/// A location can have different rates in the summer, given as a nested `summer` object. Summer
+/// rates left out fall back to the location's all-year rates. Summer is May through September
+/// unless `summer_months` gives the first and last months:
+///
+/// ```json
+/// { "summer_months": [6, 9], "locations": { "seattle": { "summer": { "peak": 0.1956 } } } }
+/// ```
+///
+/// Rates that change on a date are given in `rate_changes`, each layered over the rates in effect
+/// before it. A change without `locations` just records the date, so that usage crossing it can
+/// be flagged:
+///
+/// ```json
+/// { "rate_changes": [{ "date": "2025-01-01", "locations": { "seattle": { "peak": 0.1756 } } }] }
+/// ```
+///
+/// The date a location's rates were known to be current on can be given as its
+/// `effective_date`, so that usage long after it can be warned about:
+///
+/// ```json
+/// { "locations": { "seattle": { "off": 0.0828, "mid": 0.1449, "peak": 0.1656, "effective_date": "2025-01-01" } } }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RatesConfig {
+    /// The rates for each location, in the order they were first given.
+    locations: Vec<(String, DatedTouRates)>,
+    schedule: TouSchedule,
+    summer_months: (i8, i8),
+    change_dates: BTreeSet<Date>,
+    /// The date each location's rates were known to be current on, for those that give one.
+    effective_dates: HashMap<String, Date>,
+}
+
+#[derive(Debug)]
+pub enum RatesConfigError {
+    Io(std::io::Error),
+    Json(json::Error),
+    Invalid(String),
+    UnknownPlan {
+        name: String,
+        available: Vec<String>,
+    },
+}
+
+impl Display for RatesConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RatesConfigError::Io(e) => write!(f, "Failed to read rates file: {}", e),
+            RatesConfigError::Json(e) => write!(f, "Rates file is not valid JSON: {}", e),
+            RatesConfigError::Invalid(message) => write!(f, "Invalid rates file: {}", message),
+            RatesConfigError::UnknownPlan { name, available } => write!(
+                f,
+                "Unknown plan {:?}, expected one of: {}",
+                name,
+                available.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RatesConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RatesConfigError::Io(e) => Some(e),
+            RatesConfigError::Json(e) => Some(e),
+            RatesConfigError::Invalid(_) | RatesConfigError::UnknownPlan { .. } => None,
+        }
+    }
+}
+
+impl RatesConfig {
+    /// The built-in SCL plan.
+    pub fn builtin() -> Self {
+        let mut config = Self::empty();
+        config
+            .merge_str(DEFAULT_RATES, true)
+            .expect("built-in rates are valid");
+        config
+    }
+
+    /// A plan with no locations, and otherwise the same as SCL's.
+    fn empty() -> Self {
+        Self {
+            locations: Vec::new(),
+            schedule: TouSchedule::scl(),
+            summer_months: DEFAULT_SUMMER_MONTHS,
+            change_dates: BTreeSet::new(),
+            effective_dates: HashMap::new(),
+        }
+    }
+
+    /// Loads the plan called `name`, either `<name>.json` in `plans_dir` or a built-in plan. A
+    /// plan file takes precedence over a built-in plan of the same name. `name` can also be the
+    /// location of a plan file, as taken by [FileRates::from_location].
+    pub fn load_plan(name: &str, plans_dir: Option<&Path>) -> Result<Self, RatesConfigError> {
+        if is_plan_location(name) {
+            return FileRates::from_location(name)?.load();
+        }
+        if let Some(dir) = plans_dir {
+            let path = dir.join(format!("{}.json", name));
+            if path.is_file() {
+                return FileRates::new(path).load();
+            }
+        }
+        match name {
+            "scl" => BuiltinRates.load(),
+            _ => Err(RatesConfigError::UnknownPlan {
+                name: name.to_string(),
+                available: available_plans(plans_dir),
+            }),
+        }
+    }
+
+    /// Reads a plan from the file at `path`. Unlike a rates file, it starts with no locations, so
+    /// it must give the rates of every location it has. Anything else it leaves out, like the
+    /// schedule, is the same as SCL's.
+    pub fn from_plan_file(path: &Path) -> Result<Self, RatesConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(RatesConfigError::Io)?;
+        let mut config = Self::empty();
+        config.merge_str(&contents, true)?;
+        if config.locations.is_empty() {
+            return Err(RatesConfigError::Invalid(
+                "a plan must have at least one location".to_string(),
+            ));
+        }
+        Ok(config)
+    }
+
+    /// The rates for the location called `name`, if the plan has it.
+    pub fn rates(&self, name: &str) -> Option<&DatedTouRates> {
+        self.locations
+            .iter()
+            .find(|(location, _)| location == name)
+            .map(|(_, rates)| rates)
+    }
+
+    /// The names of the plan's locations, in the order they were given.
+    pub fn location_names(&self) -> impl Iterator<Item = &str> {
+        self.locations.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Every date a rate change is configured on, whether or not any rates change on it.
+    pub fn change_dates(&self) -> &BTreeSet<Date> {
+        &self.change_dates
+    }
+
+    /// The date the rates of the location called `name` were known to be current on, if the plan
+    /// or rates file gives it.
+    pub fn effective_date(&self, name: &str) -> Option<Date> {
+        self.effective_dates.get(name).copied()
+    }
+
+    pub fn schedule(&self) -> &TouSchedule {
+        &self.schedule
+    }
+
+    /// Layers the rates in the file at `path` over the current ones. It can only change the rates
+    /// of locations the plan already has.
+    pub fn merge_file(&mut self, path: &Path) -> Result<(), RatesConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(RatesConfigError::Io)?;
+        self.merge_str(&contents, false)
+    }
+
+    /// Layers the config in `contents` over the current one, adding any locations it has that
+    /// aren't known yet if `new_locations` is set.
+    fn merge_str(&mut self, contents: &str, new_locations: bool) -> Result<(), RatesConfigError> {
+        let root = json::parse(contents).map_err(RatesConfigError::Json)?;
+        let entries = expect_object(&root, "top level")?;
+        // The schedule decides which rates the locations need, so it's read before them.
+        if let Some(schedule) = entries.get("schedule") {
+            self.merge_schedule(schedule)?;
+        }
+        for (key, value) in entries {
+            match key.as_str() {
+                "locations" => self.merge_locations(value, new_locations)?,
+                "schedule" => {}
+                "weekends_off_peak" => {
+                    self.schedule.weekends_off_peak = parse_bool(value, "weekends_off_peak")?
+                }
+                "holidays" => self.schedule.holidays = parse_holidays(value)?,
+                "summer_months" => self.summer_months = parse_summer_months(value)?,
+                "rate_changes" => self.merge_rate_changes(value)?,
+                _ => return Err(unknown_key(key, "top level")),
+            }
+        }
+        for (_, dated) in &mut self.locations {
+            dated.initial.summer_months = self.summer_months;
+            for (_, rates) in &mut dated.changes {
+                rates.summer_months = self.summer_months;
+            }
+        }
+        Ok(())
+    }
+
+    fn merge_locations(
+        &mut self,
+        locations: &Value,
+        new_locations: bool,
+    ) -> Result<(), RatesConfigError> {
+        for (name, rates) in expect_object(locations, "locations")? {
+            let existing = self.rates(name).map(|dated| &dated.initial);
+            if existing.is_none() && !new_locations {
+                return Err(self.unknown_location(name));
+            }
+            // The effective date goes with the location rather than its rates, which can also be
+            // given in a rate change.
+            let mut rates = expect_object(rates, name)?.clone();
+            let effective_date = rates
+                .shift_remove("effective_date")
+                .map(|date| parse_date(&date, &format!("{}.effective_date", name)))
+                .transpose()?;
+            let merged = merge_location(
+                existing,
+                name,
+                &Value::Object(rates),
+                self.summer_months,
+                &self.schedule,
+            )?;
+            match self.location_mut(name) {
+                Some(dated) => dated.initial = merged,
+                None => self.locations.push((name.clone(), merged.into())),
+            }
+            if let Some(date) = effective_date {
+                self.effective_dates.insert(name.clone(), date);
+            }
+        }
+        Ok(())
+    }
+
+    fn location_mut(&mut self, name: &str) -> Option<&mut DatedTouRates> {
+        self.locations
+            .iter_mut()
+            .find(|(location, _)| location == name)
+            .map(|(_, rates)| rates)
+    }
+
+    fn unknown_location(&self, name: &str) -> RatesConfigError {
+        let known: Vec<&str> = self.location_names().collect();
+        RatesConfigError::Invalid(format!(
+            "unknown location {:?}, expected one of: {}",
+            name,
+            known.join(", ")
+        ))
+    }
+
+    fn merge_rate_changes(&mut self, changes: &Value) -> Result<(), RatesConfigError> {
+        let Value::Array(changes) = changes else {
+            return Err(RatesConfigError::Invalid(format!(
+                "expected an array for rate_changes, found {}",
+                json::kind(changes)
+            )));
+        };
+        for change in changes {
+            let mut date = None;
+            let mut locations = None;
+            for (key, value) in expect_object(change, "rate_changes")? {
+                match key.as_str() {
+                    "date" => date = Some(parse_date(value, "rate_changes.date")?),
+                    "locations" => locations = Some(value),
+                    _ => return Err(unknown_key(key, "rate_changes")),
+                }
+            }
+            let date = date.ok_or_else(|| {
+                RatesConfigError::Invalid("a rate change is missing its date".to_string())
+            })?;
+            self.change_dates.insert(date);
+            let Some(locations) = locations else {
+                continue;
+            };
+            let context = format!("rate change on {}", date);
+            let summer_months = self.summer_months;
+            let schedule = self.schedule.clone();
+            for (name, rates) in expect_object(locations, &context)? {
+                let dated = self.location_mut(name).ok_or_else(|| {
+                    RatesConfigError::Invalid(format!(
+                        "{} has a rate change but no initial rates",
+                        name
+                    ))
+                })?;
+                let merged = merge_location(
+                    Some(dated.in_effect(date)),
+                    name,
+                    rates,
+                    summer_months,
+                    &schedule,
+                )?;
+                dated.set_change(date, merged);
+            }
+        }
+        Ok(())
+    }
+
+    fn merge_schedule(&mut self, schedule: &Value) -> Result<(), RatesConfigError> {
+        let mut minutes: [Option<TimeOfUse>; MINUTES_PER_DAY] = [None; MINUTES_PER_DAY];
+        for (key, ranges) in expect_object(schedule, "schedule")? {
+            let tou = TimeOfUse::ALL
+                .into_iter()
+                .find(|tou| tou.name() == key)
+                .ok_or_else(|| unknown_key(key, "schedule"))?;
+            let Value::Array(ranges) = ranges else {
+                return Err(RatesConfigError::Invalid(format!(
+                    "expected an array for schedule.{}, found {}",
+                    key,
+                    json::kind(ranges)
+                )));
+            };
+            for range in ranges {
+                let Value::String(range) = range else {
+                    return Err(RatesConfigError::Invalid(format!(
+                        "expected a string range in schedule.{}, found {}",
+                        key,
+                        json::kind(range)
+                    )));
+                };
+                let range = parse_time_range(range)
+                    .map_err(|e| RatesConfigError::Invalid(format!("schedule.{}: {}", key, e)))?;
+                for minute in range {
+                    if let Some(existing) = minutes[minute] {
+                        return Err(RatesConfigError::Invalid(format!(
+                            "schedule has {} in both {} and {}",
+                            format_minute(minute),
+                            existing.name(),
+                            tou.name()
+                        )));
+                    }
+                    minutes[minute] = Some(tou);
+                }
+            }
+        }
+        let mut resolved = [TimeOfUse::Off; MINUTES_PER_DAY];
+        for (minute, tou) in minutes.into_iter().enumerate() {
+            resolved[minute] = tou.ok_or_else(|| {
+                RatesConfigError::Invalid(format!(
+                    "schedule doesn't cover {}",
+                    format_minute(minute)
+                ))
+            })?;
+        }
+        self.schedule = self.schedule.with_minutes(&resolved);
+        Ok(())
+    }
+}
+
+/// Layers a location's rates from the config over its `existing` rates.
+fn merge_location(
+    existing: Option<&SeasonalTouRates>,
+    name: &str,
+    rates: &Value,
+    summer_months: (i8, i8),
+    schedule: &TouSchedule,
+) -> Result<SeasonalTouRates, RatesConfigError> {
+    let mut winter = HashMap::new();
+    let mut summer = HashMap::new();
+    for (key, rate) in expect_object(rates, name)? {
+        if key == "summer" {
+            summer = parse_period_rates(rate, &format!("{}.summer", name))?;
+            continue;
+        }
+        let tou = TimeOfUse::ALL
+            .into_iter()
+            .find(|tou| tou.name() == key)
+            .ok_or_else(|| unknown_key(key, name))?;
+        winter.insert(tou, parse_rate(rate, &format!("{}.{}", name, key))?);
+    }
+    let winter = merge_tou_rates(winter, existing.map(|rates| &rates.winter), name, schedule)?;
+    // Summer rates fall back to the existing summer rates only if they were actually different,
+    // otherwise they follow the all-year rates.
+    let existing_summer = existing
+        .filter(|rates| !rates.is_flat())
+        .map(|rates| &rates.summer)
+        .unwrap_or(&winter);
+    let summer = merge_tou_rates(
+        summer,
+        Some(existing_summer),
+        &format!("{}.summer", name),
+        schedule,
+    )?;
+    Ok(SeasonalTouRates {
+        winter,
+        summer,
+        summer_months,
+    })
+}
+
+/// Parses an object of super_off/off/mid/peak rates, any of which may be left out.
+fn parse_period_rates(
+    rates: &Value,
+    context: &str,
+) -> Result<HashMap<TimeOfUse, BigDecimal>, RatesConfigError> {
+    let mut parsed = HashMap::new();
+    for (key, rate) in expect_object(rates, context)? {
+        let tou = TimeOfUse::ALL
+            .into_iter()
+            .find(|tou| tou.name() == key)
+            .ok_or_else(|| unknown_key(key, context))?;
+        parsed.insert(tou, parse_rate(rate, &format!("{}.{}", context, key))?);
+    }
+    Ok(parsed)
+}
+
+/// Fills in any rates missing from `given` from `existing`. Only the super off-peak rate, and the
+/// rates of periods that aren't in the `schedule`, can be missing from both. Those are charged at
+/// the off-peak rate.
+fn merge_tou_rates(
+    mut given: HashMap<TimeOfUse, BigDecimal>,
+    existing: Option<&TouRates>,
+    context: &str,
+    schedule: &TouSchedule,
+) -> Result<TouRates, RatesConfigError> {
+    let super_off = given
+        .remove(&TimeOfUse::SuperOff)
+        .or_else(|| existing.and_then(|rates| rates.super_off.clone()));
+    let mut resolve = |tou: TimeOfUse, unscheduled: Option<&BigDecimal>| {
+        given
+            .remove(&tou)
+            .or_else(|| existing.map(|rates| rates.for_period(tou).clone()))
+            .or_else(|| unscheduled.filter(|_| !schedule.has_period(tou)).cloned())
+            .ok_or_else(|| {
+                RatesConfigError::Invalid(format!("{} is missing its {} rate", context, tou.name()))
+            })
+    };
+    let off = resolve(TimeOfUse::Off, None)?;
+    Ok(TouRates {
+        super_off,
+        mid: resolve(TimeOfUse::Mid, Some(&off))?,
+        peak: resolve(TimeOfUse::Peak, Some(&off))?,
+        off,
+    })
+}
+
+fn parse_summer_months(value: &Value) -> Result<(i8, i8), RatesConfigError> {
+    let invalid = || {
+        RatesConfigError::Invalid(
+            "expected summer_months to be two months from 1 to 12, like [5, 9]".to_string(),
+        )
+    };
+    let Value::Array(months) = value else {
+        return Err(invalid());
+    };
+    let months: Vec<i8> = months
+        .iter()
+        .map(|month| match month {
+            Value::Number(number) => number
+                .to_string()
+                .parse()
+                .ok()
+                .filter(|m| (1..=12).contains(m)),
+            _ => None,
+        })
+        .collect::<Option<_>>()
+        .ok_or_else(invalid)?;
+    match months[..] {
+        [first, last] => Ok((first, last)),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_bool(value: &Value, context: &str) -> Result<bool, RatesConfigError> {
+    match value {
+        Value::Bool(value) => Ok(*value),
+        other => Err(RatesConfigError::Invalid(format!(
+            "expected true or false for {}, found {}",
+            context,
+            json::kind(other)
+        ))),
+    }
+}
+
+/// Parses `"scl"` for the SCL observed holidays, or a list of holiday dates.
+fn parse_holidays(value: &Value) -> Result<Holidays, RatesConfigError> {
+    match value {
+        Value::String(name) if name == "scl" => Ok(Holidays::Scl),
+        Value::Array(dates) => Ok(Holidays::Custom(
+            dates
+                .iter()
+                .map(|date| parse_date(date, "holidays"))
+                .collect::<Result<_, _>>()?,
+        )),
+        other => Err(RatesConfigError::Invalid(format!(
+            "expected \"scl\" or a list of dates for holidays, found {}",
+            json::kind(other)
+        ))),
+    }
+}
+
+fn parse_date(value: &Value, context: &str) -> Result<Date, RatesConfigError> {
+    match value {
+        Value::String(text) => text
+            .parse()
+            .map_err(|e| RatesConfigError::Invalid(format!("invalid date for {}: {}", context, e))),
+        other => Err(RatesConfigError::Invalid(format!(
+            "expected a YYYY-MM-DD string for {}, found {}",
+            context,
+            json::kind(other)
+        ))),
+    }
+}
+
+/// Somewhere a plan can be loaded from.
+///
+/// Every source gives the plan in the JSON format described on [RatesConfig], so a source that
+/// fetches the rates from the utility can be added without changing how they're read.
+pub trait RateSource {
+    /// Where the plan comes from, for messages.
+    fn describe(&self) -> String;
+
+    /// Loads the plan.
+    fn load(&self) -> Result<RatesConfig, RatesConfigError>;
+}
+
+/// The built-in SCL plan, embedded in the program.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BuiltinRates;
+
+impl RateSource for BuiltinRates {
+    fn describe(&self) -> String {
+        "the built-in SCL rates".to_string()
+    }
+
+    fn load(&self) -> Result<RatesConfig, RatesConfigError> {
+        Ok(RatesConfig::builtin())
+    }
+}
+
+/// A plan file, as read by [RatesConfig::from_plan_file].
+#[derive(Debug, Clone)]
+pub struct FileRates {
+    path: PathBuf,
+}
+
+impl FileRates {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The plan file at `location`, which is either a path or a `file://` URL. Other URLs aren't
+    /// supported yet, since the rates aren't published anywhere to fetch them from.
+    pub fn from_location(location: &str) -> Result<Self, RatesConfigError> {
+        if let Some(path) = location.strip_prefix("file://") {
+            return Ok(Self::new(path));
+        }
+        match location.split_once("://") {
+            Some((scheme, _)) => Err(RatesConfigError::Invalid(format!(
+                "can't load rates from {:?}: {} URLs aren't supported, so download the file and give its path instead",
+                location, scheme
+            ))),
+            None => Ok(Self::new(location)),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl RateSource for FileRates {
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn load(&self) -> Result<RatesConfig, RatesConfigError> {
+        RatesConfig::from_plan_file(&self.path)
+    }
+}
+
+/// Whether a `--plan` is the location of a plan file rather than the name of one, which can't
+/// have a path separator or URL scheme in it.
+fn is_plan_location(plan: &str) -> bool {
+    plan.contains("://") || plan.contains(['/', std::path::MAIN_SEPARATOR])
+}
+
+/// The names of every plan that can be loaded: the built-in plans, and any in `plans_dir`.
+pub fn available_plans(plans_dir: Option<&Path>) -> Vec<String> {
+    let mut plans: BTreeSet<String> = BUILTIN_PLANS.iter().map(|name| name.to_string()).collect();
+    if let Some(entries) = plans_dir.and_then(|dir| std::fs::read_dir(dir).ok()) {
+        for path in entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+        {
+            if path.extension().is_some_and(|ext| ext == "json")
+                && let Some(name) = path.file_stem().and_then(|stem| stem.to_str())
+            {
+                plans.insert(name.to_string());
+            }
+        }
+    }
+    plans.into_iter().collect()
+}
+
+fn expect_object<'a>(
+    value: &'a Value,
+    context: &str,
+) -> Result<&'a Map<String, Value>, RatesConfigError> {
+    match value {
+        Value::Object(entries) => Ok(entries),
+        other => Err(RatesConfigError::Invalid(format!(
+            "expected an object for {}, found {}",
+            context,
+            json::kind(other)
+        ))),
+    }
+}
+
+fn unknown_key(key: &str, context: &str) -> RatesConfigError {
+    RatesConfigError::Invalid(format!("unknown key {:?} in {}", key, context))
+}
+
+fn parse_rate(value: &Value, context: &str) -> Result<BigDecimal, RatesConfigError> {
+    let text = match value {
+        Value::Number(number) => &number.to_string(),
+        Value::String(text) => text,
+        other => {
+            return Err(RatesConfigError::Invalid(format!(
+                "expected a number for {}, found {}",
+                context,
+                json::kind(other)
+            )));
+        }
+    };
+    let rate = BigDecimal::from_str(text)
+        .map_err(|e| RatesConfigError::Invalid(format!("invalid rate for {}: {}", context, e)))?;
+    // A rate of zero is allowed, for plans with free hours.
+    if rate < BigDecimal::from(0) {
+        return Err(RatesConfigError::Invalid(format!(
+            "rate for {} can't be negative, got {}",
+            context, text
+        )));
+    }
+    Ok(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jiff::civil::{date, time};
+
+    #[test]
+    fn custom_schedule_classifies_its_own_peak() {
+        let mut config = RatesConfig::builtin();
+        config
+            .merge_str(
+                r#"{ "schedule": {
+                    "off": ["00:00-06:00"],
+                    "mid": ["06:00-14:00", "19:00-24:00"],
+                    "peak": ["14:00-19:00"]
+                } }"#,
+                false,
+            )
+            .unwrap();
+        let schedule = config.schedule();
+        for (hour, minute, expected) in [
+            (5, 59, TimeOfUse::Off),
+            (6, 0, TimeOfUse::Mid),
+            (13, 59, TimeOfUse::Mid),
+            (14, 0, TimeOfUse::Peak),
+            (16, 0, TimeOfUse::Peak),
+            (18, 59, TimeOfUse::Peak),
+            (19, 0, TimeOfUse::Mid),
+            (20, 0, TimeOfUse::Mid),
+        ] {
+            let tou = TimeOfUse::from_time(time(hour, minute, 0, 0), schedule);
+            assert_eq!(tou, expected, "{:02}:{:02}", hour, minute);
+        }
+        // Weekends are still off-peak all day.
+        let saturday = date(2024, 3, 2);
+        let tou = TimeOfUse::from_date_time(saturday, time(15, 0, 0, 0), schedule);
+        assert_eq!(tou, TimeOfUse::Off);
+    }
+
+    #[test]
+    fn rates_file_only_overrides_the_rates_it_gives() {
+        let builtin = RatesConfig::builtin();
+        let mut config = RatesConfig::builtin();
+        config
+            .merge_file(Path::new("tests/fixtures/seattle_peak_rates.json"))
+            .unwrap();
+        let seattle = config.rates("seattle").unwrap();
+        let builtin_seattle = builtin.rates("seattle").unwrap();
+        assert_eq!(
+            seattle.initial.winter.peak,
+            BigDecimal::from_str("0.1756").unwrap()
+        );
+        assert_eq!(seattle.initial.summer, seattle.initial.winter);
+        assert_eq!(
+            seattle.initial.winter.off,
+            builtin_seattle.initial.winter.off
+        );
+        assert_eq!(
+            seattle.initial.winter.mid,
+            builtin_seattle.initial.winter.mid
+        );
+        assert_eq!(
+            config.location_names().collect::<Vec<_>>(),
+            builtin.location_names().collect::<Vec<_>>()
+        );
+        for name in builtin.location_names().filter(|&name| name != "seattle") {
+            assert_eq!(config.rates(name), builtin.rates(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rates_file_cant_add_locations() {
+        let mut config = RatesConfig::builtin();
+        let error = config
+            .merge_str(r#"{ "locations": { "bothell": { "peak": 0.2 } } }"#, false)
+            .unwrap_err();
+        assert!(error.to_string().contains("bothell"), "{}", error);
+    }
+
+    #[test]
+    fn plans_can_be_loaded_from_a_path_or_file_url() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/golden_rates.json"
+        );
+        for location in [path.to_string(), format!("file://{}", path)] {
+            let config = RatesConfig::load_plan(&location, None).unwrap();
+            assert_eq!(config.location_names().collect::<Vec<_>>(), ["seattle"]);
+        }
+        match RatesConfig::load_plan("https://example.com/rates.json", None) {
+            Err(RatesConfigError::Invalid(message)) => {
+                assert!(
+                    message.contains("https URLs aren't supported"),
+                    "{}",
+                    message
+                )
+            }
+            result => panic!("expected an unsupported URL error, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn each_location_has_its_own_effective_date() {
+        let mut config = RatesConfig::builtin();
+        assert_eq!(config.effective_date("seattle"), Some(date(2025, 1, 1)));
+        config
+            .merge_str(
+                r#"{ "locations": {
+                    "seattle": { "peak": 0.1756, "effective_date": "2025-10-01" },
+                    "bothell": { "off": 0.08, "mid": 0.14, "peak": 0.16 }
+                } }"#,
+                true,
+            )
+            .unwrap();
+        assert_eq!(config.effective_date("seattle"), Some(date(2025, 10, 1)));
+        assert_eq!(config.effective_date("renton"), Some(date(2025, 1, 1)));
+        assert_eq!(config.effective_date("bothell"), None);
+        assert_eq!(
+            merge_error(r#"{ "locations": { "renton": { "effective_date": 20250101 } } }"#),
+            "Invalid rates file: expected a YYYY-MM-DD string for renton.effective_date, found number"
+        );
+    }
+
+    /// The message of the error from layering `contents` over the built-in plan.
+    fn merge_error(contents: &str) -> String {
+        let mut config = RatesConfig::builtin();
+        config.merge_str(contents, true).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn negative_rates_are_rejected() {
+        assert_eq!(
+            merge_error(r#"{ "locations": { "seattle": { "peak": -0.1656 } } }"#),
+            "Invalid rates file: rate for seattle.peak can't be negative, got -0.1656"
+        );
+        assert_eq!(
+            merge_error(r#"{ "locations": { "seattle": { "summer": { "off": "-0.01" } } } }"#),
+            "Invalid rates file: rate for seattle.summer.off can't be negative, got -0.01"
+        );
+        assert_eq!(
+            merge_error(r#"{ "locations": { "seattle": { "mid": "cheap" } } }"#),
+            "Invalid rates file: invalid rate for seattle.mid: invalid digit found in string"
+        );
+        // Free hours are fine.
+        let mut config = RatesConfig::builtin();
+        config
+            .merge_str(r#"{ "locations": { "seattle": { "off": 0 } } }"#, false)
+            .unwrap();
+        assert_eq!(
+            config.rates("seattle").unwrap().initial.winter.off,
+            BigDecimal::from(0)
+        );
+    }
+
+    #[test]
+    fn new_locations_need_a_rate_for_every_scheduled_period() {
+        assert_eq!(
+            merge_error(r#"{ "locations": { "bothell": { "off": 0.08, "mid": 0.14 } } }"#),
+            "Invalid rates file: bothell is missing its peak rate"
+        );
+        assert_eq!(
+            merge_error(
+                r#"{ "locations": { "bothell": { "off": 0.08, "summer": { "peak": 0.2 } } } }"#
+            ),
+            "Invalid rates file: bothell is missing its mid rate"
+        );
+        // Without a mid-peak period in the schedule, a location doesn't need a mid-peak rate.
+        let mut config = RatesConfig::builtin();
+        config
+            .merge_str(
+                r#"{
+                    "schedule": { "off": ["00:00-17:00", "21:00-24:00"], "peak": ["17:00-21:00"] },
+                    "locations": { "bothell": { "off": 0.08, "peak": 0.16 } }
+                }"#,
+                true,
+            )
+            .unwrap();
+        let rates = &config.rates("bothell").unwrap().initial.winter;
+        assert_eq!(rates.mid, rates.off);
+    }
+
+    #[test]
+    fn schedule_must_cover_every_minute_once() {
+        assert_eq!(
+            merge_error(
+                r#"{ "schedule": { "off": ["00:00-06:00"], "mid": ["06:00-17:00"], "peak": ["17:00-21:00"] } }"#
+            ),
+            "Invalid rates file: schedule doesn't cover 21:00"
+        );
+        assert_eq!(
+            merge_error(
+                r#"{ "schedule": { "off": ["00:00-06:30"], "mid": ["06:00-17:00", "21:00-24:00"], "peak": ["17:00-21:00"] } }"#
+            ),
+            "Invalid rates file: schedule has 06:00 in both off and mid"
+        );
+        assert_eq!(
+            merge_error(
+                r#"{ "schedule": { "off": ["00:00-24:00"], "evening": ["17:00-21:00"] } }"#
+            ),
+            "Invalid rates file: unknown key \"evening\" in schedule"
+        );
+    }
+}
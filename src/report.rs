@@ -0,0 +1,1334 @@
+//! The results of the comparison, as text for people and as JSON summaries for programs.
+
+use crate::output::{self, output, output_info, output_verbose};
+use crate::{CompareArgs, OnlyValue, OutputFormat, ScenariosArgs};
+use bigdecimal::{BigDecimal, ToPrimitive};
+use jiff::civil::{Date, Time};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use ttmbuwyntcstr::analysis::{ShiftBreakeven, Stats, blended_rate, hourly_profile};
+use ttmbuwyntcstr::comparison::{
+    Breakeven, DailyCost, DayExplanation, DayTypeCost, Details, MonthCost, PeakDay, PeakDayRanking,
+    ScenarioComparison, UsageStats, Verdict,
+};
+use ttmbuwyntcstr::currency::CurrencyFormat;
+use ttmbuwyntcstr::holidays::Holidays;
+use ttmbuwyntcstr::json;
+use ttmbuwyntcstr::rate_calculator::{TouBreakdown, split_by_period, split_usage_by_period};
+use ttmbuwyntcstr::rates::RatesConfig;
+use ttmbuwyntcstr::schedule::format_minute;
+use ttmbuwyntcstr::{
+    AnalysisResult, CostOptions, DatedTouRates, SeasonalTouRates, TimeOfUse, TouRates, TouSchedule,
+    UsageEntry,
+};
+
+/// The version of the JSON output's fields, see `--format`.
+const JSON_SCHEMA_VERSION: &str = "1.17";
+
+/// Prints the `rates` of every location in the plan, and when each TOU period applies.
+pub fn print_rates(rates_config: &RatesConfig, schedule: &TouSchedule, currency: &CurrencyFormat) {
+    let periods = schedule.shown_periods();
+    let header: Vec<String> = periods
+        .iter()
+        .map(|tou| format!("{:>10}", tou.name()))
+        .collect();
+    println!("{:<32} {}", "Location", header.join(" "));
+    let row = |label: &str, rates: &TouRates| {
+        let cells: Vec<String> = periods
+            .iter()
+            .map(|&tou| format!("{:>10}", currency.format(rates.for_period(tou), 4)))
+            .collect();
+        println!("{:<32} {}", label, cells.join(" "));
+    };
+    for name in rates_config.location_names() {
+        let rates = rates_config.rates(name).expect("location is in the plan");
+        let seasons = std::iter::once((name.to_string(), &rates.initial)).chain(
+            rates
+                .changes
+                .iter()
+                .map(|(date, rates)| (format!("{} from {}", name, date), rates)),
+        );
+        for (label, rates) in seasons {
+            if rates.is_flat() {
+                row(&label, &rates.winter);
+            } else {
+                row(&format!("{} (winter)", label), &rates.winter);
+                row(&format!("{} (summer)", label), &rates.summer);
+            }
+        }
+    }
+    if let Some(rates) = rates_config
+        .location_names()
+        .filter_map(|name| rates_config.rates(name))
+        .flat_map(|rates| rates.all())
+        .find(|rates| !rates.is_flat())
+    {
+        let (first, last) = rates.summer_months;
+        println!(
+            "Summer rates apply from month {} through month {}.",
+            first, last
+        );
+    }
+    let ranges: Vec<String> = schedule
+        .periods()
+        .map(|(minutes, tou)| {
+            format!(
+                "{}-{} {}",
+                format_minute(minutes.start),
+                format_minute(minutes.end),
+                tou.name()
+            )
+        })
+        .collect();
+    println!("Weekday schedule: {}", ranges.join(", "));
+    // The rates are usually all as of the same date, which only needs saying once.
+    let mut effective_dates: BTreeMap<Date, Vec<&str>> = BTreeMap::new();
+    let mut undated = false;
+    for name in rates_config.location_names() {
+        match rates_config.effective_date(name) {
+            Some(date) => effective_dates.entry(date).or_default().push(name),
+            None => undated = true,
+        }
+    }
+    match effective_dates.keys().next() {
+        Some(date) if effective_dates.len() == 1 && !undated => {
+            println!("The rates are as of {}.", date);
+        }
+        _ => {
+            for (date, names) in &effective_dates {
+                println!("The {} rates are as of {}.", names.join(", "), date);
+            }
+        }
+    }
+    let off_peak_days = match (schedule.weekends_off_peak, &schedule.holidays) {
+        (true, Holidays::Scl) => "Weekends and SCL holidays are",
+        (true, Holidays::Custom(_)) => "Weekends and the given holidays are",
+        (true, Holidays::SclAnd(_)) => "Weekends, SCL holidays and the given holidays are",
+        (false, Holidays::Scl) => "SCL holidays are",
+        (false, Holidays::Custom(_)) => "The given holidays are",
+        (false, Holidays::SclAnd(_)) => "SCL holidays and the given holidays are",
+    };
+    if schedule.has_period(TimeOfUse::SuperOff) {
+        println!(
+            "{} off-peak outside of super off-peak hours.",
+            off_peak_days
+        );
+    } else {
+        println!("{} off-peak all day.", off_peak_days);
+    }
+}
+
+/// The `--format json` summary of `scenarios`.
+#[derive(Serialize)]
+struct ScenariosSummary<'a> {
+    schema_version: &'static str,
+    #[serde(with = "json::decimal")]
+    total_kwh: BigDecimal,
+    scenarios: [ScenarioSummary<'a>; 2],
+    #[serde(with = "json::decimal")]
+    difference: BigDecimal,
+}
+
+#[derive(Serialize)]
+struct ScenarioSummary<'a> {
+    label: &'a str,
+    #[serde(with = "json::decimal")]
+    tou_cost: &'a BigDecimal,
+}
+
+/// Prints how the two scenarios of `scenarios` compare, as text or a JSON summary.
+pub fn print_scenarios(
+    args: &ScenariosArgs,
+    comparison: &ScenarioComparison,
+    total_kwh: BigDecimal,
+    currency: &CurrencyFormat,
+) {
+    let difference = comparison.difference();
+    match args.format {
+        OutputFormat::Text => {
+            output_info!("Total KWH used: {:.2}", total_kwh);
+            output!(
+                "{} TOU cost: {}",
+                args.label_a,
+                currency.format(&comparison.a_cost, 2)
+            );
+            output!(
+                "{} TOU cost: {}",
+                args.label_b,
+                currency.format(&comparison.b_cost, 2)
+            );
+            match difference.cmp(&BigDecimal::from(0)) {
+                Ordering::Less => output!(
+                    "{} would cost {} less than {}.",
+                    args.label_b,
+                    currency.format(&-difference, 2),
+                    args.label_a
+                ),
+                Ordering::Greater => output!(
+                    "{} would cost {} more than {}.",
+                    args.label_b,
+                    currency.format(&difference, 2),
+                    args.label_a
+                ),
+                Ordering::Equal => {
+                    output!("{} and {} would cost the same.", args.label_a, args.label_b)
+                }
+            }
+        }
+        OutputFormat::Json => {
+            let summary = ScenariosSummary {
+                schema_version: JSON_SCHEMA_VERSION,
+                total_kwh,
+                scenarios: [
+                    ScenarioSummary {
+                        label: &args.label_a,
+                        tou_cost: &comparison.a_cost,
+                    },
+                    ScenarioSummary {
+                        label: &args.label_b,
+                        tou_cost: &comparison.b_cost,
+                    },
+                ],
+                difference,
+            };
+            output!("{}", json::to_string(&summary));
+        }
+    }
+}
+
+/// Prints the result of comparing the current rate with one set of TOU rates, as text or a JSON
+/// summary.
+pub fn print_result(
+    args: &CompareArgs,
+    result: &AnalysisResult,
+    flagged: &[UsageEntry],
+    currency: &CurrencyFormat,
+) {
+    match args.format {
+        OutputFormat::Text => print_summary(args, result, currency),
+        OutputFormat::Json => {
+            let flagged = args.show_flagged.then_some(flagged);
+            output!("{}", json::to_string(&Summary::new(result, flagged)));
+        }
+    }
+    if let Some(breakeven) = &result.details.breakeven
+        && args.format == OutputFormat::Text
+    {
+        print_breakeven(breakeven, currency);
+    }
+}
+
+/// Prints just the `--only` value of the result, with nothing else so scripts can use it as is.
+pub fn print_only(only: OnlyValue, decimals: Option<i64>, result: &AnalysisResult) {
+    let savings = result.costs.savings();
+    let total_kwh = result.total_kwh();
+    let value = match only {
+        OnlyValue::CurrentCost => &result.costs.current,
+        OnlyValue::TouCost => &result.costs.tou,
+        OnlyValue::Savings => &savings,
+        OnlyValue::TotalKwh => &total_kwh,
+    };
+    let value = match decimals {
+        Some(decimals) => value.round(decimals),
+        None => value.normalized(),
+    };
+    println!("{}", value.to_plain_string());
+}
+
+/// Prints the `--show-flagged`, `--histogram` and `--stats` details of the usage as text.
+pub fn print_details(args: &CompareArgs, details: &Details, flagged: &[UsageEntry]) {
+    if args.show_flagged {
+        print_flagged(flagged);
+    }
+    if let Some(histogram) = &details.histogram {
+        print_histogram(histogram);
+    }
+    if let Some(stats) = &details.stats {
+        print_stats(stats);
+    }
+}
+
+fn print_summary(args: &CompareArgs, result: &AnalysisResult, currency: &CurrencyFormat) {
+    let details = &result.details;
+    let charges = &result.charges;
+    let costs = &result.costs;
+    if let Some(daily) = &details.daily {
+        print_daily_table(daily, currency);
+    }
+    if let Some(by_day_type) = &details.by_day_type {
+        print_day_type_table(by_day_type, currency);
+    }
+    if let Some(year_over_year) = &details.year_over_year {
+        print_year_over_year_table(year_over_year, currency);
+    }
+    if let Some(top_peak_days) = &details.top_peak_days {
+        print_top_peak_days(top_peak_days, args.rank_peak_days_by, currency);
+    }
+    if args.by_period
+        && let Some(current_by_period) = &result.current_by_period
+    {
+        print_period_comparison(result, current_by_period, currency);
+    }
+    output_info!("Total KWH used: {:.2}", result.total_kwh());
+    output_info!(
+        "Total KWH imported: {:.2}, exported: {:.2}",
+        result.imported,
+        result.exported
+    );
+    let by_period: Vec<String> = result
+        .periods
+        .iter()
+        .map(|period| match &period.kwh_share {
+            Some(share) => format!(
+                "{} {:.2} KWH ({:.0}%)",
+                period.tou.name(),
+                period.kwh,
+                share * BigDecimal::from(100)
+            ),
+            None => format!("{} {:.2} KWH", period.tou.name(), period.kwh),
+        })
+        .collect();
+    output_info!("KWH by TOU period: {}", by_period.join(", "));
+    let intervals: Vec<String> = result
+        .periods
+        .iter()
+        .map(|period| format!("{} {}", period.tou.name(), period.entries))
+        .collect();
+    output_verbose!(
+        "Usage entries by the TOU period they start in: {}",
+        intervals.join(", ")
+    );
+    if args.base_charge_per_day.is_some() {
+        output_info!(
+            "Fixed base service charge: {} over {} days",
+            currency.format(&charges.base_charge, 2),
+            result.days
+        );
+    }
+    if let Some(fee) = &args.solar_meter_fee {
+        if charges.solar_meter_fee_per_period == BigDecimal::from(0) {
+            output_info!("Solar meter fee: not charged, since the usage data has no exports");
+        } else {
+            output_info!(
+                "Solar meter fee: {} over {} billing periods at {} each",
+                currency.format(&charges.solar_meter_fee, 2),
+                charges.billing_periods,
+                currency.format(fee, 2)
+            );
+        }
+    }
+    if args.demand_charge_per_kw.is_some() {
+        let highest = charges
+            .peak_demand
+            .values()
+            .max()
+            .cloned()
+            .unwrap_or_default();
+        output_info!(
+            "TOU demand charge: {} over {} billing periods, with a peak demand of up to {:.2} kW",
+            currency.format(&charges.demand_charge, 2),
+            charges.peak_demand.len(),
+            highest
+        );
+        for (start, kw) in &charges.peak_demand {
+            output_verbose!("  billing period from {}: {:.2} kW", start, kw);
+        }
+    }
+    if args.no_export_netting {
+        output_info!(
+            "Netting each billing period leaves {} of export credit unused under the current rate, and {} under TOU",
+            currency.format(&charges.unused_credit.current, 2),
+            currency.format(&charges.unused_credit.tou, 2)
+        );
+    }
+    if let Some(minimum) = &args.minimum_bill {
+        let charge = &charges.minimum_charge;
+        output_info!(
+            "Minimum bill of {} per billing period: adds {} to the current cost over {} billing periods, and {} to the TOU cost over {}",
+            currency.format(minimum, 2),
+            currency.format(&charge.current, 2),
+            charge.current_periods,
+            currency.format(&charge.tou, 2),
+            charge.tou_periods
+        );
+    }
+    let describe_cost = |cost: &BigDecimal, import_cost: &BigDecimal, credit: &BigDecimal| {
+        if result.exported == BigDecimal::from(0) {
+            currency.format(cost, 2)
+        } else {
+            format!(
+                "{} ({} before a {} export credit)",
+                currency.format(cost, 2),
+                currency.format(import_cost, 2),
+                currency.format(credit, 2)
+            )
+        }
+    };
+    output_info!(
+        "Current cost: {}",
+        describe_cost(
+            &costs.current,
+            &costs.current_import,
+            &costs.current_export_credit()
+        )
+    );
+    output_info!(
+        "TOU cost: {}",
+        describe_cost(&costs.tou, &costs.tou_import, &costs.tou_export_credit())
+    );
+    for period in &result.periods {
+        output_verbose!(
+            "  {}: {:.2} KWH, {}",
+            period.tou.name(),
+            period.kwh,
+            currency.format(&period.tou_cost, 2)
+        );
+    }
+    let timing = match costs.tou.cmp(&costs.average_rate_tou) {
+        Ordering::Less => format!(
+            ", so your usage leans off-peak, saving {}",
+            currency.format(&(&costs.average_rate_tou - &costs.tou), 2)
+        ),
+        Ordering::Greater => format!(
+            ", so your usage leans toward peak, costing {} more",
+            currency.format(&(&costs.tou - &costs.average_rate_tou), 2)
+        ),
+        Ordering::Equal => String::new(),
+    };
+    output_info!(
+        "TOU cost at the average TOU rate: {}{}",
+        currency.format(&costs.average_rate_tou, 2),
+        timing
+    );
+    let describe_blended_rate = |rate: Option<BigDecimal>| match rate {
+        Some(rate) => format!("{}/KWH", currency.format(&rate, 4)),
+        None => "n/a".to_string(),
+    };
+    output_info!(
+        "Effective rate: current {}, TOU {}",
+        describe_blended_rate(result.current_blended_rate()),
+        describe_blended_rate(result.tou_blended_rate())
+    );
+    let percent = match costs.savings_share() {
+        Some(share) => format!(" ({:.1}%)", share.abs() * BigDecimal::from(100)),
+        None => String::new(),
+    };
+    let (line, color) = match costs.verdict() {
+        Verdict::Saves => (
+            format!(
+                "You would save {}{} by switching to TOU rates!",
+                currency.format(&costs.savings(), 2),
+                percent
+            ),
+            Some(output::Color::Green),
+        ),
+        Verdict::CostsMore => (
+            format!(
+                "You would pay {}{} more by switching to TOU rates!",
+                currency.format(&-costs.savings(), 2),
+                percent
+            ),
+            Some(output::Color::Red),
+        ),
+        Verdict::Same => (
+            "You would pay the same amount with TOU rates. Try another bill?".to_string(),
+            None,
+        ),
+    };
+    output!("{}", output::colored(color, &line));
+    if let (Some(amount), Some(fixed_cost)) = (&args.fixed_monthly, &details.fixed_monthly_cost) {
+        let compared_to = |cost: &BigDecimal| match fixed_cost.cmp(cost) {
+            Ordering::Less => format!("{} less than", currency.format(&(cost - fixed_cost), 2)),
+            Ordering::Greater => {
+                format!("{} more than", currency.format(&(fixed_cost - cost), 2))
+            }
+            Ordering::Equal => "the same as".to_string(),
+        };
+        output!(
+            "A fixed bill of {} a month would total {} over {} billing periods, {} the current cost and {} the TOU cost.",
+            currency.format(amount, 2),
+            currency.format(fixed_cost, 2),
+            charges.billing_periods,
+            compared_to(&costs.current),
+            compared_to(&costs.tou)
+        );
+    }
+    if let (Some(fraction), Some(shifted_tou_cost)) =
+        (&args.shift_peak_to_off, &details.shifted_tou_cost)
+    {
+        output!(
+            "With {:.0}% of peak usage shifted to off-peak, TOU would cost {}, saving {} compared to your current cost.",
+            fraction * BigDecimal::from(100),
+            currency.format(shifted_tou_cost, 2),
+            currency.format(&(&costs.current - shifted_tou_cost), 2)
+        );
+    }
+    match &details.shift_breakeven {
+        Some(ShiftBreakeven::AlreadyBeneficial) => {
+            output!("Peak usage to shift to off-peak to break even: 0 — already beneficial")
+        }
+        Some(ShiftBreakeven::Shift { kwh, fraction }) => output!(
+            "Peak usage to shift to off-peak to break even: {:.2} KWH ({:.1}% of peak usage)",
+            kwh,
+            fraction * BigDecimal::from(100)
+        ),
+        Some(ShiftBreakeven::Unreachable) => output!(
+            "Even shifting all peak usage to off-peak wouldn't make TOU cost as little as your current rate."
+        ),
+        None => {}
+    }
+    if let Some(annualized_savings) = &details.annualized_savings {
+        output!(
+            "Savings over the {} days covered: {}, annualized: {} per year",
+            result.covered_days(),
+            currency.format(&costs.savings(), 2),
+            currency.format(annualized_savings, 2)
+        );
+    }
+}
+
+/// The `--format json` summary of a comparison.
+#[derive(Serialize)]
+struct Summary<'a> {
+    schema_version: &'static str,
+    #[serde(with = "json::decimal")]
+    total_kwh: BigDecimal,
+    #[serde(with = "json::decimal")]
+    imported_kwh: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    exported_kwh: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    fixed_charge: BigDecimal,
+    #[serde(with = "json::decimal")]
+    solar_meter_fee: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    demand_charge: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    current_minimum_charge: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    tou_minimum_charge: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    current_unused_credit: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    tou_unused_credit: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    current_cost: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    current_import_cost: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    current_export_credit: BigDecimal,
+    #[serde(with = "json::decimal")]
+    tou_cost: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    tou_import_cost: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    tou_export_credit: BigDecimal,
+    #[serde(rename = "average_rate_tou_cost", with = "json::decimal")]
+    average_rate_tou: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    savings: BigDecimal,
+    #[serde(with = "json::optional_decimal")]
+    savings_share: Option<BigDecimal>,
+    #[serde(with = "json::optional_decimal")]
+    current_blended_rate: Option<BigDecimal>,
+    #[serde(with = "json::optional_decimal")]
+    tou_blended_rate: Option<BigDecimal>,
+    #[serde(
+        with = "json::optional_decimal",
+        skip_serializing_if = "Option::is_none"
+    )]
+    fixed_monthly_cost: Option<&'a BigDecimal>,
+    #[serde(
+        with = "json::optional_decimal",
+        skip_serializing_if = "Option::is_none"
+    )]
+    shifted_tou_cost: Option<&'a BigDecimal>,
+    #[serde(flatten)]
+    shift_breakeven: Option<ShiftBreakevenSummary>,
+    #[serde(
+        with = "json::optional_decimal",
+        skip_serializing_if = "Option::is_none"
+    )]
+    annualized_savings: Option<&'a BigDecimal>,
+    #[serde(serialize_with = "json::map")]
+    periods: Vec<(&'static str, PeriodSummary<'a>)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    daily: Option<&'a [DailyCost]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    by_day_type: Option<&'a [DayTypeCost]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    year_over_year: Option<&'a [MonthCost]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_peak_days: Option<&'a [PeakDay]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    breakeven: Option<BreakevenSummary<'a>>,
+    #[serde(flatten)]
+    usage: UsageSummary<'a>,
+}
+
+/// How much peak usage would need to move off-peak for switching to save money, which is `null`
+/// if moving it all isn't enough.
+#[derive(Serialize)]
+struct ShiftBreakevenSummary {
+    #[serde(with = "json::optional_decimal")]
+    shift_breakeven_kwh: Option<BigDecimal>,
+    #[serde(with = "json::optional_decimal")]
+    shift_breakeven_peak_share: Option<BigDecimal>,
+}
+
+#[derive(Serialize)]
+struct PeriodSummary<'a> {
+    #[serde(with = "json::decimal")]
+    kwh: &'a BigDecimal,
+    #[serde(with = "json::optional_decimal")]
+    kwh_share: Option<BigDecimal>,
+    #[serde(rename = "cost", with = "json::decimal")]
+    tou_cost: &'a BigDecimal,
+    /// Only when the current cost can be split by TOU period.
+    #[serde(flatten)]
+    current: Option<PeriodCurrentSummary<'a>>,
+}
+
+#[derive(Serialize)]
+struct PeriodCurrentSummary<'a> {
+    #[serde(with = "json::decimal")]
+    current_cost: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    cost_difference: BigDecimal,
+    #[serde(with = "json::optional_decimal")]
+    effective_rate: Option<BigDecimal>,
+    #[serde(with = "json::optional_decimal")]
+    current_effective_rate: Option<BigDecimal>,
+}
+
+impl<'a> Summary<'a> {
+    /// The summary of `result`, listing `flagged` if it's given.
+    fn new(result: &'a AnalysisResult, flagged: Option<&'a [UsageEntry]>) -> Self {
+        let details = &result.details;
+        let charges = &result.charges;
+        let costs = &result.costs;
+        let periods = result.periods.iter().map(|period| {
+            let current = period
+                .current_cost
+                .as_ref()
+                .zip(period.cost_difference())
+                .map(|(current_cost, cost_difference)| PeriodCurrentSummary {
+                    current_cost,
+                    cost_difference,
+                    effective_rate: period.tou_rate(),
+                    current_effective_rate: period.current_rate(),
+                });
+            let summary = PeriodSummary {
+                kwh: &period.kwh,
+                kwh_share: period.kwh_share.as_ref().map(|share| share.round(6)),
+                tou_cost: &period.tou_cost,
+                current,
+            };
+            (period.tou.name(), summary)
+        });
+        let shift_breakeven = details.shift_breakeven.as_ref().map(|shift_breakeven| {
+            let (kwh, fraction) = match shift_breakeven {
+                ShiftBreakeven::AlreadyBeneficial => (Some(0.into()), Some(0.into())),
+                ShiftBreakeven::Shift { kwh, fraction } => {
+                    (Some(kwh.clone()), Some(fraction.clone()))
+                }
+                ShiftBreakeven::Unreachable => (None, None),
+            };
+            ShiftBreakevenSummary {
+                shift_breakeven_kwh: kwh,
+                shift_breakeven_peak_share: fraction,
+            }
+        });
+        Self {
+            schema_version: JSON_SCHEMA_VERSION,
+            total_kwh: result.total_kwh(),
+            imported_kwh: &result.imported,
+            exported_kwh: &result.exported,
+            fixed_charge: charges.fixed_charge(),
+            solar_meter_fee: &charges.solar_meter_fee,
+            demand_charge: &charges.demand_charge,
+            current_minimum_charge: &charges.minimum_charge.current,
+            tou_minimum_charge: &charges.minimum_charge.tou,
+            current_unused_credit: &charges.unused_credit.current,
+            tou_unused_credit: &charges.unused_credit.tou,
+            current_cost: &costs.current,
+            current_import_cost: &costs.current_import,
+            current_export_credit: costs.current_export_credit(),
+            tou_cost: &costs.tou,
+            tou_import_cost: &costs.tou_import,
+            tou_export_credit: costs.tou_export_credit(),
+            average_rate_tou: &costs.average_rate_tou,
+            savings: costs.savings(),
+            savings_share: costs.savings_share(),
+            current_blended_rate: result.current_blended_rate(),
+            tou_blended_rate: result.tou_blended_rate(),
+            fixed_monthly_cost: details.fixed_monthly_cost.as_ref(),
+            shifted_tou_cost: details.shifted_tou_cost.as_ref(),
+            shift_breakeven,
+            annualized_savings: details.annualized_savings.as_ref(),
+            periods: periods.collect(),
+            daily: details.daily.as_deref(),
+            by_day_type: details.by_day_type.as_ref().map(|groups| &groups[..]),
+            year_over_year: details.year_over_year.as_deref(),
+            top_peak_days: details.top_peak_days.as_deref(),
+            breakeven: details.breakeven.as_ref().map(BreakevenSummary::new),
+            usage: UsageSummary::new(details, flagged),
+        }
+    }
+}
+
+/// The `--breakeven` result. `multiplier` and `rates` are only there when the outcome is
+/// `multiplier`, and `rates` lists the breakeven rates before the first rate change, with a `from`
+/// of `null`, and from each rate change.
+#[derive(Serialize)]
+struct BreakevenSummary<'a> {
+    /// `already_costs_more`, `multiplier` or `never`.
+    outcome: &'static str,
+    #[serde(
+        with = "json::optional_decimal",
+        skip_serializing_if = "Option::is_none"
+    )]
+    multiplier: Option<&'a BigDecimal>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    rates: Vec<BreakevenRatesSummary<'a>>,
+}
+
+#[derive(Serialize)]
+struct BreakevenRatesSummary<'a> {
+    from: Option<Date>,
+    winter: TouRatesSummary<'a>,
+    summer: TouRatesSummary<'a>,
+}
+
+#[derive(Serialize)]
+struct TouRatesSummary<'a> {
+    #[serde(
+        with = "json::optional_decimal",
+        skip_serializing_if = "Option::is_none"
+    )]
+    super_off: Option<&'a BigDecimal>,
+    #[serde(with = "json::decimal")]
+    off: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    mid: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    peak: &'a BigDecimal,
+}
+
+impl<'a> BreakevenSummary<'a> {
+    fn new(breakeven: &'a Breakeven) -> Self {
+        let tou_rates = |rates: &'a TouRates| TouRatesSummary {
+            super_off: rates.super_off.as_ref(),
+            off: &rates.off,
+            mid: &rates.mid,
+            peak: &rates.peak,
+        };
+        let seasonal_rates = |from, rates: &'a SeasonalTouRates| BreakevenRatesSummary {
+            from,
+            winter: tou_rates(&rates.winter),
+            summer: tou_rates(&rates.summer),
+        };
+        match breakeven {
+            Breakeven::AlreadyCostsMore => Self {
+                outcome: "already_costs_more",
+                multiplier: None,
+                rates: Vec::new(),
+            },
+            Breakeven::Multiplier { multiplier, rates } => {
+                let changes = rates
+                    .changes
+                    .iter()
+                    .map(|(date, rates)| seasonal_rates(Some(*date), rates));
+                Self {
+                    outcome: "multiplier",
+                    multiplier: Some(multiplier),
+                    rates: std::iter::once(seasonal_rates(None, &rates.initial))
+                        .chain(changes)
+                        .collect(),
+                }
+            }
+            Breakeven::Never => Self {
+                outcome: "never",
+                multiplier: None,
+                rates: Vec::new(),
+            },
+        }
+    }
+}
+
+/// The `--show-flagged`, `--histogram` and `--stats` details of the usage, which are only in the
+/// `--format json` summaries when they're asked for.
+#[derive(Serialize)]
+pub struct UsageSummary<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flagged: Option<Vec<FlaggedEntrySummary<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    histogram: Option<Vec<HourSummary<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<UsageStatsSummary<'a>>,
+}
+
+#[derive(Serialize)]
+struct FlaggedEntrySummary<'a> {
+    date: Date,
+    start_time: Time,
+    end_time: Time,
+    #[serde(with = "json::decimal")]
+    imported: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    exported: &'a BigDecimal,
+    notes: &'a str,
+}
+
+/// The net KWH of the entries starting in an hour of the day.
+#[derive(Serialize)]
+struct HourSummary<'a> {
+    hour: usize,
+    #[serde(with = "json::decimal")]
+    kwh: &'a BigDecimal,
+}
+
+/// The [UsageStats], which are `null` for the periods without any entries.
+#[derive(Serialize)]
+struct UsageStatsSummary<'a> {
+    all: Option<&'a Stats>,
+    #[serde(serialize_with = "json::map")]
+    by_period: Vec<(&'static str, Option<&'a Stats>)>,
+}
+
+impl<'a> UsageSummary<'a> {
+    pub fn new(details: &'a Details, flagged: Option<&'a [UsageEntry]>) -> Self {
+        let flagged = flagged.map(|entries| {
+            let entries = entries.iter().map(|entry| FlaggedEntrySummary {
+                date: entry.date,
+                start_time: entry.start_time,
+                end_time: entry.end_time,
+                imported: &entry.imported,
+                exported: &entry.exported,
+                notes: entry.notes.as_deref().unwrap_or_default(),
+            });
+            entries.collect()
+        });
+        let histogram = details.histogram.as_ref().map(|buckets| {
+            let hours = buckets.iter().enumerate();
+            hours.map(|(hour, kwh)| HourSummary { hour, kwh }).collect()
+        });
+        let stats = details.stats.as_ref().map(|stats| UsageStatsSummary {
+            all: stats.all.as_ref(),
+            by_period: stats
+                .by_period
+                .iter()
+                .map(|(tou, stats)| (tou.name(), stats.as_ref()))
+                .collect(),
+        });
+        Self {
+            flagged,
+            histogram,
+            stats,
+        }
+    }
+}
+
+fn print_breakeven(breakeven: &Breakeven, currency: &CurrencyFormat) {
+    match breakeven {
+        Breakeven::AlreadyCostsMore => output!(
+            "TOU rates already cost at least as much as your current rate, so there's no breakeven."
+        ),
+        Breakeven::Multiplier { multiplier, rates } => {
+            output!(
+                "TOU rates could rise by {:.2}% (a multiplier of {:.4}) before switching stops saving money.",
+                (multiplier - BigDecimal::from(1)) * BigDecimal::from(100),
+                multiplier
+            );
+            print_breakeven_rates("Breakeven rates", &rates.initial, currency);
+            for (date, rates) in &rates.changes {
+                let label = format!("Breakeven rates from {}", date);
+                print_breakeven_rates(&label, rates, currency);
+            }
+        }
+        Breakeven::Never => {
+            output!("Raising TOU rates never makes switching cost more for your usage.")
+        }
+    }
+}
+
+fn print_breakeven_rates(label: &str, rates: &SeasonalTouRates, currency: &CurrencyFormat) {
+    if rates.is_flat() {
+        output!("{}: {}", label, describe_rates(&rates.winter, currency));
+    } else {
+        let winter = describe_rates(&rates.winter, currency);
+        let summer = describe_rates(&rates.summer, currency);
+        output!("{} in winter: {}", label, winter);
+        output!("{} in summer: {}", label, summer);
+    }
+}
+
+fn describe_rates(rates: &TouRates, currency: &CurrencyFormat) -> String {
+    let described = format!(
+        "off-peak {}, mid-peak {}, peak {}",
+        currency.format(&rates.off, 4),
+        currency.format(&rates.mid, 4),
+        currency.format(&rates.peak, 4)
+    );
+    match &rates.super_off {
+        Some(super_off) => format!(
+            "super off-peak {}, {}",
+            currency.format(super_off, 4),
+            described
+        ),
+        None => described,
+    }
+}
+
+pub fn write_per_entry_csv(
+    path: &Path,
+    tou_rates: &DatedTouRates,
+    schedule: &TouSchedule,
+    cost_options: &CostOptions,
+    usage_data: &[UsageEntry],
+) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record([
+        "date", "start", "end", "kwh", "period", "rate", "cost", "imported", "exported",
+    ])?;
+    for entry in usage_data {
+        let tou_rates = tou_rates.for_date(entry.date);
+        for share in split_usage_by_period(entry, schedule) {
+            writer.write_record([
+                entry.date.to_string(),
+                entry.start_time.strftime("%H:%M").to_string(),
+                entry.end_time.strftime("%H:%M").to_string(),
+                share.kwh_total().normalized().to_plain_string(),
+                share.tou.name().to_string(),
+                tou_rates.for_period(share.tou).to_plain_string(),
+                share
+                    .cost(tou_rates, cost_options)
+                    .normalized()
+                    .to_plain_string(),
+                share.imported.normalized().to_plain_string(),
+                share.exported.normalized().to_plain_string(),
+            ])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn write_profile_csv(path: &Path, usage_data: &[UsageEntry]) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["hour", "average_kwh"])?;
+    for (hour, average) in hourly_profile(usage_data).iter().enumerate() {
+        writer.write_record([
+            format!("{:02}:00", hour),
+            average
+                .as_ref()
+                .map_or_else(String::new, |kwh| kwh.normalized().to_plain_string()),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn print_sample(entries: &[UsageEntry], schedule: &TouSchedule) {
+    output!(
+        "{:<10} {:>5} {:>5} {:>10} {:>10}  Period",
+        "Date",
+        "Start",
+        "End",
+        "Imported",
+        "Exported"
+    );
+    for entry in entries {
+        let periods: Vec<&str> =
+            split_by_period(entry.date, entry.start_time, entry.end_time, schedule)
+                .into_iter()
+                .map(|(tou, _)| tou.name())
+                .collect();
+        output!(
+            "{:<10} {:>5} {:>5} {:>10} {:>10}  {}",
+            entry.date,
+            entry.start_time.strftime("%H:%M"),
+            entry.end_time.strftime("%H:%M"),
+            entry.imported,
+            entry.exported,
+            periods.join("/")
+        );
+    }
+}
+
+/// Prints each of the usage entries of a day split by TOU period, with what it costs under the TOU
+/// rates, and the day's totals.
+pub fn print_explanation(explanation: &DayExplanation, currency: &CurrencyFormat) {
+    let date = explanation.date;
+    let day_type = if explanation.off_peak_day {
+        ", which is off-peak all day"
+    } else {
+        ""
+    };
+    output!(
+        "{} usage entries on {}, a {}{}:",
+        explanation.entries,
+        date,
+        date.strftime("%A"),
+        day_type
+    );
+    if explanation.entries == 0 {
+        return;
+    }
+    output!(
+        "{:>5} {:>5}  {:<9} {:>10} {:>10} {:>10}",
+        "Start",
+        "End",
+        "Period",
+        "Rate",
+        "KWH",
+        "TOU cost"
+    );
+    for share in &explanation.shares {
+        output!(
+            "{:>5} {:>5}  {:<9} {:>10} {:>10.4} {:>10}",
+            share.start_time.strftime("%H:%M"),
+            share.end_time.strftime("%H:%M"),
+            share.tou.name(),
+            currency.format(&share.rate, 4),
+            share.kwh,
+            currency.format(&share.cost, 4)
+        );
+    }
+    output!(
+        "Total: {:.4} KWH, TOU cost {}",
+        explanation.kwh,
+        currency.format(&explanation.tou_cost, 2)
+    );
+    if let Some((rate, current_cost)) = &explanation.current {
+        output!(
+            "Current cost at {}/KWH: {}",
+            currency.format(rate, 4),
+            currency.format(current_cost, 2)
+        );
+    }
+}
+
+fn print_flagged(entries: &[UsageEntry]) {
+    output!("{} usage entries have notes:", entries.len());
+    if entries.is_empty() {
+        return;
+    }
+    output!(
+        "{:<10} {:>5} {:>5} {:>10} {:>10}  Notes",
+        "Date",
+        "Start",
+        "End",
+        "Imported",
+        "Exported"
+    );
+    for entry in entries {
+        output!(
+            "{:<10} {:>5} {:>5} {:>10} {:>10}  {}",
+            entry.date,
+            entry.start_time.strftime("%H:%M"),
+            entry.end_time.strftime("%H:%M"),
+            entry.imported,
+            entry.exported,
+            entry.notes.as_deref().unwrap_or_default()
+        );
+    }
+}
+
+/// Prints the chart to stderr, like the warnings, so it stays out of the results.
+fn print_histogram(buckets: &[BigDecimal; 24]) {
+    const WIDTH: u64 = 50;
+    let max = buckets
+        .iter()
+        .map(|kwh| kwh.abs())
+        .max()
+        .unwrap_or_default();
+    eprintln!("KWH by hour of day:");
+    for (hour, kwh) in buckets.iter().enumerate() {
+        let length = if max == BigDecimal::from(0) {
+            0
+        } else {
+            (kwh.abs() * BigDecimal::from(WIDTH) / &max)
+                .round(0)
+                .to_u64()
+                .unwrap_or(0)
+        };
+        // Hours where more was exported than imported get a different bar.
+        let bar = if *kwh < BigDecimal::from(0) { "-" } else { "#" };
+        eprintln!(
+            "{:02}:00 {:>10.2} {}",
+            hour,
+            kwh,
+            bar.repeat(length as usize)
+        );
+    }
+}
+
+fn print_stats(stats: &UsageStats) {
+    let row = |label: &str, stats: &Option<Stats>| match stats {
+        Some(stats) => output!(
+            "{:<10} {:>8} {:>10.3} {:>10.3} {:>10.3} {:>10.3} {:>10.3}",
+            label,
+            stats.count,
+            stats.min,
+            stats.max,
+            stats.mean,
+            stats.median,
+            stats.p95
+        ),
+        None => output!("{:<10} {:>8}", label, 0),
+    };
+    output!("Net KWH per usage entry:");
+    output!(
+        "{:<10} {:>8} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "Period",
+        "Entries",
+        "Min",
+        "Max",
+        "Mean",
+        "Median",
+        "95th"
+    );
+    row("all", &stats.all);
+    for (tou, stats) in &stats.by_period {
+        row(tou.name(), stats);
+    }
+}
+
+fn print_daily_table(daily: &[DailyCost], currency: &CurrencyFormat) {
+    let smoothed = daily.iter().any(|day| day.smoothed_current_cost.is_some());
+    let row = |date: &str,
+               kwh: &BigDecimal,
+               current: &BigDecimal,
+               tou: &BigDecimal,
+               averages: Option<(&BigDecimal, &BigDecimal)>| {
+        let averages = averages.map_or(String::new(), |(current, tou)| {
+            format!(
+                " {:>12} {:>12}",
+                currency.format(current, 2),
+                currency.format(tou, 2)
+            )
+        });
+        output!(
+            "{:<10} {:>10.2} {:>12} {:>12} {:>12}{}",
+            date,
+            kwh,
+            currency.format(current, 2),
+            currency.format(tou, 2),
+            currency.format(&(current - tou), 2),
+            averages
+        );
+    };
+    output!(
+        "{:<10} {:>10} {:>12} {:>12} {:>12}{}",
+        "Date",
+        "KWH",
+        "Current",
+        "TOU",
+        "Savings",
+        if smoothed {
+            format!(" {:>12} {:>12}", "Avg Current", "Avg TOU")
+        } else {
+            String::new()
+        }
+    );
+    for day in daily {
+        row(
+            &day.date.to_string(),
+            &day.kwh,
+            &day.current_cost,
+            &day.tou_cost,
+            day.smoothed_current_cost
+                .as_ref()
+                .zip(day.smoothed_tou_cost.as_ref()),
+        );
+    }
+    let total = |value: fn(&DailyCost) -> &BigDecimal| daily.iter().map(value).sum::<BigDecimal>();
+    row(
+        "Total",
+        &total(|day| &day.kwh),
+        &total(|day| &day.current_cost),
+        &total(|day| &day.tou_cost),
+        None,
+    );
+}
+
+fn print_day_type_table(groups: &[DayTypeCost], currency: &CurrencyFormat) {
+    output!(
+        "{:<8} {:>5} {:>10} {:>12} {:>12} {:>12}",
+        "Days",
+        "Count",
+        "KWH",
+        "Current",
+        "TOU",
+        "Savings"
+    );
+    for group in groups {
+        output!(
+            "{:<8} {:>5} {:>10.2} {:>12} {:>12} {:>12}",
+            group.day_type,
+            group.days,
+            group.kwh,
+            currency.format(&group.current_cost, 2),
+            currency.format(&group.tou_cost, 2),
+            currency.format(&group.savings, 2)
+        );
+    }
+}
+
+/// Prints a row for each month of the year in `months`, with a KWH and TOU cost column for each
+/// year, left blank for the years without usage that month.
+fn print_year_over_year_table(months: &[MonthCost], currency: &CurrencyFormat) {
+    let years: BTreeSet<i16> = months.iter().map(|month| month.year).collect();
+    let month_numbers: BTreeSet<i8> = months.iter().map(|month| month.month).collect();
+    let mut header = format!("{:<5}", "Month");
+    for year in &years {
+        header.push_str(&format!(
+            " {:>10} {:>12}",
+            format!("KWH {}", year),
+            format!("TOU {}", year)
+        ));
+    }
+    output!("{}", header);
+    for number in month_numbers {
+        let mut row = format!("{:<5}", number);
+        for &year in &years {
+            match months
+                .iter()
+                .find(|month| month.year == year && month.month == number)
+            {
+                Some(month) => row.push_str(&format!(
+                    " {:>10.2} {:>12}",
+                    month.kwh,
+                    currency.format(&month.tou_cost, 2)
+                )),
+                None => row.push_str(&format!(" {:>10} {:>12}", "", "")),
+            }
+        }
+        output!("{}", row.trim_end());
+    }
+}
+
+fn print_period_comparison(
+    result: &AnalysisResult,
+    current: &TouBreakdown,
+    currency: &CurrencyFormat,
+) {
+    let rate = |rate: Option<BigDecimal>| match rate {
+        Some(rate) => currency.format(&rate, 4),
+        None => "n/a".to_string(),
+    };
+    let row = |name: &str, kwh: &BigDecimal, tou_cost: &BigDecimal, current_cost: &BigDecimal| {
+        output!(
+            "{:<9} {:>10.2} {:>12} {:>12} {:>12} {:>12} {:>12}",
+            name,
+            kwh,
+            currency.format(tou_cost, 2),
+            currency.format(current_cost, 2),
+            currency.format(&(tou_cost - current_cost), 2),
+            rate(blended_rate(tou_cost, kwh)),
+            rate(blended_rate(current_cost, kwh))
+        );
+    };
+    output!(
+        "{:<9} {:>10} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "Period",
+        "KWH",
+        "TOU",
+        "Current",
+        "Difference",
+        "TOU/KWH",
+        "Current/KWH"
+    );
+    for period in &result.periods {
+        let current_cost = &current.for_period(period.tou).cost;
+        row(
+            period.tou.name(),
+            &period.kwh,
+            &period.tou_cost,
+            current_cost,
+        );
+    }
+    row(
+        "Total",
+        &result.breakdown.total_kwh(),
+        &result.breakdown.total_cost(),
+        &current.total_cost(),
+    );
+}
+
+fn print_top_peak_days(days: &[PeakDay], ranking: PeakDayRanking, currency: &CurrencyFormat) {
+    let by = match ranking {
+        PeakDayRanking::Kwh => "peak KWH",
+        PeakDayRanking::Cost => "peak cost",
+    };
+    output!("Top {} days by {}:", days.len(), by);
+    output!("{:<10} {:>10} {:>12}", "Date", "Peak KWH", "Peak cost");
+    for day in days {
+        output!(
+            "{:<10} {:>10.2} {:>12}",
+            day.date,
+            day.kwh,
+            currency.format(&day.cost, 2)
+        );
+    }
+}
+
+/// The `--format json` summary of `--compare-all`.
+#[derive(Serialize)]
+struct LocationsSummary<'a> {
+    schema_version: &'static str,
+    #[serde(with = "json::decimal")]
+    current_cost: &'a BigDecimal,
+    locations: Vec<LocationSummary<'a>>,
+    #[serde(flatten)]
+    usage: UsageSummary<'a>,
+}
+
+#[derive(Serialize)]
+struct LocationSummary<'a> {
+    location: &'a str,
+    #[serde(with = "json::decimal")]
+    tou_cost: &'a BigDecimal,
+    #[serde(with = "json::decimal")]
+    savings: BigDecimal,
+}
+
+pub fn print_location_ranking(
+    format: OutputFormat,
+    currency: &CurrencyFormat,
+    current_cost: &BigDecimal,
+    ranked: &[(&str, BigDecimal)],
+    usage: UsageSummary,
+) {
+    match format {
+        OutputFormat::Text => {
+            output!("Current cost: {}", currency.format(current_cost, 2));
+            output!("{:<18} {:>12} {:>12}", "Location", "TOU cost", "Savings");
+            for (location, tou_cost) in ranked {
+                output!(
+                    "{:<18} {:>12} {:>12}",
+                    location,
+                    currency.format(tou_cost, 2),
+                    currency.format(&(current_cost - tou_cost), 2)
+                );
+            }
+        }
+        OutputFormat::Json => {
+            let locations = ranked.iter().map(|(location, tou_cost)| LocationSummary {
+                location,
+                tou_cost,
+                savings: current_cost - tou_cost,
+            });
+            let summary = LocationsSummary {
+                schema_version: JSON_SCHEMA_VERSION,
+                current_cost,
+                locations: locations.collect(),
+                usage,
+            };
+            output!("{}", json::to_string(&summary));
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use crate::holidays::{Holidays, is_weekend};
+use crate::rate_calculator::TimeOfUse;
+use jiff::civil::{Date, Time};
+use std::collections::HashSet;
+
+/// The number of minutes in a day without a daylight saving time transition.
+pub const MINUTES_PER_DAY: usize = 24 * 60;
+
+/// When each TOU period applies.
+#[derive(Debug, Clone)]
+pub struct TouSchedule {
+    /// The minute of a weekday each period starts at, in order, with the first at midnight. Each
+    /// period lasts until the next one starts.
+    boundaries: Vec<(usize, TimeOfUse)>,
+    /// Whether Saturdays and Sundays are off-peak all day, rather than following the weekday
+    /// schedule.
+    pub weekends_off_peak: bool,
+    /// Days that are off-peak all day, in addition to weekends.
+    pub holidays: Holidays,
+}
+
+impl Default for TouSchedule {
+    fn default() -> Self {
+        Self::scl()
+    }
+}
+
+impl TouSchedule {
+    /// SCL's schedule: off-peak from midnight to 6am, peak from 5pm to 9pm, and mid-peak otherwise.
+    /// Weekends and the SCL observed holidays are off-peak all day.
+    pub fn scl() -> Self {
+        let mut minutes = [TimeOfUse::Mid; MINUTES_PER_DAY];
+        minutes[0..6 * 60].fill(TimeOfUse::Off);
+        minutes[17 * 60..21 * 60].fill(TimeOfUse::Peak);
+        Self::from_minutes(&minutes, Holidays::Scl)
+    }
+
+    /// A schedule with the period for each minute of a weekday, and with weekends and `holidays`
+    /// off-peak all day.
+    pub fn from_minutes(minutes: &[TimeOfUse; MINUTES_PER_DAY], holidays: Holidays) -> Self {
+        Self {
+            boundaries: boundaries(minutes),
+            weekends_off_peak: true,
+            holidays,
+        }
+    }
+
+    /// The same schedule with the weekday periods replaced by `minutes`.
+    pub fn with_minutes(&self, minutes: &[TimeOfUse; MINUTES_PER_DAY]) -> Self {
+        Self {
+            boundaries: boundaries(minutes),
+            ..self.clone()
+        }
+    }
+
+    /// The period on a weekday at `time`. The schedule has a period for every minute, so this is
+    /// defined for every time.
+    pub fn period_at(&self, time: Time) -> TimeOfUse {
+        let minute = time.hour() as usize * 60 + time.minute() as usize;
+        let index = self
+            .boundaries
+            .partition_point(|&(start, _)| start <= minute);
+        self.boundaries[index - 1].1
+    }
+
+    /// The minute of the day the period in effect at `minute` ends at, which is
+    /// [MINUTES_PER_DAY] if it lasts until midnight.
+    pub fn period_end(&self, minute: usize) -> usize {
+        self.boundaries
+            .iter()
+            .map(|&(start, _)| start)
+            .find(|&start| start > minute)
+            .unwrap_or(MINUTES_PER_DAY)
+    }
+
+    /// The minutes of a weekday each period is in effect for, in order.
+    pub fn periods(&self) -> impl Iterator<Item = (std::ops::Range<usize>, TimeOfUse)> + '_ {
+        self.boundaries
+            .iter()
+            .map(|&(start, tou)| (start..self.period_end(start), tou))
+    }
+
+    /// Whether `tou` is in effect at any time on a weekday.
+    pub fn has_period(&self, tou: TimeOfUse) -> bool {
+        self.boundaries.iter().any(|&(_, period)| period == tou)
+    }
+
+    /// The TOU periods to show the usage of, which leaves out super off-peak and mid-peak unless
+    /// the schedule has them.
+    pub fn shown_periods(&self) -> Vec<TimeOfUse> {
+        TimeOfUse::ALL
+            .into_iter()
+            .filter(|&tou| {
+                !matches!(tou, TimeOfUse::SuperOff | TimeOfUse::Mid) || self.has_period(tou)
+            })
+            .collect()
+    }
+
+    pub fn is_off_peak_day(&self, date: Date) -> bool {
+        (self.weekends_off_peak && is_weekend(date)) || self.holidays.contains(date)
+    }
+
+    /// A description of the schedule that's the same for schedules that put every time in the
+    /// same period, to tell whether usage split by TOU period with another schedule still applies.
+    pub fn fingerprint(&self) -> String {
+        let periods: Vec<String> = self
+            .boundaries
+            .iter()
+            .map(|&(start, tou)| format!("{} {}", format_minute(start), tou.name()))
+            .collect();
+        let holidays = match &self.holidays {
+            Holidays::Scl => "scl".to_string(),
+            Holidays::Custom(dates) => sorted_dates(dates),
+            Holidays::SclAnd(dates) => format!("scl+{}", sorted_dates(dates)),
+        };
+        format!(
+            "periods={}; weekends_off_peak={}; holidays={}",
+            periods.join(","),
+            self.weekends_off_peak,
+            holidays
+        )
+    }
+}
+
+/// Where the period changes in `minutes`, including midnight.
+fn boundaries(minutes: &[TimeOfUse; MINUTES_PER_DAY]) -> Vec<(usize, TimeOfUse)> {
+    let mut boundaries: Vec<(usize, TimeOfUse)> = Vec::new();
+    for (minute, &tou) in minutes.iter().enumerate() {
+        if boundaries.last().is_none_or(|&(_, last)| last != tou) {
+            boundaries.push((minute, tou));
+        }
+    }
+    boundaries
+}
+
+/// Parses a time range like `17:00-21:30` into the minutes of the day it covers. The end may be
+/// `24:00` to mean the end of the day.
+pub fn parse_time_range(range: &str) -> Result<std::ops::Range<usize>, String> {
+    let (start, end) = range
+        .split_once('-')
+        .ok_or_else(|| format!("expected a range like 17:00-21:00, got {:?}", range))?;
+    let parse_minute = |s: &str| -> Result<usize, String> {
+        let (hour, minute) = s
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| format!("expected HH:MM, got {:?}", s))?;
+        let hour: usize = hour
+            .parse()
+            .map_err(|_| format!("invalid hour in {:?}", s))?;
+        let minute: usize = minute
+            .parse()
+            .ok()
+            .filter(|&minute| minute < 60)
+            .ok_or_else(|| format!("invalid minute in {:?}", s))?;
+        let minute_of_day = hour * 60 + minute;
+        if minute_of_day > MINUTES_PER_DAY {
+            return Err(format!("invalid hour in {:?}", s));
+        }
+        Ok(minute_of_day)
+    };
+    let start = parse_minute(start)?;
+    let end = parse_minute(end)?;
+    if start >= end {
+        return Err(format!("range {:?} does not end after it starts", range));
+    }
+    Ok(start..end)
+}
+
+/// Formats a minute of the day as HH:MM.
+pub fn format_minute(minute: usize) -> String {
+    format!("{:02}:{:02}", minute / 60, minute % 60)
+}
+
+fn sorted_dates(dates: &HashSet<Date>) -> String {
+    let mut dates: Vec<String> = dates.iter().map(|date| date.to_string()).collect();
+    dates.sort();
+    dates.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that `schedule`'s periods cover every minute of the day exactly once, and agree with
+    /// [TouSchedule::period_at].
+    fn assert_every_minute_has_one_period(schedule: &TouSchedule) {
+        let mut start = 0;
+        while start < MINUTES_PER_DAY {
+            let end = schedule.period_end(start);
+            assert!(start < end, "empty period at {}", format_minute(start));
+            let tou = schedule.period_at(time_at(start));
+            for minute in start..end {
+                assert_eq!(
+                    schedule.period_at(time_at(minute)),
+                    tou,
+                    "{}",
+                    format_minute(minute)
+                );
+                assert_eq!(schedule.period_end(minute), end);
+            }
+            start = end;
+        }
+        assert_eq!(start, MINUTES_PER_DAY);
+    }
+
+    fn time_at(minute: usize) -> Time {
+        Time::constant((minute / 60) as i8, (minute % 60) as i8, 0, 0)
+    }
+
+    #[test]
+    fn every_minute_is_in_exactly_one_period() {
+        assert_every_minute_has_one_period(&TouSchedule::scl());
+        let mut minutes = [TimeOfUse::Off; MINUTES_PER_DAY];
+        minutes[parse_time_range("00:00-04:00").unwrap()].fill(TimeOfUse::SuperOff);
+        minutes[parse_time_range("07:30-14:00").unwrap()].fill(TimeOfUse::Mid);
+        minutes[parse_time_range("14:00-19:00").unwrap()].fill(TimeOfUse::Peak);
+        minutes[parse_time_range("23:59-24:00").unwrap()].fill(TimeOfUse::Peak);
+        let schedule = TouSchedule::from_minutes(&minutes, Holidays::Scl);
+        assert_every_minute_has_one_period(&schedule);
+        assert_eq!(schedule.period_at(time_at(23 * 60 + 58)), TimeOfUse::Off);
+        assert_eq!(schedule.period_at(time_at(23 * 60 + 59)), TimeOfUse::Peak);
+    }
+
+    #[test]
+    fn time_ranges_can_end_at_midnight() {
+        assert_eq!(parse_time_range("17:00-21:30"), Ok(17 * 60..21 * 60 + 30));
+        assert_eq!(
+            parse_time_range("21:00-24:00"),
+            Ok(21 * 60..MINUTES_PER_DAY)
+        );
+        assert_eq!(parse_time_range(" 0:00 - 6:00 "), Ok(0..6 * 60));
+    }
+
+    #[test]
+    fn reversed_time_ranges_are_rejected() {
+        for range in ["21:00-17:00", "17:00-17:00", "24:00-24:00", "21:00-00:00"] {
+            let error = parse_time_range(range).unwrap_err();
+            assert!(error.contains("does not end after it starts"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn invalid_times_are_rejected() {
+        for (range, expected) in [
+            ("17:60-21:00", "invalid minute in \"17:60\""),
+            ("17:00-21:99", "invalid minute in \"21:99\""),
+            ("17:00-24:01", "invalid hour in \"24:01\""),
+            ("25:00-26:00", "invalid hour in \"25:00\""),
+            ("17-21", "expected HH:MM"),
+            ("17:00", "expected a range"),
+        ] {
+            let error = parse_time_range(range).unwrap_err();
+            assert!(error.contains(expected), "{}: {}", range, error);
+        }
+    }
+}
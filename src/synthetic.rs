@@ -0,0 +1,191 @@
+//! Synthetic usage data for tests, so they don't need real usage exports.
+
+use crate::usage_data::UsageEntry;
+use bigdecimal::BigDecimal;
+use jiff::ToSpan;
+use jiff::civil::{Date, Time};
+
+/// How much is imported in each hour of the day, repeated for every day of the usage.
+pub struct LoadShape {
+    pub hourly_kwh: [BigDecimal; 24],
+    /// The length of each entry, which must divide an hour evenly.
+    pub interval_minutes: i64,
+}
+
+impl LoadShape {
+    /// The same usage in every hour.
+    pub fn flat(hourly_kwh: BigDecimal) -> Self {
+        Self {
+            hourly_kwh: std::array::from_fn(|_| hourly_kwh.clone()),
+            interval_minutes: 15,
+        }
+    }
+
+    /// Moves `kwh` of the usage in `from_hour` to `to_hour`.
+    pub fn shift(&self, from_hour: usize, to_hour: usize, kwh: &BigDecimal) -> Self {
+        let mut hourly_kwh = self.hourly_kwh.clone();
+        hourly_kwh[from_hour] -= kwh;
+        hourly_kwh[to_hour] += kwh;
+        Self {
+            hourly_kwh,
+            interval_minutes: self.interval_minutes,
+        }
+    }
+
+    /// The usage from `from` through `to`, inclusive, with each hour's usage split evenly over
+    /// its entries.
+    pub fn generate(&self, from: Date, to: Date) -> Vec<UsageEntry> {
+        let per_hour = 60 / self.interval_minutes;
+        let mut entries = Vec::new();
+        let mut date = from;
+        while date <= to {
+            for (hour, kwh) in self.hourly_kwh.iter().enumerate() {
+                let imported = kwh / BigDecimal::from(per_hour);
+                for i in 0..per_hour {
+                    let start_time = Time::MIN
+                        .wrapping_add((hour as i64).hours())
+                        .wrapping_add((i * self.interval_minutes).minutes());
+                    entries.push(UsageEntry {
+                        date,
+                        start_time,
+                        end_time: start_time.wrapping_add(self.interval_minutes.minutes()),
+                        imported: imported.clone(),
+                        exported: BigDecimal::from(0),
+                        notes: None,
+                    });
+                }
+            }
+            date = date.tomorrow().expect("date out of range");
+        }
+        entries
+    }
+}
+
+/// The entries as a usage CSV in SCL's format.
+pub fn to_csv(entries: &[UsageEntry]) -> String {
+    let mut csv = String::from(
+        "Name,Synthetic\n\nTYPE,DATE,START TIME,END TIME,IMPORT (kWh),EXPORT (kWh),NOTES\n",
+    );
+    for entry in entries {
+        csv.push_str(&format!(
+            "Electric usage,{},{},{},{},{},{}\n",
+            entry.date,
+            entry.start_time.strftime("%H:%M"),
+            entry.end_time.strftime("%H:%M"),
+            entry.imported,
+            entry.exported,
+            entry.notes.as_deref().unwrap_or(""),
+        ));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_calculator::{CostOptions, TimeOfUse, calculate_tou_cost};
+    use crate::rates::{DatedTouRates, TouRates};
+    use crate::schedule::TouSchedule;
+    use crate::usage_data::{ReadOptions, read_usage_data_from};
+    use jiff::civil::date;
+    use std::io::Cursor;
+    use std::str::FromStr;
+
+    fn rates() -> DatedTouRates {
+        DatedTouRates::from(TouRates {
+            super_off: None,
+            off: BigDecimal::from_str("0.0828").unwrap(),
+            mid: BigDecimal::from_str("0.1449").unwrap(),
+            peak: BigDecimal::from_str("0.1656").unwrap(),
+        })
+    }
+
+    #[test]
+    fn csv_reads_back_the_same_entries() {
+        let entries =
+            LoadShape::flat(BigDecimal::from(1)).generate(date(2024, 3, 1), date(2024, 3, 3));
+        let csv = to_csv(&entries);
+        let parsed = read_usage_data_from(Cursor::new(csv), &ReadOptions::default()).unwrap();
+        assert_eq!(parsed.entries, entries);
+    }
+
+    #[test]
+    fn shifting_peak_to_off_never_increases_tou_cost() {
+        let schedule = TouSchedule::scl();
+        let hours_in = |tou| {
+            (0..24)
+                .filter(|&hour| {
+                    TimeOfUse::from_time(Time::MIN.wrapping_add((hour as i64).hours()), &schedule)
+                        == tou
+                })
+                .collect::<Vec<_>>()
+        };
+        let cost = |shape: &LoadShape| {
+            let entries = shape.generate(date(2024, 3, 4), date(2024, 3, 17));
+            calculate_tou_cost(&rates(), &schedule, &CostOptions::default(), entries.iter())
+        };
+        let shape = LoadShape::flat(BigDecimal::from(2));
+        let before = cost(&shape);
+        for peak_hour in hours_in(TimeOfUse::Peak) {
+            for off_hour in hours_in(TimeOfUse::Off) {
+                for kwh in ["0.1", "1", "2"] {
+                    let shifted =
+                        shape.shift(peak_hour, off_hour, &BigDecimal::from_str(kwh).unwrap());
+                    assert!(
+                        cost(&shifted) <= before,
+                        "shifting {} KWH from {}:00 to {}:00 increased the cost",
+                        kwh,
+                        peak_hour,
+                        off_hour
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shifting_peak_to_off_never_increases_tou_cost_for_any_load_shape() {
+        let schedule = TouSchedule::scl();
+        let hours_in = |tou| {
+            (0..24)
+                .filter(|&hour| {
+                    TimeOfUse::from_time(Time::MIN.wrapping_add((hour as i64).hours()), &schedule)
+                        == tou
+                })
+                .collect::<Vec<_>>()
+        };
+        let (peak_hours, off_hours) = (hours_in(TimeOfUse::Peak), hours_in(TimeOfUse::Off));
+        // A fixed xorshift sequence rather than a random seed, so a failure can be reproduced.
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+        for _ in 0..50 {
+            let shape = LoadShape {
+                hourly_kwh: std::array::from_fn(|_| BigDecimal::new(next(300).into(), 2)),
+                interval_minutes: [5, 15, 30, 60][next(4)],
+            };
+            let peak_hour = peak_hours[next(peak_hours.len())];
+            let off_hour = off_hours[next(off_hours.len())];
+            // Up to all of the usage in the peak hour, in hundredths.
+            let kwh = &shape.hourly_kwh[peak_hour] * BigDecimal::new(next(101).into(), 2);
+            let shifted = shape.shift(peak_hour, off_hour, &kwh);
+            // Two weeks, so weekends, when peak hours are off-peak, are in the usage too.
+            let cost = |shape: &LoadShape| {
+                let entries = shape.generate(date(2024, 3, 4), date(2024, 3, 17));
+                calculate_tou_cost(&rates(), &schedule, &CostOptions::default(), entries.iter())
+            };
+            assert!(
+                cost(&shifted) <= cost(&shape),
+                "shifting {} KWH from {}:00 to {}:00 increased the cost of {:?}",
+                kwh,
+                peak_hour,
+                off_hour,
+                shape.hourly_kwh
+            );
+        }
+    }
+}
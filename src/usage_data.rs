@@ -1,16 +1,897 @@
 use bigdecimal::BigDecimal;
-use jiff::civil::Time;
+use csv::{StringRecord, StringRecordsIntoIter};
+use flate2::bufread::MultiGzDecoder;
+use jiff::civil::{Date, DateTime, Time};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Chain, Cursor, Read};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::LazyLock;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct UsageEntry {
+    pub date: Date,
     pub start_time: Time,
+    /// If it's before `start_time`, the interval wraps past midnight into the next day. Usage
+    /// files only have that for intervals ending at 00:00, but entries built in code can wrap.
     pub end_time: Time,
     pub imported: BigDecimal,
     pub exported: BigDecimal,
+    /// The NOTES column, if it isn't empty. SCL uses it to flag readings that were estimated or
+    /// adjusted, which are less trustworthy than measured ones.
+    pub notes: Option<String>,
 }
 
 impl UsageEntry {
     pub fn kwh_total(&self) -> BigDecimal {
         &self.imported - &self.exported
     }
+
+    /// The same interval with only its imported energy, as if nothing had been exported.
+    pub fn imports_only(&self) -> UsageEntry {
+        UsageEntry {
+            date: self.date,
+            start_time: self.start_time,
+            end_time: self.end_time,
+            imported: self.imported.clone(),
+            exported: BigDecimal::from(0),
+            notes: self.notes.clone(),
+        }
+    }
+
+    /// Whether the interval ends after it starts. The last interval of the day ends at 00:00,
+    /// which is treated as midnight at the end of the day, but one starting at 00:00 too would
+    /// be zero-length rather than a whole day.
+    pub fn has_valid_interval(&self) -> bool {
+        self.end_time > self.start_time
+            || (self.end_time == Time::midnight() && self.start_time != Time::midnight())
+    }
+
+    pub fn start(&self) -> DateTime {
+        self.date.to_datetime(self.start_time)
+    }
+
+    /// How long the interval is, in whole minutes.
+    pub fn minutes(&self) -> i64 {
+        self.end().duration_since(self.start()).as_mins()
+    }
+
+    /// When the interval ends, which is on the next day if it ends before its start. One that
+    /// ends when it starts is zero-length, the same as in [split_by_period].
+    ///
+    /// [split_by_period]: crate::rate_calculator::split_by_period
+    pub fn end(&self) -> DateTime {
+        if self.end_time >= self.start_time {
+            self.date.to_datetime(self.end_time)
+        } else {
+            let next_day = self.date.tomorrow().expect("date out of range");
+            next_day.to_datetime(self.end_time)
+        }
+    }
+}
+
+static EXPECTED_HEADERS: LazyLock<StringRecord> = LazyLock::new(|| {
+    StringRecord::from(vec![
+        "TYPE",
+        "DATE",
+        "START TIME",
+        "END TIME",
+        "IMPORT (kWh)",
+        "EXPORT (kWh)",
+        "NOTES",
+    ])
+});
+
+#[derive(Debug)]
+pub enum UsageReadError {
+    Io(std::io::Error),
+    MissingHeader,
+    HeaderMismatch(StringRecord),
+    Csv(csv::Error),
+    InvalidField {
+        line: u64,
+        column: &'static str,
+        value: String,
+        message: String,
+    },
+    InvalidInterval {
+        line: u64,
+        row: String,
+    },
+    /// A row with fewer fields than the header, so it has no value for these columns.
+    MissingFields {
+        line: u64,
+        columns: Vec<&'static str>,
+    },
+}
+
+impl std::fmt::Display for UsageReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsageReadError::Io(e) => write!(f, "Failed to read usage file: {}", e),
+            UsageReadError::MissingHeader => {
+                write!(f, "Usage file is empty or has no header row")
+            }
+            UsageReadError::HeaderMismatch(headers) => write!(
+                f,
+                "Unexpected headers in usage CSV: {:?}. Expected these, in any order: {:?}",
+                headers, *EXPECTED_HEADERS
+            ),
+            UsageReadError::Csv(e) => write!(f, "Usage file could not be deserialized: {}", e),
+            UsageReadError::InvalidField {
+                line,
+                column,
+                value,
+                message,
+            } => write!(
+                f,
+                "Invalid {} value {:?} on line {}: {}",
+                column, value, line, message
+            ),
+            UsageReadError::InvalidInterval { line, row } => write!(
+                f,
+                "End time is not after start time on line {}: {}",
+                line, row
+            ),
+            UsageReadError::MissingFields { line, columns } => write!(
+                f,
+                "Row on line {} is cut short, missing the {} column{}",
+                line,
+                columns.join(", "),
+                if columns.len() == 1 { "" } else { "s" }
+            ),
+        }
+    }
+}
+
+impl UsageReadError {
+    /// Whether the error is confined to a single row, so the rows after it can still be read.
+    pub fn is_row_error(&self) -> bool {
+        match self {
+            UsageReadError::InvalidField { .. }
+            | UsageReadError::InvalidInterval { .. }
+            | UsageReadError::MissingFields { .. } => true,
+            UsageReadError::Csv(e) => matches!(e.kind(), csv::ErrorKind::Utf8 { .. }),
+            _ => false,
+        }
+    }
+}
+
+impl std::error::Error for UsageReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            UsageReadError::Io(e) => Some(e),
+            UsageReadError::Csv(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for UsageReadError {
+    fn from(e: std::io::Error) -> Self {
+        UsageReadError::Io(e)
+    }
+}
+
+impl From<csv::Error> for UsageReadError {
+    fn from(e: csv::Error) -> Self {
+        // Failing to read the file, like when a gzipped export is corrupt, isn't a problem with
+        // the CSV in it.
+        if !e.is_io_error() {
+            return UsageReadError::Csv(e);
+        }
+        match e.into_kind() {
+            csv::ErrorKind::Io(e) => UsageReadError::Io(e),
+            _ => unreachable!("the error is an IO error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReadOptions {
+    /// The TYPE of row to read, compared case-insensitively and ignoring surrounding whitespace.
+    pub usage_type: String,
+    /// Skip rows that can't be parsed instead of failing, keeping their errors in
+    /// [ParsedUsage::bad_rows].
+    pub skip_bad_rows: bool,
+    /// The strftime-style format of the DATE column, like `%m/%d/%Y`, or `None` to accept any of
+    /// [AUTO_DATE_FORMATS].
+    pub date_format: Option<String>,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self {
+            usage_type: "Electric usage".to_string(),
+            skip_bad_rows: false,
+            date_format: None,
+        }
+    }
+}
+
+/// The date formats tried, in order, when no date format is given: ISO dates, and the US-style
+/// dates that SCL's exports can have. Two-digit years come first, since `%Y` would read them as
+/// the first century.
+pub const AUTO_DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%m/%d/%y", "%m/%d/%Y"];
+
+/// The 12-hour time formats tried when a time isn't a 24-hour time, which some exports have.
+pub const TWELVE_HOUR_TIME_FORMATS: [&str; 2] = ["%I:%M %p", "%I:%M:%S %p"];
+
+/// The usage entries read from a usage file.
+#[derive(Debug, Default)]
+pub struct ParsedUsage {
+    pub entries: Vec<UsageEntry>,
+    /// How many rows were skipped for each TYPE that didn't match [ReadOptions::usage_type].
+    pub skipped_types: BTreeMap<String, usize>,
+    /// The errors for rows that were skipped because they couldn't be parsed, if
+    /// [ReadOptions::skip_bad_rows] is set.
+    pub bad_rows: Vec<UsageReadError>,
+}
+
+impl ParsedUsage {
+    /// Adds the entries and skipped rows read from another usage file.
+    pub fn append(&mut self, other: ParsedUsage) {
+        self.entries.extend(other.entries);
+        for (row_type, count) in other.skipped_types {
+            *self.skipped_types.entry(row_type).or_default() += count;
+        }
+        self.bad_rows.extend(other.bad_rows);
+    }
+
+    /// Removes entries with the same date and start time as an earlier entry, which happens when
+    /// usage files overlap. Returns how many were removed.
+    pub fn remove_duplicates(&mut self) -> usize {
+        let before = self.entries.len();
+        let mut seen = HashSet::new();
+        self.entries
+            .retain(|entry| seen.insert((entry.date, entry.start_time)));
+        before - self.entries.len()
+    }
+}
+
+pub fn read_usage_data(
+    usage_csv: &Path,
+    options: &ReadOptions,
+) -> Result<ParsedUsage, UsageReadError> {
+    read_usage_data_from(File::open(usage_csv)?, options)
+}
+
+/// Like [read_usage_data], but reads the usage CSV from any source, like stdin or a [Cursor].
+pub fn read_usage_data_from(
+    source: impl Read + 'static,
+    options: &ReadOptions,
+) -> Result<ParsedUsage, UsageReadError> {
+    let mut reader = UsageReader::from_reader(source, options)?;
+    let entries = reader.by_ref().collect::<Result<_, _>>()?;
+    Ok(ParsedUsage {
+        entries,
+        skipped_types: reader.skipped_types,
+        bad_rows: reader.bad_rows,
+    })
+}
+
+type RecordSource = Chain<Cursor<String>, Box<dyn BufRead>>;
+
+/// Reads the usage entries from a usage file one at a time, so they don't all need to be kept in
+/// memory. Use [read_usage_data] to read them all at once.
+pub struct UsageReader {
+    records: StringRecordsIntoIter<RecordSource>,
+    /// The position of each of [EXPECTED_HEADERS] in the file's columns.
+    columns: Vec<usize>,
+    skipped_lines: u64,
+    usage_type: String,
+    skipped_types: BTreeMap<String, usize>,
+    skip_bad_rows: bool,
+    bad_rows: Vec<UsageReadError>,
+    date_format: Option<String>,
+}
+
+impl UsageReader {
+    /// Opens a usage file and reads up to its header row.
+    pub fn open(usage_csv: &Path, options: &ReadOptions) -> Result<Self, UsageReadError> {
+        Self::from_reader(File::open(usage_csv)?, options)
+    }
+
+    /// Reads a usage CSV from any source, like stdin or a [Cursor], up to its header row.
+    pub fn from_reader(
+        source: impl Read + 'static,
+        options: &ReadOptions,
+    ) -> Result<Self, UsageReadError> {
+        // Annoyingly, the usage CSV comes with extra rows at the start that don't mean anything,
+        // so we need to skip them.
+        let mut reader: Box<dyn BufRead> = Box::new(BufReader::new(source));
+        if reader.fill_buf()?.starts_with(GZIP_MAGIC) {
+            reader = Box::new(BufReader::new(MultiGzDecoder::new(reader)));
+        }
+        if reader.fill_buf()?.starts_with(ZIP_MAGIC) {
+            let mut workbook = Vec::new();
+            reader.read_to_end(&mut workbook)?;
+            reader = Box::new(Cursor::new(xlsx_to_csv(workbook, options)?));
+        }
+        let mut line_buf = String::new();
+        let mut skipped_lines = 0;
+        loop {
+            line_buf.clear();
+            if reader.read_line(&mut line_buf)? == 0 {
+                return Err(UsageReadError::MissingHeader);
+            }
+            if skipped_lines == 0 && line_buf.starts_with('\u{feff}') {
+                // Files re-saved by Excel can start with a UTF-8 byte order mark.
+                line_buf.drain(..'\u{feff}'.len_utf8());
+            }
+            if is_header_line(&line_buf) {
+                break; // Found the header row, stop reading
+            }
+            skipped_lines += 1;
+        }
+        let delimiter = sniff_delimiter(&line_buf);
+        let reader_with_headers = Cursor::new(line_buf).chain(reader);
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .flexible(true)
+            .from_reader(reader_with_headers);
+        let headers = csv_reader.headers()?.clone();
+        // The columns can be in any order, as long as they're the expected ones.
+        let columns: Option<Vec<usize>> = EXPECTED_HEADERS
+            .iter()
+            .map(|name| headers.iter().position(|header| header == name))
+            .collect();
+        let columns = match columns {
+            Some(columns) if headers.len() == EXPECTED_HEADERS.len() => columns,
+            _ => return Err(UsageReadError::HeaderMismatch(headers)),
+        };
+        Ok(Self {
+            records: csv_reader.into_records(),
+            columns,
+            skipped_lines,
+            usage_type: options.usage_type.trim().to_string(),
+            skipped_types: BTreeMap::new(),
+            skip_bad_rows: options.skip_bad_rows,
+            bad_rows: Vec::new(),
+            date_format: options.date_format.clone(),
+        })
+    }
+
+    /// How many rows have been skipped so far for each TYPE that didn't match
+    /// [ReadOptions::usage_type].
+    pub fn skipped_types(&self) -> &BTreeMap<String, usize> {
+        &self.skipped_types
+    }
+
+    /// The errors for rows skipped so far because they couldn't be parsed, if
+    /// [ReadOptions::skip_bad_rows] is set.
+    pub fn bad_rows(&self) -> &[UsageReadError] {
+        &self.bad_rows
+    }
+
+    /// The value in the column for `EXPECTED_HEADERS[index]`, which is empty if the row is short.
+    /// Rows of the usage type are checked with [UsageReader::check_length] before they're read.
+    fn field<'r>(&self, record: &'r StringRecord, index: usize) -> &'r str {
+        record.get(self.columns[index]).unwrap_or("")
+    }
+
+    fn parse_field<T>(
+        &self,
+        record: &StringRecord,
+        line: u64,
+        index: usize,
+    ) -> Result<T, UsageReadError>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        let value = self.field(record, index);
+        value
+            .parse()
+            .map_err(|e: T::Err| UsageReadError::InvalidField {
+                line,
+                column: &EXPECTED_HEADERS[index],
+                value: value.to_string(),
+                message: e.to_string(),
+            })
+    }
+
+    /// Parses the DATE column with [ReadOptions::date_format], or else the first of
+    /// [AUTO_DATE_FORMATS] that fits.
+    fn parse_date(&self, record: &StringRecord, line: u64) -> Result<Date, UsageReadError> {
+        let value = self.field(record, 1).trim();
+        let formats = match &self.date_format {
+            Some(format) => vec![format.as_str()],
+            None => AUTO_DATE_FORMATS.to_vec(),
+        };
+        formats
+            .iter()
+            .find_map(|format| jiff::fmt::strtime::parse(format, value).ok()?.to_date().ok())
+            .ok_or_else(|| UsageReadError::InvalidField {
+                line,
+                column: &EXPECTED_HEADERS[1],
+                value: value.to_string(),
+                message: match &self.date_format {
+                    Some(format) => format!("expected a date in the format {:?}", format),
+                    None => format!(
+                        "expected a date in one of the formats {}, or give its format with --date-format",
+                        formats.join(", ")
+                    ),
+                },
+            })
+    }
+
+    /// Checks that `record` has every column, since the CSV reader accepts rows of any length.
+    fn check_length(&self, record: &StringRecord, line: u64) -> Result<(), UsageReadError> {
+        let columns: Vec<&'static str> = self
+            .columns
+            .iter()
+            .zip(EXPECTED_HEADERS.iter())
+            .filter(|&(&column, _)| column >= record.len())
+            .map(|(_, name)| name)
+            .collect();
+        if columns.is_empty() {
+            Ok(())
+        } else {
+            Err(UsageReadError::MissingFields { line, columns })
+        }
+    }
+
+    /// Parses a time column as a 24-hour time, like `17:00`, or else a 12-hour time in one of
+    /// [TWELVE_HOUR_TIME_FORMATS], like `5:00 PM`.
+    fn parse_time(
+        &self,
+        record: &StringRecord,
+        line: u64,
+        index: usize,
+    ) -> Result<Time, UsageReadError> {
+        let value = self.field(record, index).trim();
+        value
+            .parse()
+            .ok()
+            .or_else(|| {
+                TWELVE_HOUR_TIME_FORMATS.iter().find_map(|format| {
+                    jiff::fmt::strtime::parse(format, value)
+                        .ok()?
+                        .to_time()
+                        .ok()
+                })
+            })
+            .ok_or_else(|| UsageReadError::InvalidField {
+                line,
+                column: &EXPECTED_HEADERS[index],
+                value: value.to_string(),
+                message: "expected a 24-hour time like 17:00 or a 12-hour time like 5:00 PM"
+                    .to_string(),
+            })
+    }
+
+    fn parse_record(&self, record: &StringRecord) -> Result<UsageEntry, UsageReadError> {
+        let line = self.skipped_lines + record.position().map_or(0, |p| p.line());
+        self.check_length(record, line)?;
+        let notes = self.field(record, 6).trim();
+        let entry = UsageEntry {
+            date: self.parse_date(record, line)?,
+            start_time: self.parse_time(record, line, 2)?,
+            end_time: self.parse_time(record, line, 3)?,
+            imported: self.parse_field(record, line, 4)?,
+            exported: self.parse_field(record, line, 5)?,
+            notes: (!notes.is_empty()).then(|| notes.to_string()),
+        };
+        if !entry.has_valid_interval() {
+            return Err(UsageReadError::InvalidInterval {
+                line,
+                row: record.iter().collect::<Vec<_>>().join(","),
+            });
+        }
+        Ok(entry)
+    }
+}
+
+impl Iterator for UsageReader {
+    type Item = Result<UsageEntry, UsageReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = match self.records.next()? {
+                Ok(record) => {
+                    let row_type = self.field(&record, 0).trim();
+                    if !row_type.eq_ignore_ascii_case(&self.usage_type) {
+                        *self.skipped_types.entry(row_type.to_string()).or_default() += 1;
+                        continue;
+                    }
+                    self.parse_record(&record)
+                }
+                Err(e) => Err(e.into()),
+            };
+            match entry {
+                Err(e) if self.skip_bad_rows && e.is_row_error() => self.bad_rows.push(e),
+                entry => return Some(entry),
+            }
+        }
+    }
+}
+
+/// The magic bytes of a gzip file, for usage exports that were compressed to save space.
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+
+/// The magic bytes of a zip archive, which is what XLSX files are.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Converts the first sheet of an XLSX usage export to CSV, so it's read the same way as a CSV
+/// export. Excel stores dates and times as numbers, so those in the DATE and time columns are
+/// written in the format they'd have in a CSV export.
+#[cfg(feature = "xlsx")]
+fn xlsx_to_csv(workbook: Vec<u8>, options: &ReadOptions) -> Result<String, UsageReadError> {
+    use calamine::{Data, ExcelDateTime, ExcelDateTimeType, Reader, Xlsx};
+
+    let invalid = |e: calamine::XlsxError| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("invalid XLSX file: {}", e),
+        )
+    };
+    let mut workbook = Xlsx::new(Cursor::new(workbook)).map_err(invalid)?;
+    let sheet = workbook
+        .worksheet_range_at(0)
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "XLSX file has no worksheets",
+            )
+        })?
+        .map_err(invalid)?;
+    let date_format = options
+        .date_format
+        .as_deref()
+        .unwrap_or(AUTO_DATE_FORMATS[0]);
+    // The columns of the header row, once it's been found.
+    let mut headers: Vec<String> = Vec::new();
+    let mut csv = String::new();
+    for row in sheet.rows() {
+        // The sheet is a rectangle of cells, so leave off the empty ones at the end of the row.
+        let len = row.len()
+            - row
+                .iter()
+                .rev()
+                .take_while(|cell| **cell == Data::Empty)
+                .count();
+        let fields = row[..len].iter().enumerate().map(|(i, cell)| {
+            // Numbers without a date format are dates and times too in the date and time columns.
+            // Their workbook's epoch isn't known, so they're taken to count from 1900 as usual.
+            let datetime = match cell {
+                Data::DateTime(datetime) => Some(*datetime),
+                Data::Float(number) => Some(ExcelDateTime::new(
+                    *number,
+                    ExcelDateTimeType::DateTime,
+                    false,
+                )),
+                Data::Int(number) => Some(ExcelDateTime::new(
+                    *number as f64,
+                    ExcelDateTimeType::DateTime,
+                    false,
+                )),
+                _ => None,
+            };
+            let datetime = || datetime.and_then(excel_datetime);
+            let converted = match headers.get(i).map(String::as_str) {
+                Some("DATE") => datetime().map(|dt| dt.strftime(date_format).to_string()),
+                Some("START TIME" | "END TIME") => {
+                    datetime().map(|dt| dt.time().strftime("%H:%M:%S").to_string())
+                }
+                _ => None,
+            };
+            converted.unwrap_or_else(|| match cell {
+                Data::DateTime(datetime) => datetime.as_f64().to_string(),
+                cell => cell.to_string(),
+            })
+        });
+        let mut fields: Vec<String> = fields.collect();
+        // Spreadsheets leave out empty cells at the end of a row, like an empty NOTES cell, which
+        // would otherwise look like a row that was cut short.
+        if !headers.is_empty() && !fields.is_empty() && fields.len() < headers.len() {
+            fields.resize(headers.len(), String::new());
+        }
+        let line = fields
+            .iter()
+            // Quoting keeps commas and quotes in the cells, and is also how the header is found.
+            .map(|field| format!("\"{}\"", field.replace('"', "\"\"").replace('\n', " ")))
+            .collect::<Vec<_>>()
+            .join(",");
+        if headers.is_empty() && is_header_line(&line) {
+            headers = fields;
+        }
+        csv.push_str(&line);
+        csv.push('\n');
+    }
+    Ok(csv)
+}
+
+/// Converts the number of days since its workbook's epoch that Excel stores a date and time as to
+/// a date and time rounded to the second.
+#[cfg(feature = "xlsx")]
+fn excel_datetime(datetime: calamine::ExcelDateTime) -> Option<DateTime> {
+    let serial = datetime.as_f64();
+    if !serial.is_finite() || serial < 0.0 {
+        return None;
+    }
+    let (year, month, day, hour, minute, second, milli) = datetime.to_ymd_hms_milli();
+    let datetime = DateTime::new(
+        i16::try_from(year).ok()?,
+        month as i8,
+        day as i8,
+        hour as i8,
+        minute as i8,
+        second as i8,
+        0,
+    )
+    .ok()?;
+    if milli >= 500 {
+        datetime.checked_add(jiff::Span::new().seconds(1)).ok()
+    } else {
+        Some(datetime)
+    }
+}
+
+#[cfg(not(feature = "xlsx"))]
+fn xlsx_to_csv(_workbook: Vec<u8>, _options: &ReadOptions) -> Result<String, UsageReadError> {
+    Err(UsageReadError::Io(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "this looks like an XLSX file, which needs the `xlsx` feature; save it as CSV instead",
+    )))
+}
+
+/// The delimiters a usage file may use. Excel in some locales saves CSVs with semicolons, and
+/// copying from a spreadsheet gives tabs.
+const DELIMITERS: [u8; 3] = [b',', b';', b'\t'];
+
+/// Whether `line` is the header row, which may have its fields quoted and in any order, and be
+/// separated by any of [DELIMITERS].
+fn is_header_line(line: &str) -> bool {
+    let line = line.replace('"', "");
+    DELIMITERS.iter().any(|&delimiter| {
+        let fields: Vec<&str> = line.trim_end().split(char::from(delimiter)).collect();
+        fields.contains(&"TYPE") && fields.contains(&"DATE")
+    })
+}
+
+/// Guesses the delimiter from the header row, as whichever of [DELIMITERS] it has the most of,
+/// preferring commas.
+fn sniff_delimiter(header: &str) -> u8 {
+    // `max_by_key` picks the last of equal counts, so go from the least preferred.
+    DELIMITERS
+        .into_iter()
+        .rev()
+        .max_by_key(|&delimiter| header.bytes().filter(|&b| b == delimiter).count())
+        .expect("there are delimiters")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jiff::civil::date;
+
+    const TRUNCATED_ROW: &str = include_str!("../tests/fixtures/truncated_row.csv");
+
+    const USAGE_24_HOUR: &str = include_str!("../tests/fixtures/usage_24_hour.csv");
+
+    fn read(options: &ReadOptions) -> Result<ParsedUsage, UsageReadError> {
+        read_usage_data_from(Cursor::new(TRUNCATED_ROW), options)
+    }
+
+    /// The entries in `csv`, read with the default options.
+    fn entries(csv: &'static str) -> Vec<UsageEntry> {
+        read_usage_data_from(Cursor::new(csv), &ReadOptions::default())
+            .unwrap()
+            .entries
+    }
+
+    #[test]
+    fn byte_order_mark_is_ignored() {
+        let bom = include_str!("../tests/fixtures/bom.csv");
+        assert!(bom.starts_with('\u{feff}'));
+        assert_eq!(entries(bom), entries(USAGE_24_HOUR));
+    }
+
+    #[test]
+    fn quoted_fields_read_the_same_as_unquoted_fields() {
+        let quoted = entries(include_str!("../tests/fixtures/quoted_header.csv"));
+        assert_eq!(quoted.len(), 5);
+        assert_eq!(quoted, entries(USAGE_24_HOUR));
+    }
+
+    #[test]
+    fn columns_are_found_by_name_in_any_order() {
+        let reordered = entries(include_str!("../tests/fixtures/reordered_columns.csv"));
+        assert_eq!(reordered.len(), 5);
+        assert_eq!(reordered, entries(USAGE_24_HOUR));
+    }
+
+    #[test]
+    fn semicolon_and_tab_delimiters_read_the_same_as_commas() {
+        let commas = entries(USAGE_24_HOUR);
+        assert_eq!(commas.len(), 5);
+        for csv in [
+            include_str!("../tests/fixtures/usage_semicolon.csv"),
+            include_str!("../tests/fixtures/usage_tab.csv"),
+        ] {
+            assert_eq!(entries(csv), commas);
+        }
+    }
+
+    #[test]
+    fn delimiter_is_sniffed_from_the_header() {
+        let header = "TYPE,DATE,START TIME,END TIME,IMPORT (kWh),EXPORT (kWh),NOTES";
+        assert_eq!(sniff_delimiter(header), b',');
+        assert_eq!(sniff_delimiter(&header.replace(',', ";")), b';');
+        assert_eq!(sniff_delimiter(&header.replace(',', "\t")), b'\t');
+    }
+
+    #[test]
+    fn us_dates_read_the_same_as_iso_dates() {
+        let us = entries(include_str!("../tests/fixtures/usage_us_dates.csv"));
+        assert_eq!(us.len(), 5);
+        assert_eq!(us, entries(USAGE_24_HOUR));
+    }
+
+    #[test]
+    fn ambiguous_dates_are_read_as_us_dates_unless_the_format_is_given() {
+        let csv = include_str!("../tests/fixtures/ambiguous_date.csv");
+        assert_eq!(entries(csv)[0].date, date(2024, 3, 4));
+        let options = ReadOptions {
+            date_format: Some("%d/%m/%Y".to_string()),
+            ..ReadOptions::default()
+        };
+        let parsed = read_usage_data_from(Cursor::new(csv), &options).unwrap();
+        assert_eq!(parsed.entries[0].date, date(2024, 4, 3));
+    }
+
+    #[test]
+    fn dates_not_in_the_given_format_are_an_error() {
+        let options = ReadOptions {
+            date_format: Some("%m/%d/%Y".to_string()),
+            ..ReadOptions::default()
+        };
+        match read_usage_data_from(Cursor::new(USAGE_24_HOUR), &options) {
+            Err(e @ UsageReadError::InvalidField { line: 5, .. }) => assert_eq!(
+                e.to_string(),
+                "Invalid DATE value \"2024-03-01\" on line 5: \
+                 expected a date in the format \"%m/%d/%Y\""
+            ),
+            result => panic!("expected an invalid date error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn gzipped_usage_reads_the_same_as_plain_usage() {
+        let plain = entries(USAGE_24_HOUR);
+        assert_eq!(plain.len(), 5);
+        let compressed: [&'static [u8]; 3] = [
+            include_bytes!("../tests/fixtures/usage_stored.csv.gz"),
+            include_bytes!("../tests/fixtures/usage_fixed_huffman.csv.gz"),
+            include_bytes!("../tests/fixtures/usage_dynamic_huffman.csv.gz"),
+        ];
+        for compressed in compressed {
+            let parsed = read_usage_data_from(Cursor::new(compressed), &ReadOptions::default());
+            assert_eq!(parsed.unwrap().entries, plain);
+        }
+    }
+
+    #[test]
+    fn corrupt_gzip_trailer_is_an_error() {
+        let corrupt: [&'static [u8]; 2] = [
+            include_bytes!("../tests/fixtures/usage_bad_crc.csv.gz"),
+            include_bytes!("../tests/fixtures/usage_bad_size.csv.gz"),
+        ];
+        for corrupt in corrupt {
+            match read_usage_data_from(Cursor::new(corrupt), &ReadOptions::default()) {
+                Err(UsageReadError::Io(e)) => assert!(e.to_string().contains("checksum")),
+                result => panic!("expected a read error, got {:?}", result),
+            }
+        }
+    }
+
+    #[cfg(feature = "xlsx")]
+    #[test]
+    fn xlsx_usage_reads_the_same_as_csv_usage() {
+        let csv = entries(USAGE_24_HOUR);
+        assert_eq!(csv.len(), 5);
+        // The fixture has shared and inline strings, and dates and times stored as numbers both
+        // with and without a date format.
+        let xlsx = read_usage_data_from(
+            Cursor::new(include_bytes!("../tests/fixtures/usage.xlsx")),
+            &ReadOptions::default(),
+        );
+        assert_eq!(xlsx.unwrap().entries, csv);
+    }
+
+    #[test]
+    fn twelve_hour_times_read_the_same_as_24_hour_times() {
+        let read = |csv: &'static str| {
+            read_usage_data_from(Cursor::new(csv), &ReadOptions::default())
+                .unwrap()
+                .entries
+        };
+        let twelve_hour = read(include_str!("../tests/fixtures/usage_12_hour.csv"));
+        assert_eq!(twelve_hour.len(), 5);
+        assert_eq!(
+            twelve_hour,
+            read(include_str!("../tests/fixtures/usage_24_hour.csv"))
+        );
+    }
+
+    #[test]
+    fn interval_ending_before_it_starts_is_an_error() {
+        let csv = include_str!("../tests/fixtures/reversed_interval.csv");
+        match read_usage_data_from(Cursor::new(csv), &ReadOptions::default()) {
+            Err(e @ UsageReadError::InvalidInterval { line: 6, .. }) => assert_eq!(
+                e.to_string(),
+                "End time is not after start time on line 6: \
+                 Electric usage,2024-03-02,10:15,10:00,0.3,0,"
+            ),
+            result => panic!("expected an invalid interval error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn interval_ending_at_midnight_is_not_reversed() {
+        let options = ReadOptions {
+            skip_bad_rows: true,
+            ..ReadOptions::default()
+        };
+        let csv = include_str!("../tests/fixtures/reversed_interval.csv");
+        let parsed = read_usage_data_from(Cursor::new(csv), &options).unwrap();
+        // The reversed row and the zero-length rows are skipped, including 00:00 to 00:00.
+        let lines: Vec<String> = parsed.bad_rows.iter().map(|e| e.to_string()).collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("line 6"), "{}", lines[0]);
+        assert!(lines[1].contains("line 7"), "{}", lines[1]);
+        assert!(lines[2].contains("line 9"), "{}", lines[2]);
+        assert_eq!(parsed.entries.len(), 2);
+        let last_of_day = &parsed.entries[0];
+        assert_eq!(last_of_day.start_time, Time::constant(23, 45, 0, 0));
+        assert_eq!(last_of_day.end_time, Time::midnight());
+        assert_eq!(last_of_day.end().date(), date(2024, 3, 2));
+    }
+
+    #[test]
+    fn interval_ending_when_it_starts_is_zero_length() {
+        let entry = |start_time: Time| UsageEntry {
+            date: date(2024, 3, 2),
+            start_time,
+            end_time: start_time,
+            imported: BigDecimal::from(1),
+            exported: BigDecimal::from(0),
+            notes: None,
+        };
+        for start_time in [Time::midnight(), Time::constant(11, 0, 0, 0)] {
+            let entry = entry(start_time);
+            assert!(!entry.has_valid_interval());
+            assert_eq!(entry.minutes(), 0);
+            assert_eq!(entry.end(), entry.start());
+        }
+    }
+
+    #[test]
+    fn truncated_row_names_the_missing_columns() {
+        match read(&ReadOptions::default()) {
+            Err(UsageReadError::MissingFields { line, columns }) => {
+                assert_eq!(line, 6);
+                assert_eq!(columns, ["EXPORT (kWh)", "NOTES"]);
+            }
+            result => panic!("expected a missing fields error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn truncated_row_is_skipped_with_skip_bad_rows() {
+        let options = ReadOptions {
+            skip_bad_rows: true,
+            ..ReadOptions::default()
+        };
+        let parsed = read(&options).unwrap();
+        assert_eq!(parsed.entries.len(), 2);
+        assert_eq!(parsed.bad_rows.len(), 1);
+        assert!(
+            parsed.bad_rows[0]
+                .to_string()
+                .contains("EXPORT (kWh), NOTES")
+        );
+    }
 }
@@ -0,0 +1,266 @@
+//! Reads usage from several usage files together, dropping the entries that are in more than one
+//! of them and the ones that aren't wanted.
+
+use crate::analysis::{ExcessExport, check_export};
+use crate::cache::{CachedDay, FileStamp, UsageCache};
+use crate::rate_calculator::DaySummary;
+use crate::schedule::TouSchedule;
+use crate::usage_data::{ReadOptions, UsageEntry, UsageReadError, UsageReader};
+use bigdecimal::BigDecimal;
+use jiff::civil::Date;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Which of the usage entries read to keep.
+#[derive(Debug, Clone, Default)]
+pub struct UsageFilter {
+    /// Only keep usage on or after this date.
+    pub from: Option<Date>,
+    /// Only keep usage on or before this date.
+    pub to: Option<Date>,
+    /// The most a solar system could export, in KW, to note the entries that exported more.
+    pub max_system_kw: Option<BigDecimal>,
+    /// Whether to drop the entries that exported more than `max_system_kw` allows, rather than
+    /// only noting them.
+    pub skip_excess_exports: bool,
+}
+
+impl UsageFilter {
+    pub fn in_date_range(&self, date: Date) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
+
+    /// Adds `entry` to `excess_exports` if it exported more than `max_system_kw` allows,
+    /// returning whether it should be skipped.
+    fn check_export(&self, entry: &UsageEntry, excess_exports: &mut Vec<ExcessExport>) -> bool {
+        let Some(excess) = self
+            .max_system_kw
+            .as_ref()
+            .and_then(|max_system_kw| check_export(entry, max_system_kw))
+        else {
+            return false;
+        };
+        excess_exports.push(excess);
+        self.skip_excess_exports
+    }
+}
+
+/// Counts of the usage entries read from the usage files, and what was skipped, to report once
+/// they've all been read.
+#[derive(Debug, Default)]
+pub struct ReadReport {
+    pub skipped: SkippedRows,
+    pub found: usize,
+    pub duplicates: usize,
+    /// Days dropped because they were in an earlier usage file, when the days are cached.
+    pub duplicate_days: usize,
+    pub kept: usize,
+    /// How many of the kept entries have notes, like estimated readings.
+    pub noted: usize,
+    /// The entries that exported more than [UsageFilter::max_system_kw] allows.
+    pub excess_exports: Vec<ExcessExport>,
+    /// How many of the usage files were taken from the cache.
+    pub cached_files: usize,
+}
+
+/// The rows of the usage files that weren't read as usage entries.
+#[derive(Debug, Default)]
+pub struct SkippedRows {
+    /// How many rows of each type other than [ReadOptions::usage_type] there were.
+    pub types: BTreeMap<String, usize>,
+    /// The errors of the rows that couldn't be read, with [ReadOptions::skip_bad_rows].
+    pub bad_rows: Vec<String>,
+}
+
+/// An error reading one of the usage files.
+#[derive(Debug)]
+pub enum UsageFilesError {
+    Read {
+        name: String,
+        error: UsageReadError,
+    },
+    Stamp {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+}
+
+impl std::fmt::Display for UsageFilesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsageFilesError::Read { name, error } => write!(f, "{}: {}", name, error),
+            UsageFilesError::Stamp { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for UsageFilesError {}
+
+/// The usage files to read, in order, and how to read them. A path of `-` reads standard input.
+#[derive(Debug, Clone, Default)]
+pub struct UsageFiles {
+    pub paths: Vec<PathBuf>,
+    pub options: ReadOptions,
+    pub filter: UsageFilter,
+}
+
+impl UsageFiles {
+    /// Reads the usage files in order, passing each usage entry to `visit` as it's read and calling
+    /// `tick` for each entry read. Entries with the same date and start time as an earlier entry, which
+    /// happens when usage files overlap, are dropped, as are the entries `filter` doesn't keep.
+    pub fn read(
+        &self,
+        mut tick: impl FnMut(),
+        mut visit: impl FnMut(UsageEntry),
+    ) -> Result<ReadReport, UsageFilesError> {
+        let mut seen = HashSet::new();
+        let mut report = ReadReport::default();
+        for path in &self.paths {
+            read_usage_file(
+                path,
+                &self.options,
+                &mut tick,
+                &mut report.skipped,
+                |entry| {
+                    if !seen.insert((entry.date, entry.start_time)) {
+                        report.duplicates += 1;
+                        return;
+                    }
+                    report.found += 1;
+                    if self.filter.in_date_range(entry.date)
+                        && !self.filter.check_export(&entry, &mut report.excess_exports)
+                    {
+                        report.kept += 1;
+                        if entry.notes.is_some() {
+                            report.noted += 1;
+                        }
+                        visit(entry);
+                    }
+                },
+            )?;
+        }
+        Ok(report)
+    }
+
+    /// Like [UsageFiles::read], but passes `visit` each day of usage instead of each entry, and takes
+    /// the days of usage files that haven't changed from `cache`, which is updated with the rest. Only
+    /// the entries of the files that had to be read are passed to `check`.
+    ///
+    /// When a day is in more than one usage file, it's taken from the first, unless the files' entries
+    /// that day span separate times, like a day split across two exports, in which case both are used.
+    pub fn read_cached(
+        &self,
+        schedule: &TouSchedule,
+        cache: &mut UsageCache,
+        mut tick: impl FnMut(),
+        mut check: impl FnMut(&UsageEntry),
+        mut visit: impl FnMut(&DaySummary),
+    ) -> Result<ReadReport, UsageFilesError> {
+        let mut seen = HashSet::new();
+        let mut report = ReadReport::default();
+        let mut used_days: BTreeMap<Date, Vec<CachedDay>> = BTreeMap::new();
+        for path in &self.paths {
+            // Standard input can't be told apart from one run to the next, so it's never cached.
+            let stamp = if path.as_os_str() == "-" {
+                None
+            } else {
+                let stamp = FileStamp::of(path).map_err(|error| UsageFilesError::Stamp {
+                    path: path.clone(),
+                    error,
+                })?;
+                Some(stamp)
+            };
+            let cached = stamp.and_then(|stamp| cache.days(path, stamp));
+            let days = if let Some(days) = cached {
+                report.cached_files += 1;
+                days.to_vec()
+            } else {
+                let mut days: BTreeMap<Date, CachedDay> = BTreeMap::new();
+                let mut file_seen = HashSet::new();
+                read_usage_file(
+                    path,
+                    &self.options,
+                    &mut tick,
+                    &mut report.skipped,
+                    |entry| {
+                        if !file_seen.insert((entry.date, entry.start_time)) {
+                            report.duplicates += 1;
+                            return;
+                        }
+                        if seen.insert((entry.date, entry.start_time))
+                            && self.filter.in_date_range(entry.date)
+                        {
+                            self.filter.check_export(&entry, &mut report.excess_exports);
+                            check(&entry);
+                        }
+                        match days.get_mut(&entry.date) {
+                            Some(day) => day.add(&entry, schedule),
+                            None => {
+                                days.insert(entry.date, CachedDay::new(&entry, schedule));
+                            }
+                        }
+                    },
+                )?;
+                let days: Vec<CachedDay> = days.into_values().collect();
+                if let Some(stamp) = stamp {
+                    cache.insert(path, stamp, days.clone());
+                }
+                days
+            };
+            for day in days {
+                let used = used_days.entry(day.date()).or_default();
+                if used.iter().any(|other| other.overlaps(&day)) {
+                    report.duplicate_days += 1;
+                    continue;
+                }
+                report.found += day.entries;
+                if self.filter.in_date_range(day.date()) {
+                    report.kept += day.entries;
+                    report.noted += day.noted;
+                    visit(&day.summary);
+                }
+                used.push(day);
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Reads the usage file at `path`, or standard input if it's `-`, passing each usage entry to
+/// `visit` and noting the rows that were skipped in `skipped`.
+fn read_usage_file(
+    path: &Path,
+    options: &ReadOptions,
+    tick: &mut impl FnMut(),
+    skipped: &mut SkippedRows,
+    mut visit: impl FnMut(UsageEntry),
+) -> Result<(), UsageFilesError> {
+    let from_stdin = path.as_os_str() == "-";
+    let name = if from_stdin {
+        "<stdin>".to_string()
+    } else {
+        path.display().to_string()
+    };
+    let error = |error: UsageReadError| UsageFilesError::Read {
+        name: name.clone(),
+        error,
+    };
+    let reader = if from_stdin {
+        UsageReader::from_reader(std::io::stdin(), options)
+    } else {
+        UsageReader::open(path, options)
+    };
+    let mut reader = reader.map_err(error)?;
+    for entry in reader.by_ref() {
+        let entry = entry.map_err(error)?;
+        tick();
+        visit(entry);
+    }
+    for (row_type, count) in reader.skipped_types() {
+        *skipped.types.entry(row_type.clone()).or_default() += count;
+    }
+    skipped
+        .bad_rows
+        .extend(reader.bad_rows().iter().map(|e| format!("{}: {}", name, e)));
+    Ok(())
+}
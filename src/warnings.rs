@@ -0,0 +1,311 @@
+//! Warnings about the usage data and rates that don't stop the comparison, but may make its
+//! results less trustworthy. They all go to stderr.
+
+use crate::logging::{info, verbose};
+use crate::{CompareArgs, UsageArgs};
+use bigdecimal::BigDecimal;
+use jiff::ToSpan;
+use jiff::civil::Date;
+use jiff::tz::TimeZone;
+use std::collections::BTreeMap;
+use ttmbuwyntcstr::analysis::{CoverageIssue, DstChange, UnexpectedInterval, dst_transitions};
+use ttmbuwyntcstr::comparison::AnalysisRates;
+use ttmbuwyntcstr::rates::{CurrentRate, RatesConfig};
+use ttmbuwyntcstr::usage_data::ReadOptions;
+use ttmbuwyntcstr::usage_files::ReadReport;
+use ttmbuwyntcstr::{AnalysisResult, CostOptions};
+
+/// The range of average daily imported KWH a home plausibly uses. Usage outside it is warned about.
+const MIN_PLAUSIBLE_DAILY_KWH: u32 = 1;
+const MAX_PLAUSIBLE_DAILY_KWH: u32 = 200;
+
+/// How many months after a location's effective date usage can end before its rates are warned
+/// about as possibly out of date.
+const STALE_RATES_MONTHS: i32 = 12;
+
+/// The time zone the usage export's dates and times are in.
+const USAGE_TIME_ZONE: &str = "America/Los_Angeles";
+
+/// Warns about a rate of a dollar or more per KWH, which is far above any SCL rate and was
+/// probably meant to be in cents.
+fn warn_implausible_rate(description: &str, rate: &BigDecimal) {
+    if *rate >= BigDecimal::from(1) {
+        info!(
+            "Warning: {} is {} dollars per KWH, which looks like it's in cents instead",
+            description,
+            rate.normalized()
+        );
+    }
+}
+
+/// Warns about any of the rates the usage is analyzed with that look like they're in cents, see
+/// [warn_implausible_rate]. Of the TOU rates, only the highest is checked.
+pub fn warn_implausible_rates(
+    current_rate: &CurrentRate,
+    cost_options: &CostOptions,
+    analysis_rates: &[AnalysisRates],
+) {
+    match current_rate {
+        CurrentRate::Flat(rate) => warn_implausible_rate("the current rate", rate),
+        CurrentRate::Tiered(rate) => {
+            warn_implausible_rate("the tier 1 rate", &rate.tier1_rate);
+            warn_implausible_rate("the tier 2 rate", &rate.tier2_rate);
+        }
+    }
+    if let Some(rate) = &cost_options.export_credit_rate {
+        warn_implausible_rate("the export credit rate", rate);
+    }
+    if let Some(net_metering) = &cost_options.net_metering {
+        warn_implausible_rate("the excess credit rate", &net_metering.excess_credit_rate);
+    }
+    let highest_tou_rate = analysis_rates
+        .iter()
+        .flat_map(|rates| rates.tou.all())
+        .flat_map(|rates| [&rates.winter, &rates.summer])
+        .flat_map(|rates| rates.all())
+        .max();
+    if let Some(rate) = highest_tou_rate {
+        warn_implausible_rate("the highest TOU rate", rate);
+    }
+}
+
+/// Reports the usage entries that were read from the usage files, and the ones that were dropped.
+pub fn print_read_report(report: &ReadReport, usage: &UsageArgs, options: &ReadOptions) {
+    if report.duplicates > 0 {
+        info!(
+            "Warning: dropped {} duplicate usage entries with the same date and start time as another entry",
+            report.duplicates
+        );
+    }
+    if report.duplicate_days > 0 {
+        info!(
+            "Warning: dropped {} days of usage that were also in an earlier usage file",
+            report.duplicate_days
+        );
+    }
+    if !report.skipped.types.is_empty() {
+        let skipped: Vec<String> = report
+            .skipped
+            .types
+            .iter()
+            .map(|(row_type, count)| format!("{} {:?}", count, row_type))
+            .collect();
+        info!(
+            "Warning: skipped rows that aren't {:?}: {}",
+            options.usage_type,
+            skipped.join(", ")
+        );
+    }
+    let bad_rows = &report.skipped.bad_rows;
+    if !bad_rows.is_empty() {
+        const SHOWN: usize = 5;
+        info!(
+            "Warning: skipped {} rows that couldn't be read",
+            bad_rows.len()
+        );
+        for bad_row in bad_rows.iter().take(SHOWN) {
+            info!("  {}", bad_row);
+        }
+        if bad_rows.len() > SHOWN {
+            info!("  ...and {} more", bad_rows.len() - SHOWN);
+        }
+    }
+    info!("Found {} usage entries", report.found);
+    if usage.from.is_some() || usage.to.is_some() {
+        info!(
+            "Kept {} usage entries in the date range, skipped {}",
+            report.kept,
+            report.found - report.kept
+        );
+    }
+    if report.noted > 0 {
+        info!(
+            "Warning: {} usage entries have notes, which can mean they're estimated or adjusted readings that are less accurate",
+            report.noted
+        );
+    }
+    if let Some(max_system_kw) = &usage.max_system_kw
+        && !report.excess_exports.is_empty()
+    {
+        const SHOWN: usize = 5;
+        info!(
+            "Warning: {} usage entries exported more than a {} KW system could, so their export readings may be corrupt{}",
+            report.excess_exports.len(),
+            max_system_kw,
+            if usage.skip_excess_exports {
+                "; skipped them"
+            } else {
+                ""
+            }
+        );
+        for excess in report.excess_exports.iter().take(SHOWN) {
+            info!("  {}", excess);
+        }
+        if report.excess_exports.len() > SHOWN {
+            info!("  ...and {} more", report.excess_exports.len() - SHOWN);
+        }
+    }
+}
+
+/// Warns about the usage and the plan's rates, whichever TOU rates the usage was analyzed with.
+pub fn warn_usage(args: &CompareArgs, rates_config: &RatesConfig, result: &AnalysisResult) {
+    if let Some((first, last)) = result.date_range {
+        warn_dst_transitions(first, last);
+    }
+    warn_implausible_usage(&result.imported, result.days);
+    // Rates given on the command line are the user's own, so only the plan's can be stale.
+    if args.tou_rates.compare_all {
+        let locations = rates_config.location_names();
+        warn_stale_rates(
+            &args.config.plan,
+            rates_config,
+            locations,
+            result.date_range,
+        );
+    } else if let Some(location) = &args.tou_rates.tou_location {
+        let locations = std::iter::once(location.as_str());
+        warn_stale_rates(
+            &args.config.plan,
+            rates_config,
+            locations,
+            result.date_range,
+        );
+    }
+}
+
+pub fn warn_unpriced_rate_change(date: Date, rates: &str) {
+    info!(
+        "Warning: the usage data crosses the rate change on {}, but {} don't change then, so part of the usage is priced at the wrong rates",
+        date, rates
+    );
+}
+
+/// Warns if the usage data is too short for `--compare-year-over-year` or `--annualize` to mean
+/// much.
+pub fn warn_short_usage(args: &CompareArgs, result: &AnalysisResult) {
+    if let Some(months) = &result.details.year_over_year
+        && months.first().map(|month| month.year) == months.last().map(|month| month.year)
+    {
+        info!(
+            "Warning: the usage data only covers one year, so there's no other year to compare it with"
+        );
+    }
+    if args.annualize && result.covered_days() < 7 {
+        info!(
+            "Warning: the usage data only covers {} days, so the annualized savings are unreliable",
+            result.covered_days()
+        );
+    }
+}
+
+/// Warns if the usage data ends more than [STALE_RATES_MONTHS] after the effective date of any of
+/// the plan's `locations` the usage was analyzed with, in which case their rates may have changed
+/// since. Locations with the same effective date are warned about together.
+pub fn warn_stale_rates<'a>(
+    plan: &str,
+    rates_config: &RatesConfig,
+    locations: impl IntoIterator<Item = &'a str>,
+    date_range: Option<(Date, Date)>,
+) {
+    let Some((_, last)) = date_range else {
+        return;
+    };
+    let mut stale: BTreeMap<Date, Vec<&str>> = BTreeMap::new();
+    for location in locations {
+        if let Some(effective) = rates_config.effective_date(location)
+            && effective.saturating_add(STALE_RATES_MONTHS.months()) < last
+        {
+            stale.entry(effective).or_default().push(location);
+        }
+    }
+    for (effective, locations) in stale {
+        info!(
+            "Warning: the {} plan's {} rates are as of {}, more than {} months before the usage data ends on {}, so they may be out of date; check them against your bill, and give the current ones with --off-peak-rate, --mid-peak-rate and --peak-rate if they've changed",
+            plan,
+            locations.join(", "),
+            effective,
+            STALE_RATES_MONTHS,
+            last
+        );
+    }
+}
+
+/// Warns if the usage averages a daily KWH no home would plausibly use, which usually means the
+/// usage CSV isn't interval data for a single home, like a billing summary.
+fn warn_implausible_usage(imported: &BigDecimal, days: usize) {
+    if days == 0 {
+        return;
+    }
+    let daily = imported / BigDecimal::from(days as u64);
+    if daily < BigDecimal::from(MIN_PLAUSIBLE_DAILY_KWH)
+        || daily > BigDecimal::from(MAX_PLAUSIBLE_DAILY_KWH)
+    {
+        info!(
+            "Warning: the usage data averages {:.2} KWH imported per day, outside the usual range for a home of {} to {} KWH per day. Check that the usage CSV is the right file, since the costs below may be meaningless.",
+            daily, MIN_PLAUSIBLE_DAILY_KWH, MAX_PLAUSIBLE_DAILY_KWH
+        );
+    }
+}
+
+/// Warns about the daylight saving time transitions the usage data crosses, since the usage
+/// export is in local time.
+fn warn_dst_transitions(first: Date, last: Date) {
+    let zone = match TimeZone::get(USAGE_TIME_ZONE) {
+        Ok(zone) => zone,
+        Err(e) => {
+            verbose!("Not checking for daylight saving time transitions: {}", e);
+            return;
+        }
+    };
+    for (date, change) in dst_transitions(&zone, first, last) {
+        match change {
+            DstChange::SpringForward => info!(
+                "Warning: the clocks sprang forward on {}, so that day only has 23 hours and its usage may be missing an hour",
+                date
+            ),
+            DstChange::FallBack => info!(
+                "Warning: the clocks fell back on {}, so that day has 25 hours and usage in the repeated hour may be dropped as duplicates or counted twice",
+                date
+            ),
+        }
+    }
+}
+
+pub fn warn_coverage_issues(issues: &[CoverageIssue]) {
+    const SHOWN: usize = 5;
+    if issues.is_empty() {
+        return;
+    }
+    let gaps = issues
+        .iter()
+        .filter(|issue| matches!(issue, CoverageIssue::Gap { .. }))
+        .count();
+    info!(
+        "Warning: the usage data has {} gaps and {} overlapping entries, so the totals may be off",
+        gaps,
+        issues.len() - gaps
+    );
+    for issue in issues.iter().take(SHOWN) {
+        info!("  {}", issue);
+    }
+    if issues.len() > SHOWN {
+        info!("  ...and {} more", issues.len() - SHOWN);
+    }
+}
+
+pub fn warn_unexpected_intervals(intervals: &[UnexpectedInterval]) {
+    const SHOWN: usize = 5;
+    if intervals.is_empty() {
+        return;
+    }
+    info!(
+        "Warning: {} usage entries aren't the expected length, so their usage may be split across the wrong TOU periods",
+        intervals.len()
+    );
+    for interval in intervals.iter().take(SHOWN) {
+        info!("  {}", interval);
+    }
+    if intervals.len() > SHOWN {
+        info!("  ...and {} more", intervals.len() - SHOWN);
+    }
+}
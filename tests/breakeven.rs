@@ -0,0 +1,39 @@
+use std::process::Command;
+
+/// Runs the binary with `args`, returning everything it printed.
+fn run(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_ttmbuwyntcstr"))
+        .args(args)
+        .output()
+        .expect("the binary runs");
+    assert!(
+        output.status.code().is_some_and(|code| code < 3),
+        "failed with {}: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned() + &String::from_utf8_lossy(&output.stderr)
+}
+
+#[test]
+fn breakeven_compares_rounded_costs() {
+    // A weekday mid-peak hour, which costs 0.1398 under TOU and 0.1449 under the current rate,
+    // both 0.14 once rounded.
+    let output = run(&[
+        "--current-rate",
+        "0.1449",
+        "--tou-location",
+        "seattle",
+        "--rates-file",
+        "tests/fixtures/breakeven_rates.json",
+        "--rounding",
+        "half-up",
+        "--breakeven",
+        "tests/fixtures/usage_mid_peak_hour.csv",
+    ]);
+    assert!(
+        output.contains("TOU rates already cost at least as much as your current rate"),
+        "{}",
+        output
+    );
+}
@@ -0,0 +1,58 @@
+//! Runs the binary on checked-in usage and compares its results with the expected results checked
+//! in next to it, so a change to any of the numbers shows up as a failing test.
+
+use serde_json::Value;
+use std::process::Command;
+
+/// SCL's Seattle rates, given in a rates file so an update to the built-in rates doesn't change
+/// the results.
+const RATES: &[&str] = &[
+    "--current-rate",
+    "0.12",
+    "--tou-location",
+    "seattle",
+    "--rates-file",
+    "tests/fixtures/golden_rates.json",
+];
+
+/// Compares the rates on the usage in `tests/fixtures/golden_<name>.csv`, returning the exit code
+/// and the JSON summary.
+fn compare(name: &str) -> (i32, Value) {
+    let output = Command::new(env!("CARGO_BIN_EXE_ttmbuwyntcstr"))
+        .args(RATES)
+        .args(["--format", "json"])
+        .arg(format!("tests/fixtures/golden_{}.csv", name))
+        .output()
+        .expect("the binary runs");
+    let summary = serde_json::from_slice(&output.stdout).expect("the output is JSON");
+    (output.status.code().expect("the binary exits"), summary)
+}
+
+fn expected(name: &str) -> Value {
+    let path = format!("tests/fixtures/golden_{}.json", name);
+    let expected = std::fs::read_to_string(&path).expect("the expected results are checked in");
+    serde_json::from_str(&expected).expect("the expected results are JSON")
+}
+
+#[test]
+fn consumer_would_pay_more_on_tou_rates() {
+    let (code, summary) = compare("consumer");
+    assert_eq!(summary["current_cost"], "13.692");
+    assert_eq!(summary["tou_cost"], "14.57073");
+    assert_eq!(summary["savings"], "-0.87873");
+    // Switching costs more.
+    assert_eq!(code, 1);
+    assert_eq!(summary, expected("consumer"));
+}
+
+#[test]
+fn solar_would_save_on_tou_rates() {
+    let (code, summary) = compare("solar");
+    assert_eq!(summary["exported_kwh"], "138.6");
+    assert_eq!(summary["current_cost"], "-7.308");
+    assert_eq!(summary["tou_cost"], "-7.60725");
+    assert_eq!(summary["savings"], "0.29925");
+    // Switching saves money.
+    assert_eq!(code, 0);
+    assert_eq!(summary, expected("solar"));
+}
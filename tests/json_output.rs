@@ -0,0 +1,86 @@
+use serde_json::Value;
+use std::process::Command;
+
+/// Runs the binary with `args`, returning its standard output.
+fn run(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_ttmbuwyntcstr"))
+        .args(args)
+        .output()
+        .expect("the binary runs");
+    assert!(
+        output.status.code().is_some_and(|code| code < 3),
+        "failed with {}: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).expect("the output is UTF-8")
+}
+
+#[test]
+fn breakeven_and_stats_are_in_the_json_summary() {
+    let output = run(&[
+        "--current-rate",
+        "0.16",
+        "--tou-location",
+        "seattle",
+        "--format",
+        "json",
+        "--breakeven",
+        "--stats",
+        "tests/fixtures/usage_24_hour.csv",
+    ]);
+    let summary: Value = serde_json::from_str(&output).expect("the output is only JSON");
+    assert_eq!(summary["current_cost"], "0.336");
+    assert_eq!(summary["tou_cost"], "0.32292");
+    let breakeven = &summary["breakeven"];
+    assert_eq!(breakeven["outcome"], "multiplier");
+    assert_eq!(breakeven["multiplier"], "1.040505388751");
+    assert_eq!(breakeven["rates"][0]["from"], Value::Null);
+    assert_eq!(
+        breakeven["rates"][0]["winter"]["peak"],
+        "0.1723076923771656"
+    );
+    let stats = &summary["stats"];
+    assert_eq!(stats["all"]["count"], 5);
+    assert_eq!(stats["all"]["median"], "0.3");
+    assert_eq!(stats["by_period"]["mid"]["count"], 3);
+    assert_eq!(stats["by_period"]["peak"]["max"], "1.2");
+}
+
+#[test]
+fn histogram_and_flagged_entries_are_in_the_json_summary() {
+    let output = run(&[
+        "--current-rate",
+        "0.16",
+        "--tou-location",
+        "seattle",
+        "--format",
+        "json",
+        "--histogram",
+        "--show-flagged",
+        "tests/fixtures/usage_24_hour.csv",
+    ]);
+    let summary: Value = serde_json::from_str(&output).expect("the output is only JSON");
+    let histogram = summary["histogram"].as_array().expect("an array of hours");
+    assert_eq!(histogram.len(), 24);
+    assert_eq!(histogram[12]["hour"], 12);
+    assert_eq!(histogram[12]["kwh"], "0.3");
+    assert_eq!(histogram[17]["kwh"], "1.2");
+    assert_eq!(summary["flagged"], Value::Array(Vec::new()));
+}
+
+#[test]
+fn stats_are_in_the_compare_all_json_summary() {
+    let output = run(&[
+        "--current-rate",
+        "0.16",
+        "--compare-all",
+        "--format",
+        "json",
+        "--stats",
+        "tests/fixtures/usage_24_hour.csv",
+    ]);
+    let summary: Value = serde_json::from_str(&output).expect("the output is only JSON");
+    assert!(!summary["locations"].as_array().unwrap().is_empty());
+    assert_eq!(summary["stats"]["all"]["count"], 5);
+}